@@ -29,6 +29,55 @@ impl Lang {
             Self::Custom(lang) => lang,
         }
     }
+
+    /// Creates `Lang` from a language tag like `en`, `en-US` or `nb`.
+    /// Unknown languages are stored in `Lang::Custom`.
+    pub fn from_tag(tag: &str) -> Self {
+        let primary_subtag = tag.split(['-', '_']).next().unwrap_or_default();
+        match primary_subtag.to_ascii_lowercase().as_str() {
+            "cs" => Self::Czech,
+            "en" => Self::English,
+            "fr" => Self::French,
+            "no" | "nb" | "nn" => Self::Norwegian,
+            "es" => Self::Spanish,
+            "sv" => Self::Swedish,
+            _ => Self::Custom(Cow::Owned(tag.to_owned())),
+        }
+    }
+
+    /// Picks the supported language preferred in the `Accept-Language` header value,
+    /// e.g. `"cs-CZ,cs;q=0.9,en;q=0.8"`.
+    pub fn negotiate(accept_language: &str, supported: &[Lang]) -> Option<Lang> {
+        let mut preferences = accept_language
+            .split(',')
+            .filter_map(|preference| {
+                let mut parts = preference.split(';').map(str::trim);
+                let tag = parts.next().filter(|tag| !tag.is_empty())?;
+                let quality = parts
+                    .find_map(|part| part.strip_prefix("q="))
+                    .map_or(Some(1.), |quality| quality.parse::<f32>().ok())?;
+                (quality > 0.).then_some((tag, quality))
+            })
+            .collect::<Vec<_>>();
+        // stable sort keeps the header order for equal qualities
+        preferences.sort_by(|(_, quality_a), (_, quality_b)| quality_b.total_cmp(quality_a));
+
+        preferences.into_iter().find_map(|(tag, _)| {
+            if tag == "*" {
+                return supported.first().cloned();
+            }
+            supported
+                .iter()
+                .find(|lang| lang.as_str().eq_ignore_ascii_case(tag))
+                .or_else(|| {
+                    let lang = Self::from_tag(tag);
+                    supported
+                        .iter()
+                        .find(|supported_lang| **supported_lang == lang)
+                })
+                .cloned()
+        })
+    }
 }
 
 impl fmt::Display for Lang {
@@ -36,3 +85,28 @@ impl fmt::Display for Lang {
         write!(f, "{}", self.as_str())
     }
 }
+
+// ====== ====== TESTS ====== ======
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_respects_qualities() {
+        // ------ ARRANGE ------
+        let supported = [Lang::English, Lang::Czech, Lang::Norwegian];
+
+        // ------ ACT ------
+        let czech = Lang::negotiate("de;q=0.9, en;q=0.5, cs-CZ;q=0.8", &supported);
+        let norwegian = Lang::negotiate("nb-NO", &supported);
+        let any = Lang::negotiate("de, *;q=0.1", &supported);
+        let none = Lang::negotiate("de, en;q=0", &supported);
+
+        // ------ ASSERT ------
+        assert_eq!(czech, Some(Lang::Czech));
+        assert_eq!(norwegian, Some(Lang::Norwegian));
+        assert_eq!(any, Some(Lang::English));
+        assert_eq!(none, None);
+    }
+}
@@ -17,3 +17,73 @@ sse.addEventListener("reload", function (msg) {
     sse.close();
     location.reload();
 });
+// The frontend has been rebuilt by `mzoon start`.
+// Zoon listens for `moon_hot_reload` to snapshot its state into `sessionStorage`
+// and restores it together with the scroll position on startup.
+sse.addEventListener("hot_reload", function (msg) {
+    sse.close();
+    try {
+        window.dispatchEvent(new Event("moon_hot_reload"));
+        sessionStorage.setItem("moon_hot_reload_scroll", JSON.stringify([window.scrollX, window.scrollY]));
+        sessionStorage.setItem("moon_hot_reload", "true");
+    } catch (error) {
+        console.error("Failed to preserve the app state", error);
+    }
+    location.reload();
+});
+// Compilation errors sent by `mzoon start`. An empty array means the build has succeeded.
+// Styles are set through CSSOM because Content-Security-Policy blocks inline `style` attributes.
+sse.addEventListener("build_errors", function (msg) {
+    var overlay = document.getElementById("moon_build_errors");
+    if (overlay !== null) {
+        overlay.remove();
+    }
+    var diagnostics = JSON.parse(msg.data);
+    if (diagnostics.length === 0) {
+        return;
+    }
+    overlay = document.createElement("div");
+    overlay.id = "moon_build_errors";
+    Object.assign(overlay.style, {
+        position: "fixed",
+        inset: "0",
+        zIndex: "2147483647",
+        overflow: "auto",
+        padding: "16px",
+        background: "rgba(20, 20, 20, 0.95)",
+        color: "#e8e8e8",
+        font: "14px monospace",
+    });
+
+    var closeButton = document.createElement("button");
+    closeButton.textContent = "✕";
+    Object.assign(closeButton.style, {
+        float: "right",
+        font: "inherit",
+        color: "inherit",
+        background: "none",
+        border: "none",
+        cursor: "pointer",
+    });
+    closeButton.addEventListener("click", function () {
+        overlay.remove();
+    });
+    overlay.appendChild(closeButton);
+
+    diagnostics.forEach(function (diagnostic) {
+        var title = diagnostic.crate_name;
+        if (diagnostic.file !== null) {
+            title += " " + diagnostic.file + ":" + diagnostic.line + ":" + diagnostic.column;
+        }
+        var heading = document.createElement("div");
+        heading.textContent = title;
+        heading.style.color = "#ff6b6b";
+        overlay.appendChild(heading);
+
+        var message = document.createElement("pre");
+        message.textContent = diagnostic.message;
+        message.style.whiteSpace = "pre-wrap";
+        overlay.appendChild(message);
+    });
+    document.body.appendChild(overlay);
+});
@@ -1,9 +1,13 @@
+pub mod actor_ref;
 pub mod index;
 pub mod p_var;
 pub mod sessions;
+pub mod storage;
 
-pub use index::Index;
+pub use actor_ref::ActorRef;
+pub use index::{Index, WaitForError};
 pub use p_var::PVar;
+use std::{fmt, str::FromStr, time::Duration};
 use uuid::Uuid;
 
 // ------ ActorId ------
@@ -17,14 +21,135 @@ impl ActorId {
     }
 }
 
+impl fmt::Display for ActorId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for ActorId {
+    type Err = uuid::Error;
+
+    fn from_str(actor_id: &str) -> Result<Self, Self::Err> {
+        Ok(Self(actor_id.parse()?))
+    }
+}
+
 // ------ ActorInstance ------
 
-pub trait ActorInstance {
+pub trait ActorInstance: Sized + Send + 'static {
     const KEY: &'static str;
 
+    /// Idle instances are dropped and revived again on the next message.
+    /// `None` keeps them in memory until they are removed.
+    const IDLE_TIMEOUT: Option<Duration> = Some(Duration::from_secs(5 * 60));
+
     fn actor_id(&self) -> ActorId;
 
-    fn revive(actor_id: ActorId) -> Self;
+    /// Restores the instance from its `PVar`s.
+    /// Returns `None` if the actor doesn't exist.
+    fn revive(actor_id: ActorId) -> Option<Self>;
 
+    /// Removes `PVar`s and index entries of the actor.
     fn remove(&self);
 }
+
+// ------ actor! ------
+
+/// Declares an actor handle, its `PVar`s and indices.
+///
+/// ```ignore
+/// actor! {
+///     pub actor CounterActor(CounterActorInstance): "counter";
+///     p_vars {
+///         PVarName(String): "name";
+///         PVarCount(u32): "count";
+///     }
+///     indices {
+///         pub by_name: ByName(PVarName);
+///     }
+/// }
+///
+/// struct CounterActorInstance {
+///     actor_id: ActorId,
+///     name: PVarName,
+///     count: PVarCount,
+/// }
+///
+/// impl ActorInstance for CounterActorInstance { ... }
+///
+/// impl CounterActor {
+///     pub async fn increment(&self) -> Option<u32> {
+///         self.actor_ref().call(|instance| instance.increment()).await
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! actor {
+    (
+        $actor_vis:vis actor $actor:ident($instance:ty): $actor_key:literal;
+        p_vars {
+            $($p_var_vis:vis $p_var:ident($p_var_value:ty): $p_var_key:literal;)*
+        }
+        $(indices {
+            $($index_vis:vis $index_fn:ident: $index:ident($index_p_var:ident);)*
+        })?
+    ) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        $actor_vis struct $actor {
+            actor_id: $crate::ActorId,
+        }
+
+        impl From<$crate::ActorId> for $actor {
+            fn from(actor_id: $crate::ActorId) -> Self {
+                Self { actor_id }
+            }
+        }
+
+        impl $actor {
+            pub fn actor_id(&self) -> $crate::ActorId {
+                self.actor_id
+            }
+
+            #[allow(dead_code)]
+            fn actor_ref(&self) -> $crate::ActorRef<$instance> {
+                $crate::ActorRef::new(self.actor_id)
+            }
+        }
+
+        $(
+            #[derive(Debug, Clone, Copy)]
+            $p_var_vis struct $p_var($crate::ActorId);
+
+            impl $p_var {
+                #[allow(dead_code)]
+                pub fn new(actor_id: $crate::ActorId) -> Self {
+                    Self(actor_id)
+                }
+            }
+
+            impl $crate::PVar for $p_var {
+                const KEY: &'static str = concat!($actor_key, ".", $p_var_key);
+                type Value = $p_var_value;
+
+                fn actor_id(&self) -> $crate::ActorId {
+                    self.0
+                }
+            }
+        )*
+
+        $($(
+            $index_vis const fn $index_fn() -> $index {
+                $index
+            }
+
+            $index_vis struct $index;
+
+            impl $crate::Index for $index {
+                const KEY: &'static str = concat!($actor_key, ".", stringify!($index_fn));
+                type PVar = $index_p_var;
+                type Actor = $actor;
+            }
+        )*)?
+    };
+}
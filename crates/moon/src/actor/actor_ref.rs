@@ -0,0 +1,286 @@
+use crate::actor::{ActorId, ActorInstance};
+use chashmap::CHashMap;
+use futures::future::BoxFuture;
+use once_cell::sync::Lazy;
+use std::{any::Any, fmt, marker::PhantomData};
+use tokio::sync::{
+    mpsc::{
+        error::SendError, unbounded_channel, UnboundedReceiver, UnboundedSender,
+        WeakUnboundedSender,
+    },
+    oneshot,
+};
+use tokio::time::timeout;
+
+/// Senders of active actors. The values are `UnboundedSender<Message<I>>`.
+///
+/// Messages are sent while the actor's entry is locked.
+/// Activation and passivation lock the entry for writing so they can't interleave with sends.
+static MAILBOXES: Lazy<CHashMap<ActorId, Box<dyn Any + Send + Sync>>> = Lazy::new(CHashMap::new);
+
+type Job<I> = Box<dyn for<'a> FnOnce(&'a mut I) -> BoxFuture<'a, ()> + Send>;
+
+enum Message<I> {
+    Job(Job<I>),
+    Remove,
+}
+
+// ------ ActorRef ------
+
+/// The address of a virtual actor.
+///
+/// The actor instance is activated (revived from its `PVar`s) on the first message,
+/// processes messages one by one and it's passivated after `ActorInstance::IDLE_TIMEOUT`.
+pub struct ActorRef<I> {
+    actor_id: ActorId,
+    instance: PhantomData<fn() -> I>,
+}
+
+impl<I> Clone for ActorRef<I> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<I> Copy for ActorRef<I> {}
+
+impl<I> fmt::Debug for ActorRef<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ActorRef").field(&self.actor_id).finish()
+    }
+}
+
+impl<I: ActorInstance> ActorRef<I> {
+    pub fn new(actor_id: ActorId) -> Self {
+        Self {
+            actor_id,
+            instance: PhantomData,
+        }
+    }
+
+    /// Activates a new actor or an actor with a state that cannot be revived.
+    pub fn activate(instance: I) -> Self {
+        let actor_id = instance.actor_id();
+        MAILBOXES.insert(actor_id, Box::new(spawn_mailbox(instance)));
+        Self::new(actor_id)
+    }
+
+    pub fn actor_id(&self) -> ActorId {
+        self.actor_id
+    }
+
+    /// Returns `None` if the actor doesn't exist.
+    pub async fn call<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut I) -> R + Send + 'static,
+    ) -> Option<R> {
+        self.call_async(move |instance| {
+            let output = f(instance);
+            Box::pin(async move { output })
+        })
+        .await
+    }
+
+    /// Returns `None` if the actor doesn't exist.
+    pub async fn call_async<R: Send + 'static>(
+        &self,
+        f: impl for<'a> FnOnce(&'a mut I) -> BoxFuture<'a, R> + Send + 'static,
+    ) -> Option<R> {
+        let (output_sender, output_receiver) = oneshot::channel();
+        let job: Job<I> = Box::new(move |instance| {
+            Box::pin(async move {
+                let _ = output_sender.send(f(instance).await);
+            })
+        });
+        self.send(Message::Job(job))?;
+        output_receiver.await.ok()
+    }
+
+    /// Calls `ActorInstance::remove` once the messages sent before are processed.
+    pub fn remove(&self) {
+        self.send(Message::Remove);
+    }
+
+    fn send(&self, message: Message<I>) -> Option<()> {
+        let message = match MAILBOXES.get(&self.actor_id) {
+            Some(mailbox) => match downcast_mailbox::<I>(mailbox.as_ref())?.send(message) {
+                Ok(()) => return Some(()),
+                // the actor has been removed in the meantime
+                Err(SendError(returned_message)) => returned_message,
+            },
+            None => message,
+        };
+        // The actor is revived while its entry is locked to prevent concurrent activations.
+        let mut message = Some(message);
+        let mut sent = None;
+        MAILBOXES.alter(self.actor_id, |mailbox| {
+            let mailbox = match mailbox.as_deref().and_then(downcast_mailbox::<I>) {
+                Some(mailbox) if !mailbox.is_closed() => mailbox.clone(),
+                _ => spawn_mailbox(I::revive(self.actor_id)?),
+            };
+            sent = mailbox.send(message.take()?).ok();
+            Some(Box::new(mailbox))
+        });
+        sent
+    }
+}
+
+// ------ mailbox ------
+
+fn downcast_mailbox<I: ActorInstance>(
+    mailbox: &(dyn Any + Send + Sync),
+) -> Option<&UnboundedSender<Message<I>>> {
+    mailbox.downcast_ref::<UnboundedSender<Message<I>>>()
+}
+
+/// The returned sender has to be inserted into `MAILBOXES`.
+fn spawn_mailbox<I: ActorInstance>(instance: I) -> UnboundedSender<Message<I>> {
+    let (mailbox, receiver) = unbounded_channel();
+    tokio::spawn(process_messages(instance, mailbox.downgrade(), receiver));
+    mailbox
+}
+
+fn remove_mailbox<I: ActorInstance>(actor_id: ActorId, mailbox: &UnboundedSender<Message<I>>) {
+    MAILBOXES.alter(actor_id, |active_mailbox| {
+        active_mailbox.filter(|active_mailbox| !is_same_mailbox(active_mailbox.as_ref(), mailbox))
+    });
+}
+
+fn is_same_mailbox<I: ActorInstance>(
+    active_mailbox: &(dyn Any + Send + Sync),
+    mailbox: &UnboundedSender<Message<I>>,
+) -> bool {
+    downcast_mailbox::<I>(active_mailbox)
+        .is_some_and(|active_mailbox| active_mailbox.same_channel(mailbox))
+}
+
+/// Removes the mailbox unless a message is waiting in it.
+/// Sends are blocked while the entry is locked so no message can be left in the closed mailbox.
+fn passivate<I: ActorInstance>(
+    actor_id: ActorId,
+    mailbox: &WeakUnboundedSender<Message<I>>,
+    receiver: &mut UnboundedReceiver<Message<I>>,
+) -> Option<Message<I>> {
+    let mut waiting_message = None;
+    MAILBOXES.alter(actor_id, |active_mailbox| {
+        if let Ok(message) = receiver.try_recv() {
+            waiting_message = Some(message);
+            return active_mailbox;
+        }
+        receiver.close();
+        active_mailbox.filter(|active_mailbox| {
+            !mailbox
+                .upgrade()
+                .is_some_and(|mailbox| is_same_mailbox(active_mailbox.as_ref(), &mailbox))
+        })
+    });
+    waiting_message
+}
+
+async fn process_messages<I: ActorInstance>(
+    mut instance: I,
+    mailbox: WeakUnboundedSender<Message<I>>,
+    mut receiver: UnboundedReceiver<Message<I>>,
+) {
+    let actor_id = instance.actor_id();
+    loop {
+        let message = match I::IDLE_TIMEOUT {
+            Some(idle_timeout) => match timeout(idle_timeout, receiver.recv()).await {
+                Ok(message) => message,
+                Err(_) => passivate(actor_id, &mailbox, &mut receiver),
+            },
+            None => receiver.recv().await,
+        };
+        match message {
+            Some(Message::Job(job)) => job(&mut instance).await,
+            Some(Message::Remove) => {
+                // `PVar`s have to be removed before the mailbox to prevent revival.
+                instance.remove();
+                if let Some(mailbox) = mailbox.upgrade() {
+                    remove_mailbox(actor_id, &mailbox);
+                }
+                // Dropped jobs resolve their `call`s to `None`.
+                receiver.close();
+                return;
+            }
+            None => return,
+        }
+    }
+}
+
+// ====== ====== TESTS ====== ======
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actor::PVar;
+    use std::time::Duration;
+
+    #[derive(Clone, Copy)]
+    struct PVarCount(ActorId);
+
+    impl PVar for PVarCount {
+        const KEY: &'static str = "test_counter.count";
+        type Value = u32;
+
+        fn actor_id(&self) -> ActorId {
+            self.0
+        }
+    }
+
+    struct CounterInstance {
+        actor_id: ActorId,
+        count: PVarCount,
+    }
+
+    impl ActorInstance for CounterInstance {
+        const KEY: &'static str = "test_counter";
+        const IDLE_TIMEOUT: Option<Duration> = Some(Duration::from_millis(50));
+
+        fn actor_id(&self) -> ActorId {
+            self.actor_id
+        }
+
+        fn revive(actor_id: ActorId) -> Option<Self> {
+            let count = PVarCount(actor_id);
+            count.read()?;
+            Some(Self { actor_id, count })
+        }
+
+        fn remove(&self) {
+            self.count.remove();
+        }
+    }
+
+    impl CounterInstance {
+        fn increment(&mut self) -> u32 {
+            let count = self.count.read().unwrap_or_default() + 1;
+            self.count.write(count);
+            count
+        }
+    }
+
+    #[tokio::test]
+    async fn test_actor_is_revived_after_passivation_and_not_after_removal() {
+        // ------ ARRANGE ------
+        let actor_id = ActorId::new();
+        let counter = ActorRef::activate(CounterInstance {
+            actor_id,
+            count: PVarCount(actor_id).create(0),
+        });
+
+        // ------ ACT ------
+        let first_count = counter.call(CounterInstance::increment).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let passivated = MAILBOXES.get(&actor_id).is_none();
+        let revived_count = counter.call(CounterInstance::increment).await;
+        counter.remove();
+        let count_after_removal = counter.call(CounterInstance::increment).await;
+
+        // ------ ASSERT ------
+        assert_eq!(first_count, Some(1));
+        assert!(passivated);
+        assert_eq!(revived_count, Some(2));
+        assert_eq!(count_after_removal, None);
+    }
+}
@@ -1,37 +1,286 @@
-use crate::actor::{ActorId, PVar};
+use crate::actor::{storage::storage, ActorId, PVar};
 use async_trait::async_trait;
-use std::borrow::Borrow;
+use moonlight::serde_json;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    error::Error,
+    fmt,
+    time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
+
+/// How long `Index::wait_for` remembers removed keys to report `WaitForError::Removed` on timeout.
+const REMOVED_KEY_RETENTION: Duration = Duration::from_secs(60);
+
+/// Index changes are written to the storage before waiters are notified,
+/// `wait_for` registers its waiter before it reads the storage so it can't miss a notification.
+static NOTIFICATIONS: Lazy<Mutex<Notifications>> = Lazy::new(Mutex::default);
+
+/// Index key and serialized entry key.
+type EntryKey = (&'static str, String);
+
+#[derive(Default)]
+struct Notifications {
+    waiters: HashMap<EntryKey, Vec<oneshot::Sender<ActorId>>>,
+    removed_keys: HashMap<EntryKey, Instant>,
+}
 
 #[async_trait(?Send)]
 pub trait Index {
+    const KEY: &'static str;
     type PVar: PVar;
-    type Actor;
+    type Actor: From<ActorId>;
+
+    fn insert(&self, key: <Self::PVar as PVar>::Value, actor_id: ActorId) {
+        if let Some(key) = serialize_key::<Self>(&key) {
+            storage().write(Self::KEY, &key, actor_id.to_string());
+            NOTIFICATIONS.lock().inserted((Self::KEY, key), actor_id);
+        }
+    }
+
+    fn get(&self, key: impl Borrow<<Self::PVar as PVar>::Value>) -> Option<Self::Actor> {
+        let key = serialize_key::<Self>(key.borrow())?;
+        let actor_id = storage().read(Self::KEY, &key)?;
+        Some(Self::Actor::from(actor_id.parse().ok()?))
+    }
 
-    fn insert(&self, _key: <Self::PVar as PVar>::Value, _actor_id: ActorId) {
-        todo!()
+    fn remove(&self, key: impl Borrow<<Self::PVar as PVar>::Value>) {
+        if let Some(key) = serialize_key::<Self>(key.borrow()) {
+            storage().remove(Self::KEY, &key);
+            NOTIFICATIONS.lock().removed((Self::KEY, key));
+        }
     }
 
-    fn get(&self, _key: impl Borrow<<Self::PVar as PVar>::Value>) -> Option<Self::Actor> {
-        todo!()
+    fn len(&self) -> usize {
+        storage().len(Self::KEY)
     }
 
-    fn for_each(&self, _f: impl FnMut(<Self::PVar as PVar>::Value, Self::Actor)) {
-        todo!()
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn for_each(&self, mut f: impl FnMut(<Self::PVar as PVar>::Value, Self::Actor)) {
+        for (key, actor_id) in storage().entries(Self::KEY) {
+            let (Ok(key_value), Ok(actor_id)) = (serde_json::from_str(&key), actor_id.parse())
+            else {
+                eprintln!("Skipping invalid entry '{key}' in index '{}'", Self::KEY);
+                continue;
+            };
+            f(key_value, Self::Actor::from(actor_id))
+        }
     }
 
+    /// Waits until the `key` is inserted, e.g. when an `UpMsg` arrives before
+    /// the `message_sse` connection has created the `SessionActor`.
     async fn wait_for(
         &self,
         key: impl Borrow<<Self::PVar as PVar>::Value> + 'static,
-    ) -> Option<Self::Actor> {
-        // @TODO backoff + jitter + queue or something else?
-        let key = key.borrow();
-        for i in 0..10 {
-            let session = self.get(key);
-            if session.is_some() {
-                return session;
+        timeout: Duration,
+    ) -> Result<Self::Actor, WaitForError> {
+        let entry_key = (
+            Self::KEY,
+            serialize_key::<Self>(key.borrow()).ok_or(WaitForError::InvalidKey)?,
+        );
+        let receiver = NOTIFICATIONS.lock().add_waiter(entry_key.clone());
+        if let Some(actor) = self.get(key.borrow()) {
+            drop(receiver);
+            NOTIFICATIONS.lock().remove_closed_waiters(&entry_key);
+            return Ok(actor);
+        }
+        match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(actor_id)) => Ok(Self::Actor::from(actor_id)),
+            Ok(Err(_)) | Err(_) => {
+                let mut notifications = NOTIFICATIONS.lock();
+                notifications.remove_closed_waiters(&entry_key);
+                if notifications.was_removed(&entry_key) {
+                    return Err(WaitForError::Removed);
+                }
+                Err(WaitForError::TimedOut(timeout))
+            }
+        }
+    }
+}
+
+fn serialize_key<I: Index + ?Sized>(key: &<I::PVar as PVar>::Value) -> Option<String> {
+    serde_json::to_string(key)
+        .map_err(|error| eprintln!("Failed to serialize key of index '{}': {error}", I::KEY))
+        .ok()
+}
+
+// ------ Notifications ------
+
+impl Notifications {
+    fn add_waiter(&mut self, entry_key: EntryKey) -> oneshot::Receiver<ActorId> {
+        let (sender, receiver) = oneshot::channel();
+        self.waiters.entry(entry_key).or_default().push(sender);
+        receiver
+    }
+
+    fn remove_closed_waiters(&mut self, entry_key: &EntryKey) {
+        if let Some(waiters) = self.waiters.get_mut(entry_key) {
+            waiters.retain(|waiter| !waiter.is_closed());
+            if waiters.is_empty() {
+                self.waiters.remove(entry_key);
             }
-            tokio::time::sleep(tokio::time::Duration::from_millis(i * 200)).await;
         }
-        None
+    }
+
+    fn inserted(&mut self, entry_key: EntryKey, actor_id: ActorId) {
+        self.removed_keys.remove(&entry_key);
+        for waiter in self.waiters.remove(&entry_key).unwrap_or_default() {
+            let _ = waiter.send(actor_id);
+        }
+    }
+
+    fn removed(&mut self, entry_key: EntryKey) {
+        let now = Instant::now();
+        self.removed_keys
+            .retain(|_, removed_at| now - *removed_at < REMOVED_KEY_RETENTION);
+        self.removed_keys.insert(entry_key, now);
+    }
+
+    fn was_removed(&self, entry_key: &EntryKey) -> bool {
+        self.removed_keys
+            .get(entry_key)
+            .is_some_and(|removed_at| removed_at.elapsed() < REMOVED_KEY_RETENTION)
+    }
+}
+
+// ------ WaitForError ------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitForError {
+    /// The key has never existed (or it has been removed long ago)
+    /// and it hasn't been inserted within the timeout.
+    TimedOut(Duration),
+    /// The key has been removed recently, e.g. the session has expired,
+    /// and it hasn't been inserted again within the timeout.
+    Removed,
+    /// The key couldn't be serialized.
+    InvalidKey,
+}
+
+impl fmt::Display for WaitForError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TimedOut(timeout) => write!(f, "the key hasn't been inserted within {timeout:?}"),
+            Self::Removed => write!(f, "the key has been removed"),
+            Self::InvalidKey => write!(f, "the key couldn't be serialized"),
+        }
+    }
+}
+
+impl Error for WaitForError {}
+
+// ====== ====== TESTS ====== ======
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[derive(Clone, Copy)]
+    struct PVarName(ActorId);
+
+    impl PVar for PVarName {
+        const KEY: &'static str = "test_user.name";
+        type Value = String;
+
+        fn actor_id(&self) -> ActorId {
+            self.0
+        }
+    }
+
+    struct ByName;
+
+    impl Index for ByName {
+        const KEY: &'static str = "test_user.by_name";
+        type PVar = PVarName;
+        type Actor = ActorId;
+    }
+
+    /// JSON object keys have to be strings.
+    #[derive(Clone, Copy)]
+    struct PVarScores(ActorId);
+
+    impl PVar for PVarScores {
+        const KEY: &'static str = "test_user.scores";
+        type Value = BTreeMap<(u8, u8), u8>;
+
+        fn actor_id(&self) -> ActorId {
+            self.0
+        }
+    }
+
+    struct ByScores;
+
+    impl Index for ByScores {
+        const KEY: &'static str = "test_user.by_scores";
+        type PVar = PVarScores;
+        type Actor = ActorId;
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_is_notified_on_insert_and_reports_removal() {
+        // ------ ARRANGE ------
+        let actor_id = ActorId::new();
+        let timeout = Duration::from_secs(5);
+
+        // ------ ACT ------
+        let (inserted_actor, ()) =
+            tokio::join!(ByName.wait_for("Alice".to_owned(), timeout), async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                ByName.insert("Alice".to_owned(), actor_id);
+            });
+        ByName.remove("Alice".to_owned());
+        let removed_actor = ByName
+            .wait_for("Alice".to_owned(), Duration::from_millis(50))
+            .await;
+        let missing_actor = ByName
+            .wait_for("Bob".to_owned(), Duration::from_millis(50))
+            .await;
+
+        // ------ ASSERT ------
+        assert_eq!(inserted_actor, Ok(actor_id));
+        assert_eq!(removed_actor, Err(WaitForError::Removed));
+        assert_eq!(
+            missing_actor,
+            Err(WaitForError::TimedOut(Duration::from_millis(50)))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_is_notified_on_insert_after_removal() {
+        // ------ ARRANGE ------
+        let (actor_id, new_actor_id) = (ActorId::new(), ActorId::new());
+        ByName.insert("Carol".to_owned(), actor_id);
+        ByName.remove("Carol".to_owned());
+
+        // ------ ACT ------
+        let (reinserted_actor, ()) = tokio::join!(
+            ByName.wait_for("Carol".to_owned(), Duration::from_secs(5)),
+            async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                ByName.insert("Carol".to_owned(), new_actor_id);
+            }
+        );
+
+        // ------ ASSERT ------
+        assert_eq!(reinserted_actor, Ok(new_actor_id));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_fails_on_invalid_key() {
+        // ------ ARRANGE ------
+        let scores = BTreeMap::from([((1, 2), 3)]);
+
+        // ------ ACT ------
+        let actor = ByScores.wait_for(scores, Duration::from_secs(5)).await;
+
+        // ------ ASSERT ------
+        assert_eq!(actor, Err(WaitForError::InvalidKey));
     }
 }
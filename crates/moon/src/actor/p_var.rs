@@ -1,24 +1,33 @@
-use crate::actor::ActorId;
+use crate::actor::{storage::storage, ActorId};
+use moonlight::serde_json;
+use serde::{de::DeserializeOwned, Serialize};
 
 pub trait PVar: Sized {
-    const KEY: &'static str = "session_Id";
-    type Value;
+    const KEY: &'static str;
+    type Value: Serialize + DeserializeOwned;
 
     fn actor_id(&self) -> ActorId;
 
-    fn create(self, _value: Self::Value) -> Self {
-        todo!()
+    fn create(self, value: Self::Value) -> Self {
+        self.write(value);
+        self
     }
 
     fn read(&self) -> Option<Self::Value> {
-        todo!()
+        let value = storage().read(Self::KEY, &self.actor_id().to_string())?;
+        serde_json::from_str(&value)
+            .map_err(|error| eprintln!("Failed to deserialize PVar '{}': {error}", Self::KEY))
+            .ok()
     }
 
-    fn write(&self, _value: Self::Value) {
-        todo!()
+    fn write(&self, value: Self::Value) {
+        match serde_json::to_string(&value) {
+            Ok(value) => storage().write(Self::KEY, &self.actor_id().to_string(), value),
+            Err(error) => eprintln!("Failed to serialize PVar '{}': {error}", Self::KEY),
+        }
     }
 
     fn remove(&self) {
-        todo!()
+        storage().remove(Self::KEY, &self.actor_id().to_string());
     }
 }
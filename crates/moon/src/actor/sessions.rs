@@ -1,14 +1,21 @@
-use crate::actor::{ActorId, ActorInstance, Index, PVar};
+use crate::actor::{storage::storage, ActorId, ActorInstance, ActorRef, Index, PVar};
 use crate::sse::ShareableSSEMethods;
-use crate::MessageSSE;
-use chashmap::CHashMap;
+use crate::{actor, MessageSSE};
 use futures::future::join_all;
-use moonlight::{serde_json, CorId, DownMsgTransporterForSer, Serialize, SessionId};
+use moonlight::{
+    Codec, CorId, DownMsgTransporterForSer, Serialize, SessionId, WebSocketFrameForSer,
+};
 use once_cell::sync::Lazy;
-use std::borrow::Borrow;
-use std::cell::RefCell;
+use parking_lot::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
 
-// @TODO rewrite to a proper virtual actor
+mod membership;
+use membership::MEMBERSHIP;
+
+/// Serializes changes of the `by_session_id` index
+/// so a reconnected session can't lose its mapping to a removed one.
+static SESSION_MAPPING_LOCK: Lazy<Mutex<()>> = Lazy::new(Mutex::default);
 
 pub async fn broadcast_down_msg<DMsg: Serialize>(down_msg: &DMsg, cor_id: CorId) {
     let mut send_down_msg_futs = vec![];
@@ -18,170 +25,285 @@ pub async fn broadcast_down_msg<DMsg: Serialize>(down_msg: &DMsg, cor_id: CorId)
     join_all(send_down_msg_futs).await;
 }
 
-// ------ Indices ------
-
-static BY_SESSION_ID: Lazy<CHashMap<SessionId, SessionActor>> = Lazy::new(CHashMap::new);
+/// Sends the `DownMsg` to all sessions that have joined the `group` (e.g. a chat room).
+pub async fn send_down_msg_to_group<DMsg: Serialize>(group: &str, down_msg: &DMsg, cor_id: CorId) {
+    let session_ids = MEMBERSHIP.read().group_sessions(group);
+    send_down_msg_to_sessions(session_ids, down_msg, cor_id).await
+}
 
-pub const fn by_session_id() -> BySessionId {
-    BySessionId
+/// Sends the `DownMsg` to all sessions tagged with the `user_id` (e.g. all user's browser tabs).
+pub async fn send_down_msg_to_user<DMsg: Serialize>(user_id: &str, down_msg: &DMsg, cor_id: CorId) {
+    let session_ids = MEMBERSHIP.read().user_sessions(user_id);
+    send_down_msg_to_sessions(session_ids, down_msg, cor_id).await
 }
-pub struct BySessionId;
-impl Index for BySessionId {
-    type PVar = PVarSessionId;
-    type Actor = SessionActor;
 
-    // --
+pub async fn send_down_msg_to_sessions<DMsg: Serialize>(
+    session_ids: impl IntoIterator<Item = SessionId>,
+    down_msg: &DMsg,
+    cor_id: CorId,
+) {
+    let send_down_msg_futs = session_ids
+        .into_iter()
+        .filter_map(|session_id| by_session_id().get(session_id))
+        .map(|session_actor| async move { session_actor.send_down_msg(down_msg, cor_id).await });
+    join_all(send_down_msg_futs).await;
+}
 
-    fn insert(&self, key: <Self::PVar as PVar>::Value, actor_id: ActorId) {
-        BY_SESSION_ID.insert(key, SessionActor { actor_id });
+actor! {
+    pub actor SessionActor(SessionActorInstance): "_session";
+    p_vars {
+        pub PVarSessionId(SessionId): "session_id";
     }
-
-    fn get(&self, key: impl Borrow<<Self::PVar as PVar>::Value>) -> Option<Self::Actor> {
-        BY_SESSION_ID
-            .get(key.borrow())
-            .map(|session_actor| *session_actor)
+    indices {
+        pub by_session_id: BySessionId(PVarSessionId);
     }
+}
 
-    fn for_each(&self, f: impl FnMut(SessionId, SessionActor)) {
-        let f = RefCell::new(f);
-        BY_SESSION_ID.retain(|session_id, session_actor| {
-            f.borrow_mut()(*session_id, *session_actor);
-            true
-        });
-    }
+/// The user the session has been bound to by `SessionActor::set_user_id`
+/// or by the authenticated message SSE handshake.
+pub(crate) fn session_user_id(session_id: SessionId) -> Option<String> {
+    MEMBERSHIP.read().user_id(session_id).map(ToOwned::to_owned)
 }
 
-// ------ PVars ------
+/// Called by the connection remover when the session hasn't reconnected in time.
+/// The session leaves all its groups and it's untagged.
+pub(crate) fn remove_session(session_id: SessionId) {
+    let _session_mapping_lock = SESSION_MAPPING_LOCK.lock();
+    let Some(session_actor) = by_session_id().get(session_id) else {
+        return;
+    };
+    by_session_id().remove(session_id);
+    MEMBERSHIP.write().remove_session(session_id);
+    session_actor.remove();
+}
 
-static PVAR_SESSION_IDS: Lazy<CHashMap<ActorId, SessionId>> = Lazy::new(CHashMap::new);
+/// Sessions don't survive restarts, their connections are closed.
+pub(crate) fn remove_stale_sessions() {
+    storage().remove_collection(PVarSessionId::KEY);
+    storage().remove_collection(BySessionId::KEY);
+}
 
-#[derive(Clone, Copy)]
-pub struct PVarSessionId(ActorId);
-impl PVar for PVarSessionId {
-    const KEY: &'static str = "session_id";
-    type Value = SessionId;
+// ------ DownMsgChannel ------
 
-    fn actor_id(&self) -> ActorId {
-        self.0
-    }
+#[derive(Clone)]
+pub(crate) enum DownMsgChannel {
+    SSE(MessageSSE),
+    WebSocket(UnboundedSender<Vec<u8>>),
+}
 
-    // --
+// ------ Actor ------
 
-    fn create(self, value: Self::Value) -> Self {
-        PVAR_SESSION_IDS.insert(self.0, value);
-        self
-    }
+// -- SessionActor --
 
-    fn read(&self) -> Option<Self::Value> {
-        PVAR_SESSION_IDS
-            .get(&self.0)
-            .map(|session_id| session_id.clone())
+impl SessionActor {
+    pub fn create(session_id: SessionId, message_sse: MessageSSE) -> Self {
+        Self::create_with_channel(
+            session_id,
+            DownMsgChannel::SSE(message_sse),
+            Codec::default(),
+        )
     }
 
-    fn write(&self, value: Self::Value) {
-        PVAR_SESSION_IDS.insert(self.0, value);
+    pub(crate) fn create_with_channel(
+        session_id: SessionId,
+        down_msg_channel: DownMsgChannel,
+        codec: Codec,
+    ) -> Self {
+        let session_mapping_lock = SESSION_MAPPING_LOCK.lock();
+        // The reconnected client's actor is reused so `SessionActor`s held by the app stay valid.
+        // Its previous instance processes the messages sent before and then it's dropped.
+        let actor_id = by_session_id()
+            .get(session_id)
+            .map_or_else(ActorId::new, |session_actor| session_actor.actor_id);
+        by_session_id().insert(session_id, actor_id);
+
+        ActorRef::activate(SessionActorInstance {
+            actor_id,
+            down_msg_channel,
+            codec,
+            session_id: PVarSessionId::new(actor_id).create(session_id),
+        });
+        drop(session_mapping_lock);
+
+        println!(
+            "New session: `{}`. (Session count: {})",
+            session_id,
+            by_session_id().len()
+        );
+        Self { actor_id }
     }
 
     fn remove(&self) {
-        PVAR_SESSION_IDS.remove(&self.0);
+        self.actor_ref().remove();
     }
-}
 
-// ------ Actor ------
+    /// Joins the group (e.g. a chat room or a topic).
+    /// The session leaves all its groups when it hasn't reconnected
+    /// within `replay_buffer_max_age` after its connection has been lost.
+    pub fn join_group(&self, group: impl Into<String>) {
+        let mut membership = MEMBERSHIP.write();
+        if let Some(session_id) = self.live_session_id() {
+            membership.join_group(session_id, group.into());
+        }
+    }
 
-// -- SessionActor --
+    pub fn leave_group(&self, group: &str) {
+        let mut membership = MEMBERSHIP.write();
+        if let Some(session_id) = self.live_session_id() {
+            membership.leave_group(session_id, group);
+        }
+    }
 
-#[derive(Clone, Copy)]
-pub struct SessionActor {
-    actor_id: ActorId,
-}
+    pub fn groups(&self) -> Vec<String> {
+        let membership = MEMBERSHIP.read();
+        self.live_session_id()
+            .map(|session_id| membership.groups(session_id))
+            .unwrap_or_default()
+    }
 
-impl SessionActor {
-    pub fn create(session_id: SessionId, message_sse: MessageSSE) -> Self {
-        Self {
-            actor_id: SessionActorInstance::create(session_id, message_sse),
+    /// Tags the session with the id of the authenticated user.
+    pub fn set_user_id(&self, user_id: impl Into<String>) {
+        let mut membership = MEMBERSHIP.write();
+        if let Some(session_id) = self.live_session_id() {
+            membership.set_user_id(session_id, Some(user_id.into()));
         }
     }
 
-    pub(crate) fn remove(&self) {
-        if let Some(instance) = SESSION_ACTOR_INSTANCES.remove(&self.actor_id) {
-            let session_id = instance.session_id.read();
-            instance.remove();
-            if let Some(session_id) = session_id {
-                println!(
-                    "Session `{}` closed. (Session count: {})",
-                    session_id,
-                    SESSION_ACTOR_INSTANCES.len(),
-                )
-            }
+    /// Untags the session, e.g. on logout.
+    pub fn remove_user_id(&self) {
+        let mut membership = MEMBERSHIP.write();
+        if let Some(session_id) = self.live_session_id() {
+            membership.set_user_id(session_id, None);
         }
     }
 
+    pub fn user_id(&self) -> Option<String> {
+        let membership = MEMBERSHIP.read();
+        membership
+            .user_id(self.live_session_id()?)
+            .map(ToOwned::to_owned)
+    }
+
+    /// Returns `None` if the session has been removed.
+    ///
+    /// Call it while holding the `MEMBERSHIP` lock to prevent races with `remove_session`.
+    fn live_session_id(&self) -> Option<SessionId> {
+        let session_id = PVarSessionId::new(self.actor_id).read()?;
+        by_session_id()
+            .get(session_id)
+            .is_some_and(|session_actor| session_actor == *self)
+            .then_some(session_id)
+    }
+
     pub async fn send_down_msg<DMsg: Serialize>(&self, down_msg: &DMsg, cor_id: CorId) {
-        if let Some(instance) = SESSION_ACTOR_INSTANCES.get(&self.actor_id) {
-            instance.send_down_msg(down_msg, cor_id).await;
+        let down_msg_target = self
+            .actor_ref()
+            .call(|instance| (instance.down_msg_channel.clone(), instance.codec))
+            .await;
+        let Some((down_msg_channel, codec)) = down_msg_target else {
+            return;
+        };
+        let Some(session_id) = PVarSessionId::new(self.actor_id).read() else {
+            return;
+        };
+
+        let down_msg_transporter = DownMsgTransporterForSer { down_msg, cor_id };
+
+        match down_msg_channel {
+            DownMsgChannel::SSE(message_sse) => {
+                #[cfg(feature = "serde")]
+                let down_msg_transporter = match codec.encode_to_string(&down_msg_transporter) {
+                    Ok(down_msg_transporter) => down_msg_transporter,
+                    Err(error) => return eprintln!("Failed to encode DownMsg: {error}"),
+                };
+                message_sse.send(&session_id, "down_msg", &down_msg_transporter);
+            }
+            DownMsgChannel::WebSocket(down_msg_sender) => {
+                let frame = WebSocketFrameForSer::DownMsg(down_msg_transporter);
+                #[cfg(feature = "serde")]
+                let down_msg_transporter = match codec.encode(&frame) {
+                    Ok(down_msg_transporter) => down_msg_transporter,
+                    Err(error) => return eprintln!("Failed to encode DownMsg: {error}"),
+                };
+                let _ = down_msg_sender.send(down_msg_transporter);
+            }
         }
     }
 }
 
 // -- SessionActorInstance --
 
-static SESSION_ACTOR_INSTANCES: Lazy<CHashMap<ActorId, SessionActorInstance>> =
-    Lazy::new(CHashMap::new);
-
 struct SessionActorInstance {
     actor_id: ActorId,
-    message_sse: MessageSSE,
+    down_msg_channel: DownMsgChannel,
+    codec: Codec,
     session_id: PVarSessionId,
 }
 
 impl ActorInstance for SessionActorInstance {
     const KEY: &'static str = "_session";
+    // Sessions are removed when their connections are closed.
+    const IDLE_TIMEOUT: Option<Duration> = None;
 
     fn actor_id(&self) -> ActorId {
         self.actor_id
     }
 
-    fn revive(_actor_id: ActorId) -> Self {
-        unimplemented!("revive not implemented for SessionActorInstance");
+    // The DownMsg channel cannot be restored, the reconnected client activates the actor again.
+    fn revive(_actor_id: ActorId) -> Option<Self> {
+        None
     }
 
     fn remove(&self) {
+        // the index entry and the membership have been removed by `remove_session`
+        let Some(session_id) = self.session_id.read() else {
+            return;
+        };
         self.session_id.remove();
-        SESSION_ACTOR_INSTANCES.remove(&self.actor_id);
+        println!(
+            "Session `{}` closed. (Session count: {})",
+            session_id,
+            by_session_id().len(),
+        )
     }
 }
 
-impl SessionActorInstance {
-    fn create(session_id: SessionId, message_sse: MessageSSE) -> ActorId {
-        let actor_id = ActorId::new();
+// ====== ====== TESTS ====== ======
 
-        by_session_id().insert(session_id, actor_id);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc::unbounded_channel;
 
-        let actor_instance = Self {
-            actor_id,
-            message_sse,
-            session_id: PVarSessionId(actor_id).create(session_id),
-        };
-        SESSION_ACTOR_INSTANCES.insert(actor_id, actor_instance);
-
-        println!(
-            "New session: `{}`. (Session count: {})",
+    #[tokio::test]
+    async fn test_reconnected_session_keeps_its_actor_and_groups() {
+        // ------ ARRANGE ------
+        let session_id = SessionId::new();
+        let group = session_id.to_string();
+        let (down_msg_sender, _down_msg_receiver) = unbounded_channel();
+        let session_actor = SessionActor::create_with_channel(
             session_id,
-            SESSION_ACTOR_INSTANCES.len()
+            DownMsgChannel::WebSocket(down_msg_sender),
+            Codec::default(),
         );
-        actor_id
-    }
-
-    pub async fn send_down_msg<DMsg: Serialize>(&self, down_msg: &DMsg, cor_id: CorId) {
-        let session_id = self.session_id.read().unwrap();
-
-        let down_msg_transporter = DownMsgTransporterForSer { down_msg, cor_id };
-
-        #[cfg(feature = "serde")]
-        let down_msg_transporter = serde_json::to_string(&down_msg_transporter).unwrap();
+        session_actor.join_group(group.clone());
+        let (new_down_msg_sender, mut new_down_msg_receiver) = unbounded_channel();
 
-        self.message_sse
-            .send(&session_id, "down_msg", &down_msg_transporter);
+        // ------ ACT ------
+        let reconnected_session_actor = SessionActor::create_with_channel(
+            session_id,
+            DownMsgChannel::WebSocket(new_down_msg_sender),
+            Codec::default(),
+        );
+        session_actor.send_down_msg(&"hello", CorId::new()).await;
+        let groups_after_reconnect = session_actor.groups();
+        remove_session(session_id);
+
+        // ------ ASSERT ------
+        assert!(reconnected_session_actor == session_actor);
+        assert_eq!(groups_after_reconnect, vec![group.clone()]);
+        assert!(new_down_msg_receiver.try_recv().is_ok());
+        assert!(session_actor.groups().is_empty());
+        assert!(MEMBERSHIP.read().group_sessions(&group).is_empty());
     }
 }
@@ -0,0 +1,126 @@
+use moonlight::SessionId;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::collections::{HashMap, HashSet};
+
+pub(super) static MEMBERSHIP: Lazy<RwLock<Membership>> = Lazy::new(RwLock::default);
+
+// ------ Membership ------
+
+/// Groups and users of live sessions.
+#[derive(Default)]
+pub(super) struct Membership {
+    groups: HashMap<String, HashSet<SessionId>>,
+    users: HashMap<String, HashSet<SessionId>>,
+    session_groups: HashMap<SessionId, HashSet<String>>,
+    session_users: HashMap<SessionId, String>,
+}
+
+impl Membership {
+    pub(super) fn join_group(&mut self, session_id: SessionId, group: String) {
+        self.groups
+            .entry(group.clone())
+            .or_default()
+            .insert(session_id);
+        self.session_groups
+            .entry(session_id)
+            .or_default()
+            .insert(group);
+    }
+
+    pub(super) fn leave_group(&mut self, session_id: SessionId, group: &str) {
+        remove_member(&mut self.groups, group, session_id);
+        if let Some(groups) = self.session_groups.get_mut(&session_id) {
+            groups.remove(group);
+            if groups.is_empty() {
+                self.session_groups.remove(&session_id);
+            }
+        }
+    }
+
+    pub(super) fn set_user_id(&mut self, session_id: SessionId, user_id: Option<String>) {
+        if let Some(previous_user_id) = self.session_users.remove(&session_id) {
+            remove_member(&mut self.users, &previous_user_id, session_id);
+        }
+        if let Some(user_id) = user_id {
+            self.users
+                .entry(user_id.clone())
+                .or_default()
+                .insert(session_id);
+            self.session_users.insert(session_id, user_id);
+        }
+    }
+
+    pub(super) fn user_id(&self, session_id: SessionId) -> Option<&str> {
+        self.session_users.get(&session_id).map(String::as_str)
+    }
+
+    pub(super) fn groups(&self, session_id: SessionId) -> Vec<String> {
+        self.session_groups
+            .get(&session_id)
+            .map(|groups| groups.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub(super) fn group_sessions(&self, group: &str) -> Vec<SessionId> {
+        sessions_of(&self.groups, group)
+    }
+
+    pub(super) fn user_sessions(&self, user_id: &str) -> Vec<SessionId> {
+        sessions_of(&self.users, user_id)
+    }
+
+    pub(super) fn remove_session(&mut self, session_id: SessionId) {
+        for group in self.session_groups.remove(&session_id).unwrap_or_default() {
+            remove_member(&mut self.groups, &group, session_id);
+        }
+        self.set_user_id(session_id, None);
+    }
+}
+
+fn sessions_of(members: &HashMap<String, HashSet<SessionId>>, key: &str) -> Vec<SessionId> {
+    members
+        .get(key)
+        .map(|session_ids| session_ids.iter().copied().collect())
+        .unwrap_or_default()
+}
+
+fn remove_member(
+    members: &mut HashMap<String, HashSet<SessionId>>,
+    key: &str,
+    session_id: SessionId,
+) {
+    if let Some(session_ids) = members.get_mut(key) {
+        session_ids.remove(&session_id);
+        if session_ids.is_empty() {
+            members.remove(key);
+        }
+    }
+}
+
+// ====== ====== TESTS ====== ======
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_removed_session_leaves_its_groups_and_user() {
+        // ------ ARRANGE ------
+        let mut membership = Membership::default();
+        let (session_a, session_b) = (SessionId::new(), SessionId::new());
+        membership.join_group(session_a, "room".to_owned());
+        membership.join_group(session_b, "room".to_owned());
+        membership.join_group(session_a, "lobby".to_owned());
+        membership.set_user_id(session_a, Some("alice".to_owned()));
+
+        // ------ ACT ------
+        membership.remove_session(session_a);
+
+        // ------ ASSERT ------
+        assert_eq!(membership.group_sessions("room"), vec![session_b]);
+        assert!(membership.group_sessions("lobby").is_empty());
+        assert!(membership.user_sessions("alice").is_empty());
+        assert!(!membership.session_groups.contains_key(&session_a));
+    }
+}
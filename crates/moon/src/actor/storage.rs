@@ -0,0 +1,441 @@
+use crate::config::{StorageBackend, CONFIG};
+use moonlight::serde_json;
+use once_cell::sync::OnceCell;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+static STORAGE: OnceCell<Box<dyn Storage>> = OnceCell::new();
+
+/// Returns the storage set by `set_storage` or the one configured in `MoonZoon.toml`.
+pub fn storage() -> &'static dyn Storage {
+    STORAGE
+        .get_or_init(|| match CONFIG.storage.backend {
+            StorageBackend::Memory => Box::new(MemoryStorage::default()),
+            StorageBackend::File => Box::new(
+                FileStorage::open(&CONFIG.storage.path).unwrap_or_else(|error| {
+                    panic!(
+                        "failed to open the storage file '{}': {error}",
+                        CONFIG.storage.path.display()
+                    )
+                }),
+            ),
+        })
+        .as_ref()
+}
+
+/// Replaces the configured storage. Call it before `moon::start`.
+pub fn set_storage(storage: impl Storage) -> Result<(), Box<dyn Storage>> {
+    STORAGE.set(Box::new(storage))
+}
+
+// ------ Storage ------
+
+/// Key-value store for `PVar`s and indices.
+///
+/// Entries are grouped into collections named by `PVar::KEY` or `Index::KEY`.
+/// Values are serialized to JSON.
+pub trait Storage: Send + Sync + 'static {
+    fn read(&self, collection: &str, key: &str) -> Option<String>;
+
+    fn write(&self, collection: &str, key: &str, value: String);
+
+    fn remove(&self, collection: &str, key: &str);
+
+    fn remove_collection(&self, collection: &str);
+
+    fn entries(&self, collection: &str) -> Vec<(String, String)>;
+
+    fn len(&self, collection: &str) -> usize {
+        self.entries(collection).len()
+    }
+
+    /// Blocks until all changes are persisted.
+    fn flush(&self) {}
+}
+
+// ------ MemoryStorage ------
+
+/// Data are lost when the Moon app is stopped.
+#[derive(Default)]
+pub struct MemoryStorage {
+    collections: RwLock<HashMap<String, HashMap<String, String>>>,
+}
+
+impl Storage for MemoryStorage {
+    fn read(&self, collection: &str, key: &str) -> Option<String> {
+        self.collections.read().get(collection)?.get(key).cloned()
+    }
+
+    fn write(&self, collection: &str, key: &str, value: String) {
+        self.collections
+            .write()
+            .entry(collection.to_owned())
+            .or_default()
+            .insert(key.to_owned(), value);
+    }
+
+    fn remove(&self, collection: &str, key: &str) {
+        if let Some(entries) = self.collections.write().get_mut(collection) {
+            entries.remove(key);
+        }
+    }
+
+    fn remove_collection(&self, collection: &str) {
+        self.collections.write().remove(collection);
+    }
+
+    fn entries(&self, collection: &str) -> Vec<(String, String)> {
+        self.collections
+            .read()
+            .get(collection)
+            .map(|entries| {
+                entries
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn len(&self, collection: &str) -> usize {
+        self.collections
+            .read()
+            .get(collection)
+            .map_or(0, HashMap::len)
+    }
+}
+
+// ------ FileStorage ------
+
+/// The log is compacted when it has at least this number of lines...
+const COMPACTION_MIN_LINES: usize = 10_000;
+/// ...and at least `COMPACTION_RATIO` times more lines than live entries.
+const COMPACTION_RATIO: usize = 2;
+
+/// Keeps data in memory and appends all changes to a JSON Lines file.
+///
+/// Changes are written by a dedicated thread so they don't block async handlers.
+/// The file is compacted when it's opened and when it grows too much.
+pub struct FileStorage {
+    memory: MemoryStorage,
+    log: Mutex<Log>,
+}
+
+struct Log {
+    commands: mpsc::Sender<LogCommand>,
+    line_count: usize,
+    compaction_threshold: usize,
+}
+
+enum LogCommand {
+    Append(String),
+    /// Replaces the file with the snapshot of all entries.
+    Compact(String),
+    Flush(mpsc::Sender<()>),
+}
+
+impl FileStorage {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+
+        let memory = MemoryStorage::default();
+        if path.exists() {
+            for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+                match serde_json::from_str::<Change>(&line?) {
+                    Ok(change) => change.apply(&memory),
+                    // probably an incomplete line written before a crash
+                    Err(error) => eprintln!(
+                        "Skipping invalid line {} in the storage file '{}': {error}",
+                        index + 1,
+                        path.display()
+                    ),
+                }
+            }
+        }
+        let (snapshot, entry_count) = snapshot(&memory)?;
+        let log = compact(path, &snapshot)?;
+
+        let (commands, command_receiver) = mpsc::channel();
+        let path = path.to_owned();
+        thread::Builder::new()
+            .name("moon_storage_writer".to_owned())
+            .spawn(move || write_log(&path, log, command_receiver))?;
+
+        Ok(Self {
+            memory,
+            log: Mutex::new(Log {
+                commands,
+                line_count: entry_count,
+                compaction_threshold: compaction_threshold(entry_count),
+            }),
+        })
+    }
+
+    /// Changes are applied to the memory and sent to the writer while the log is locked
+    /// so the writer receives them in the same order and snapshots are consistent.
+    fn apply(&self, change: Change) {
+        let line = match serde_json::to_string(&change) {
+            Ok(line) => line + "\n",
+            Err(error) => {
+                eprintln!("Failed to serialize the storage change: {error}");
+                return;
+            }
+        };
+        let mut log = self.log.lock();
+        change.apply(&self.memory);
+        let _ = log.commands.send(LogCommand::Append(line));
+        log.line_count += 1;
+        if log.line_count < log.compaction_threshold {
+            return;
+        }
+        let entry_count = self.entry_count();
+        log.compaction_threshold = compaction_threshold(entry_count);
+        if log.line_count < entry_count * COMPACTION_RATIO {
+            return;
+        }
+        match snapshot(&self.memory) {
+            Ok((snapshot, _)) => {
+                let _ = log.commands.send(LogCommand::Compact(snapshot));
+                log.line_count = entry_count;
+            }
+            Err(error) => eprintln!("Failed to compact the storage file: {error}"),
+        }
+    }
+
+    fn entry_count(&self) -> usize {
+        self.memory
+            .collections
+            .read()
+            .values()
+            .map(HashMap::len)
+            .sum()
+    }
+}
+
+impl Storage for FileStorage {
+    fn read(&self, collection: &str, key: &str) -> Option<String> {
+        self.memory.read(collection, key)
+    }
+
+    fn write(&self, collection: &str, key: &str, value: String) {
+        self.apply(Change::Write {
+            collection: collection.into(),
+            key: key.into(),
+            value: value.into(),
+        });
+    }
+
+    fn remove(&self, collection: &str, key: &str) {
+        self.apply(Change::Remove {
+            collection: collection.into(),
+            key: key.into(),
+        });
+    }
+
+    fn remove_collection(&self, collection: &str) {
+        self.apply(Change::RemoveCollection {
+            collection: collection.into(),
+        });
+    }
+
+    fn entries(&self, collection: &str) -> Vec<(String, String)> {
+        self.memory.entries(collection)
+    }
+
+    fn len(&self, collection: &str) -> usize {
+        self.memory.len(collection)
+    }
+
+    fn flush(&self) {
+        let (done_sender, done_receiver) = mpsc::channel();
+        if self
+            .log
+            .lock()
+            .commands
+            .send(LogCommand::Flush(done_sender))
+            .is_ok()
+        {
+            let _ = done_receiver.recv();
+        }
+    }
+}
+
+impl Drop for FileStorage {
+    fn drop(&mut self) {
+        // the writer thread ends when the command sender is dropped
+        self.flush();
+    }
+}
+
+fn compaction_threshold(entry_count: usize) -> usize {
+    COMPACTION_MIN_LINES.max(entry_count * COMPACTION_RATIO)
+}
+
+fn write_log(path: &Path, mut log: BufWriter<File>, commands: mpsc::Receiver<LogCommand>) {
+    while let Ok(command) = commands.recv() {
+        let mut flushed_senders = Vec::new();
+        // all waiting changes are written before the file is flushed
+        for command in iter::once(command).chain(commands.try_iter()) {
+            match command {
+                LogCommand::Append(line) => {
+                    if let Err(error) = log.write_all(line.as_bytes()) {
+                        eprintln!("Failed to write to the storage file: {error}");
+                    }
+                }
+                LogCommand::Compact(snapshot) => match compact(path, &snapshot) {
+                    Ok(compacted_log) => log = compacted_log,
+                    Err(error) => eprintln!("Failed to compact the storage file: {error}"),
+                },
+                LogCommand::Flush(flushed_sender) => flushed_senders.push(flushed_sender),
+            }
+        }
+        if let Err(error) = log.flush() {
+            eprintln!("Failed to write to the storage file: {error}");
+        }
+        for flushed_sender in flushed_senders {
+            let _ = flushed_sender.send(());
+        }
+    }
+}
+
+/// Returns all entries as `Change::Write` lines and their count.
+fn snapshot(memory: &MemoryStorage) -> serde_json::Result<(String, usize)> {
+    let mut snapshot = String::new();
+    let mut entry_count = 0;
+    for (collection, entries) in memory.collections.read().iter() {
+        for (key, value) in entries {
+            let change = Change::Write {
+                collection: collection.into(),
+                key: key.into(),
+                value: value.into(),
+            };
+            snapshot.push_str(&serde_json::to_string(&change)?);
+            snapshot.push('\n');
+            entry_count += 1;
+        }
+    }
+    Ok((snapshot, entry_count))
+}
+
+/// Atomically replaces the file with the snapshot and opens it for appending.
+fn compact(path: &Path, snapshot: &str) -> io::Result<BufWriter<File>> {
+    let mut compacted_path = PathBuf::from(path);
+    compacted_path.set_extension("compacting");
+
+    let mut compacted = File::create(&compacted_path)?;
+    compacted.write_all(snapshot.as_bytes())?;
+    compacted.sync_all()?;
+    fs::rename(compacted_path, path)?;
+
+    let log = OpenOptions::new().append(true).create(true).open(path)?;
+    Ok(BufWriter::new(log))
+}
+
+// ------ Change ------
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+enum Change<'a> {
+    Write {
+        collection: Cow<'a, str>,
+        key: Cow<'a, str>,
+        value: Cow<'a, str>,
+    },
+    Remove {
+        collection: Cow<'a, str>,
+        key: Cow<'a, str>,
+    },
+    RemoveCollection {
+        collection: Cow<'a, str>,
+    },
+}
+
+impl Change<'_> {
+    fn apply(self, memory: &MemoryStorage) {
+        match self {
+            Self::Write {
+                collection,
+                key,
+                value,
+            } => memory.write(&collection, &key, value.into_owned()),
+            Self::Remove { collection, key } => memory.remove(&collection, &key),
+            Self::RemoveCollection { collection } => memory.remove_collection(&collection),
+        }
+    }
+}
+
+// ====== ====== TESTS ====== ======
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_storage_restores_compacted_data() {
+        // ------ ARRANGE ------
+        let path =
+            std::env::temp_dir().join(format!("moon_storage_{}.jsonl", uuid::Uuid::new_v4()));
+        let storage = FileStorage::open(&path).unwrap();
+        storage.write("pvar", "a", "1".to_owned());
+        storage.write("pvar", "b", "2".to_owned());
+        storage.write("pvar", "a", "3".to_owned());
+        storage.remove("pvar", "b");
+        storage.write("index", "x", "a".to_owned());
+        storage.remove_collection("index");
+        drop(storage);
+
+        // ------ ACT ------
+        let storage = FileStorage::open(&path).unwrap();
+        let line_count = fs::read_to_string(&path).unwrap().lines().count();
+
+        // ------ ASSERT ------
+        assert_eq!(
+            storage.entries("pvar"),
+            vec![("a".to_owned(), "3".to_owned())]
+        );
+        assert!(storage.entries("index").is_empty());
+        assert_eq!(line_count, 1);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_file_storage_compacts_growing_log() {
+        // ------ ARRANGE ------
+        let path =
+            std::env::temp_dir().join(format!("moon_storage_{}.jsonl", uuid::Uuid::new_v4()));
+        let storage = FileStorage::open(&path).unwrap();
+
+        // ------ ACT ------
+        for count in 0..COMPACTION_MIN_LINES {
+            storage.write("pvar", "a", count.to_string());
+        }
+        storage.flush();
+        let line_count = fs::read_to_string(&path).unwrap().lines().count();
+
+        // ------ ASSERT ------
+        assert_eq!(line_count, 1);
+        drop(storage);
+        let storage = FileStorage::open(&path).unwrap();
+        assert_eq!(
+            storage.read("pvar", "a"),
+            Some((COMPACTION_MIN_LINES - 1).to_string())
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,247 @@
+use crate::config::CONFIG;
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use moonlight::{
+    serde_json::{self, Map, Value},
+    AuthToken,
+};
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    error::Error,
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// `{"alg":"HS256","typ":"JWT"}` encoded in Base64URL.
+const HMAC_TOKEN_HEADER: &str = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9";
+
+static AUTHENTICATOR: OnceCell<Option<Box<dyn Authenticator>>> = OnceCell::new();
+
+static HMAC_AUTHENTICATOR: Lazy<Option<HmacAuthenticator>> =
+    Lazy::new(|| CONFIG.auth.secret.as_ref().map(HmacAuthenticator::new));
+
+/// Returns the authenticator set by `set_authenticator` or
+/// the `HmacAuthenticator` when `AUTH_SECRET` is configured.
+pub fn authenticator() -> Option<&'static dyn Authenticator> {
+    AUTHENTICATOR
+        .get_or_init(|| {
+            HMAC_AUTHENTICATOR
+                .as_ref()
+                .map(|authenticator| Box::new(authenticator.clone()) as Box<dyn Authenticator>)
+        })
+        .as_deref()
+}
+
+/// Replaces the configured authenticator. Call it before `moon::start`.
+pub fn set_authenticator(
+    authenticator: impl Authenticator,
+) -> Result<(), Option<Box<dyn Authenticator>>> {
+    AUTHENTICATOR.set(Some(Box::new(authenticator)))
+}
+
+/// Issues a token signed by the `HmacAuthenticator`, e.g. in your login `UpMsg` handler.
+///
+/// # Panics
+///
+/// Panics when `AUTH_SECRET` isn't configured.
+pub fn issue_token(identity: &Identity) -> AuthToken {
+    HMAC_AUTHENTICATOR
+        .as_ref()
+        .expect("AUTH_SECRET has to be set to issue auth tokens")
+        .issue_token(identity)
+}
+
+// ------ Authenticator ------
+
+/// Verifies `X-Auth-Token` before `UpMsg`s are passed to the `up_msg_handler`.
+///
+/// Requests with an invalid token are rejected with `401 Unauthorized`.
+#[async_trait]
+pub trait Authenticator: Send + Sync + 'static {
+    async fn authenticate(&self, auth_token: &AuthToken) -> Result<Identity, AuthError>;
+}
+
+// ------ Identity ------
+
+/// The verified user. Custom claims are stored next to the standard ones in the token.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Identity {
+    #[serde(rename = "sub")]
+    pub user_id: String,
+    /// Unix timestamp in seconds.
+    #[serde(rename = "exp")]
+    pub expires_at: u64,
+    #[serde(flatten)]
+    pub claims: Map<String, Value>,
+}
+
+impl Identity {
+    /// The identity expires after `AUTH_TOKEN_MAX_AGE`.
+    pub fn new(user_id: impl Into<String>) -> Self {
+        Self {
+            user_id: user_id.into(),
+            expires_at: unix_now() + CONFIG.auth.token_max_age,
+            claims: Map::new(),
+        }
+    }
+
+    pub fn claim(mut self, name: impl Into<String>, value: impl Serialize) -> Self {
+        match serde_json::to_value(value) {
+            Ok(value) => {
+                self.claims.insert(name.into(), value);
+            }
+            Err(error) => eprintln!("Failed to serialize the identity claim: {error}"),
+        }
+        self
+    }
+
+    pub fn get_claim<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        serde_json::from_value(self.claims.get(name)?.clone()).ok()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= unix_now()
+    }
+}
+
+// ------ HmacAuthenticator ------
+
+/// Verifies JWT-style tokens signed with HMAC-SHA256 (`HS256`).
+#[derive(Clone)]
+pub struct HmacAuthenticator {
+    mac: Hmac<Sha256>,
+}
+
+impl HmacAuthenticator {
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            mac: Hmac::new_from_slice(secret.as_ref()).expect("HMAC accepts keys of any size"),
+        }
+    }
+
+    pub fn issue_token(&self, identity: &Identity) -> AuthToken {
+        let payload = serde_json::to_vec(identity).expect("Identity is always serializable");
+        let signed_part = format!("{HMAC_TOKEN_HEADER}.{}", URL_SAFE_NO_PAD.encode(payload));
+        let signature = URL_SAFE_NO_PAD.encode(self.sign(&signed_part).finalize().into_bytes());
+        AuthToken::new(format!("{signed_part}.{signature}"))
+    }
+
+    pub fn verify(&self, auth_token: &AuthToken) -> Result<Identity, AuthError> {
+        let (signed_part, signature) = auth_token
+            .as_str()
+            .rsplit_once('.')
+            .ok_or(AuthError::Malformed)?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| AuthError::Malformed)?;
+        // constant-time comparison
+        self.sign(signed_part)
+            .verify_slice(&signature)
+            .map_err(|_| AuthError::InvalidSignature)?;
+
+        let payload = signed_part
+            .strip_prefix(HMAC_TOKEN_HEADER)
+            .and_then(|payload| payload.strip_prefix('.'))
+            .ok_or(AuthError::Malformed)?;
+        let payload = URL_SAFE_NO_PAD
+            .decode(payload)
+            .map_err(|_| AuthError::Malformed)?;
+        let identity =
+            serde_json::from_slice::<Identity>(&payload).map_err(|_| AuthError::Malformed)?;
+
+        if identity.is_expired() {
+            Err(AuthError::Expired)?
+        }
+        Ok(identity)
+    }
+
+    fn sign(&self, signed_part: &str) -> Hmac<Sha256> {
+        let mut mac = self.mac.clone();
+        mac.update(signed_part.as_bytes());
+        mac
+    }
+}
+
+#[async_trait]
+impl Authenticator for HmacAuthenticator {
+    async fn authenticate(&self, auth_token: &AuthToken) -> Result<Identity, AuthError> {
+        self.verify(auth_token)
+    }
+}
+
+// ------ AuthError ------
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    Malformed,
+    InvalidSignature,
+    Expired,
+    /// Returned by custom `Authenticator`s.
+    Rejected(String),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed => write!(f, "auth token is malformed"),
+            Self::InvalidSignature => write!(f, "auth token has an invalid signature"),
+            Self::Expired => write!(f, "auth token has expired"),
+            Self::Rejected(reason) => write!(f, "auth token has been rejected: {reason}"),
+        }
+    }
+}
+
+impl Error for AuthError {}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+// ====== ====== TESTS ====== ======
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hmac_authenticator_verifies_only_valid_tokens() {
+        // ------ ARRANGE ------
+        let authenticator = HmacAuthenticator::new("secret");
+        let identity = Identity::new("alice").claim("role", "admin");
+        let expired_identity = Identity {
+            expires_at: unix_now() - 1,
+            ..identity.clone()
+        };
+
+        // ------ ACT ------
+        let token = authenticator.issue_token(&identity);
+        let verified_identity = authenticator.verify(&token);
+        let foreign_token = HmacAuthenticator::new("other secret").issue_token(&identity);
+        let expired_token = authenticator.issue_token(&expired_identity);
+
+        // ------ ASSERT ------
+        assert_eq!(verified_identity.as_ref(), Ok(&identity));
+        assert_eq!(
+            verified_identity.unwrap().get_claim::<String>("role"),
+            Some("admin".to_owned())
+        );
+        assert_eq!(
+            authenticator.verify(&foreign_token),
+            Err(AuthError::InvalidSignature)
+        );
+        assert_eq!(
+            authenticator.verify(&expired_token),
+            Err(AuthError::Expired)
+        );
+        assert_eq!(
+            authenticator.verify(&AuthToken::new("invalid")),
+            Err(AuthError::Malformed)
+        );
+    }
+}
@@ -1,10 +1,28 @@
-use crate::from_env_vars::FromEnvVars;
+use crate::from_env_vars::{self, ConfigErrors, FromEnvVars};
 use log::LevelFilter;
 pub use once_cell::sync::Lazy;
 use serde::Deserialize;
-use std::{borrow::Cow, collections::BTreeSet};
+use std::{
+    borrow::Cow,
+    collections::BTreeSet,
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::PathBuf,
+};
 
-pub static CONFIG: Lazy<Config> = Lazy::new(Config::from_env_vars);
+static LOADED_CONFIG: Lazy<Result<Config, ConfigErrors>> = Lazy::new(Config::load);
+
+/// # Panics
+///
+/// Panics on the first access when the config is invalid.
+/// `moon::start` validates the config with `loaded_config` before.
+pub static CONFIG: Lazy<&'static Config> =
+    Lazy::new(|| loaded_config().unwrap_or_else(|errors| panic!("invalid Moon config:\n{errors}")));
+
+/// Returns all errors of the config files and invalid values at once.
+pub fn loaded_config() -> Result<&'static Config, &'static ConfigErrors> {
+    LOADED_CONFIG.as_ref()
+}
 
 #[derive(Debug, Deserialize)]
 #[serde(default)]
@@ -25,12 +43,45 @@ pub struct Config {
     pub frontend_auto_reload: bool,
     // FRONTEND_MULTITHREADING
     pub frontend_multithreading: bool,
+    // UP_MSG_MAX_BYTES
+    pub up_msg_max_bytes: usize,
+    // SHUTDOWN_TIMEOUT (in seconds)
+    pub shutdown_timeout: u64,
 
-    #[serde(default = "Redirect::from_env_vars")]
+    #[serde(default = "Redirect::from_env_vars_or_default")]
     pub redirect: Redirect,
 
-    #[serde(default = "Cors::from_env_vars")]
+    #[serde(default = "Cors::from_env_vars_or_default")]
     pub cors: Cors,
+
+    #[serde(default = "MessageSSE::from_env_vars_or_default")]
+    pub message_sse: MessageSSE,
+
+    #[serde(default = "Storage::from_env_vars_or_default")]
+    pub storage: Storage,
+
+    #[serde(default = "Auth::from_env_vars_or_default")]
+    pub auth: Auth,
+
+    #[serde(default = "RateLimit::from_env_vars_or_default")]
+    pub rate_limit: RateLimit,
+
+    #[serde(default = "Metrics::from_env_vars_or_default")]
+    pub metrics: Metrics,
+
+    #[serde(default = "Csp::from_env_vars_or_default")]
+    pub csp: Csp,
+}
+
+impl Config {
+    fn load() -> Result<Self, ConfigErrors> {
+        let file_errors = from_env_vars::config_file_errors();
+        match Self::try_from_env_vars() {
+            Ok(config) if file_errors.is_empty() => Ok(config),
+            Ok(_) => Err(ConfigErrors(file_errors)),
+            Err(ConfigErrors(errors)) => Err(ConfigErrors([file_errors, errors].concat())),
+        }
+    }
 }
 
 impl FromEnvVars for Config {
@@ -47,8 +98,16 @@ impl Default for Config {
             backend_log_level: LevelFilter::Warn,
             frontend_dist: false,
             frontend_multithreading: false,
+            up_msg_max_bytes: 2 * 1_048_576,
+            shutdown_timeout: 30,
             redirect: Redirect::default(),
             cors: Cors::default(),
+            message_sse: MessageSSE::default(),
+            storage: Storage::default(),
+            auth: Auth::default(),
+            rate_limit: RateLimit::default(),
+            metrics: Metrics::default(),
+            csp: Csp::default(),
             frontend_auto_reload: false,
         }
     }
@@ -96,3 +155,220 @@ impl Default for Cors {
         }
     }
 }
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct MessageSSE {
+    // MESSAGE_SSE_REPLAY_BUFFER_SIZE
+    pub replay_buffer_size: usize,
+    // MESSAGE_SSE_REPLAY_BUFFER_MAX_AGE (in seconds)
+    /// Sessions with lost connections are kept alive for this time, too.
+    pub replay_buffer_max_age: u64,
+}
+
+impl FromEnvVars for MessageSSE {
+    const ENTITY_NAME: &'static str = "MessageSSE";
+    const ENV_PREFIX: &'static str = "MESSAGE_SSE_";
+}
+
+impl Default for MessageSSE {
+    fn default() -> Self {
+        Self {
+            replay_buffer_size: 100,
+            replay_buffer_max_age: 60,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Storage {
+    // STORAGE_BACKEND="memory" / "file"
+    pub backend: StorageBackend,
+    // STORAGE_PATH
+    pub path: PathBuf,
+}
+
+impl FromEnvVars for Storage {
+    const ENTITY_NAME: &'static str = "Storage";
+    const ENV_PREFIX: &'static str = "STORAGE_";
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Self {
+            backend: StorageBackend::default(),
+            path: PathBuf::from("moon_storage.jsonl"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    /// Data are lost on restart.
+    #[default]
+    Memory,
+    /// Data are stored in the file `Storage::path`.
+    File,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Auth {
+    // AUTH_SECRET (enables the built-in `HmacAuthenticator`)
+    pub secret: Option<String>,
+    // AUTH_TOKEN_MAX_AGE (in seconds)
+    pub token_max_age: u64,
+    // AUTH_REQUIRE_FOR_MESSAGE_SSE (applies to the message WebSocket, too)
+    pub require_for_message_sse: bool,
+}
+
+impl FromEnvVars for Auth {
+    const ENTITY_NAME: &'static str = "Auth";
+    const ENV_PREFIX: &'static str = "AUTH_";
+}
+
+impl Default for Auth {
+    fn default() -> Self {
+        Self {
+            secret: None,
+            token_max_age: 24 * 60 * 60,
+            require_for_message_sse: false,
+        }
+    }
+}
+
+// The secret must not be logged with the rest of the config.
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Auth")
+            .field("secret", &self.secret.as_ref().map(|_| "[REDACTED]"))
+            .field("token_max_age", &self.token_max_age)
+            .field("require_for_message_sse", &self.require_for_message_sse)
+            .finish()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RateLimit {
+    // RATE_LIMIT_ENABLED (disabled by default)
+    pub enabled: bool,
+    // RATE_LIMIT_UP_MSGS_PER_SECOND (per session)
+    pub up_msgs_per_second: f64,
+    // RATE_LIMIT_UP_MSG_BURST
+    pub up_msg_burst: u32,
+    // RATE_LIMIT_IP_UP_MSGS_PER_SECOND (per client IP)
+    pub ip_up_msgs_per_second: f64,
+    // RATE_LIMIT_IP_UP_MSG_BURST
+    pub ip_up_msg_burst: u32,
+    // RATE_LIMIT_CONNECTIONS_PER_MINUTE (message SSE / WebSocket, per session and per client IP)
+    pub connections_per_minute: f64,
+    // RATE_LIMIT_CONNECTION_BURST
+    pub connection_burst: u32,
+    // RATE_LIMIT_MAX_SESSIONS (0 = unlimited)
+    pub max_sessions: usize,
+    // RATE_LIMIT_TRUST_PROXY (read the client IP from `Forwarded` / `X-Forwarded-For`)
+    pub trust_proxy: bool,
+}
+
+impl FromEnvVars for RateLimit {
+    const ENTITY_NAME: &'static str = "RateLimit";
+    const ENV_PREFIX: &'static str = "RATE_LIMIT_";
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            up_msgs_per_second: 20.,
+            up_msg_burst: 50,
+            ip_up_msgs_per_second: 100.,
+            ip_up_msg_burst: 200,
+            connections_per_minute: 30.,
+            connection_burst: 10,
+            max_sessions: 10_000,
+            trust_proxy: false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Metrics {
+    // METRICS_ENABLED (`/_api/metrics`)
+    pub enabled: bool,
+    // METRICS_TOKEN (required as `Authorization: Bearer <token>` when set)
+    pub token: Option<String>,
+    // METRICS_ALLOWED_IPS="127.0.0.1,::1" (default; empty = all IPs)
+    pub allowed_ips: BTreeSet<IpAddr>,
+}
+
+impl FromEnvVars for Metrics {
+    const ENTITY_NAME: &'static str = "Metrics";
+    const ENV_PREFIX: &'static str = "METRICS_";
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            token: None,
+            // only local scrapers by default
+            allowed_ips: BTreeSet::from([
+                IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(Ipv6Addr::LOCALHOST),
+            ]),
+        }
+    }
+}
+
+// The token must not be logged with the rest of the config.
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics")
+            .field("enabled", &self.enabled)
+            .field("token", &self.token.as_ref().map(|_| "[REDACTED]"))
+            .field("allowed_ips", &self.allowed_ips)
+            .finish()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Csp {
+    // CSP_ENABLED (sets `Content-Security-Policy` on pages rendered by `Frontend`)
+    pub enabled: bool,
+    // CSP_POLICY (`{nonce}` is replaced with the nonce generated for each response)
+    pub policy: String,
+    // CSP_REPORT_ONLY (sets `Content-Security-Policy-Report-Only` instead)
+    pub report_only: bool,
+    // CSP_EXTERNAL_SCRIPTS (serve bootstrap scripts from `/_api/bootstrap` instead of inlining them)
+    pub external_scripts: bool,
+}
+
+impl FromEnvVars for Csp {
+    const ENTITY_NAME: &'static str = "Csp";
+    const ENV_PREFIX: &'static str = "CSP_";
+}
+
+impl Default for Csp {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            policy: [
+                "default-src 'self'",
+                "script-src 'self' 'nonce-{nonce}' 'wasm-unsafe-eval'",
+                "style-src 'self' 'nonce-{nonce}'",
+                "img-src 'self' data:",
+                "object-src 'none'",
+                "base-uri 'self'",
+                "frame-ancestors 'self'",
+            ]
+            .join("; "),
+            report_only: false,
+            external_scripts: false,
+        }
+    }
+}
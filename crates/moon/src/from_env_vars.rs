@@ -1,5 +1,21 @@
+use config_source::ConfigVar;
 use serde::Deserialize;
+use std::{cell::RefCell, collections::BTreeMap, error::Error, fmt};
+use var_deserializer::VarsDeserializer;
 
+mod config_source;
+mod var_deserializer;
+
+pub use config_source::config_profile;
+
+thread_local! {
+    /// Errors of nested entities collected by the parent's `try_from_env_vars`.
+    static NESTED_ERRORS: RefCell<Vec<Vec<ConfigError>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Loads the entity from env vars overlaid on values from `MoonZoon.toml` and `MoonZoonCustom.toml`.
+///
+/// Nested tables are flattened to env var names, e.g. `[postgres] host = "localhost"` to `POSTGRES_HOST`.
 pub trait FromEnvVars
 where
     for<'de> Self: Deserialize<'de>,
@@ -7,15 +23,183 @@ where
     const ENTITY_NAME: &'static str;
     const ENV_PREFIX: &'static str = "";
 
+    /// # Panics
+    ///
+    /// Panics with all errors when the entity can't be loaded.
     fn from_env_vars() -> Self {
-        envy::prefixed(Self::ENV_PREFIX)
-            .from_env()
-            .unwrap_or_else(|error| {
-                panic!(
-                    "cannot load {} from env variables: {}",
-                    Self::ENTITY_NAME,
-                    error
-                )
-            })
+        Self::try_from_env_vars()
+            .unwrap_or_else(|errors| panic!("cannot load {}:\n{errors}", Self::ENTITY_NAME))
+    }
+
+    /// Use it in `#[serde(default = "..")]` for nested entities.
+    /// Their errors are reported by the parent's `try_from_env_vars`.
+    fn from_env_vars_or_default() -> Self
+    where
+        Self: Default,
+    {
+        Self::try_from_env_vars().unwrap_or_else(|errors| {
+            if let Err(errors) = report_to_parent(errors) {
+                panic!("cannot load {}:\n{errors}", Self::ENTITY_NAME)
+            }
+            Self::default()
+        })
+    }
+
+    /// Returns all invalid and missing values at once.
+    fn try_from_env_vars() -> Result<Self, ConfigErrors> {
+        let vars = config_source::vars_with_prefix(Self::ENV_PREFIX);
+        deserialize_vars(Self::ENTITY_NAME, Self::ENV_PREFIX, vars).map_err(ConfigErrors)
+    }
+}
+
+/// Fails when the entity isn't nested in another one loaded by `try_from_env_vars`.
+fn report_to_parent(errors: ConfigErrors) -> Result<(), ConfigErrors> {
+    NESTED_ERRORS.with(
+        |nested_errors| match nested_errors.borrow_mut().last_mut() {
+            Some(parent_errors) => {
+                parent_errors.extend(errors.0);
+                Ok(())
+            }
+            None => Err(errors),
+        },
+    )
+}
+
+/// Deserializes the entity again without the invalid var until all errors are found.
+fn deserialize_vars<T: for<'de> Deserialize<'de>>(
+    entity_name: &str,
+    prefix: &str,
+    mut vars: BTreeMap<String, ConfigVar>,
+) -> Result<T, Vec<ConfigError>> {
+    let mut errors = Vec::new();
+    loop {
+        NESTED_ERRORS.with(|nested_errors| nested_errors.borrow_mut().push(Vec::new()));
+        let result = T::deserialize(VarsDeserializer::new(prefix, &vars));
+        let nested_errors = NESTED_ERRORS
+            .with(|nested_errors| nested_errors.borrow_mut().pop())
+            .unwrap_or_default();
+
+        let error = match result {
+            Ok(entity) if errors.is_empty() && nested_errors.is_empty() => return Ok(entity),
+            Ok(_) => {
+                errors.extend(nested_errors);
+                return Err(errors);
+            }
+            Err(error) => error,
+        };
+        let var_name = error.var_name(prefix);
+        // The invalid var is removed so its default value is used in the next attempt.
+        match var_name.as_ref().and_then(|var_name| vars.remove(var_name)) {
+            Some(var) => errors.push(ConfigError {
+                location: var.location,
+                message: error.message,
+            }),
+            None => {
+                let location = match var_name {
+                    Some(var_name) => format!("env var {var_name}"),
+                    None => entity_name.to_owned(),
+                };
+                errors.push(ConfigError {
+                    location,
+                    message: error.message,
+                });
+                errors.extend(nested_errors);
+                return Err(errors);
+            }
+        }
+    }
+}
+
+/// Returns errors of reading and parsing `MoonZoon.toml` and `MoonZoonCustom.toml`.
+pub(crate) fn config_file_errors() -> Vec<ConfigError> {
+    config_source::file_errors()
+}
+
+// ------ ConfigError ------
+
+#[derive(Debug, Clone)]
+pub struct ConfigError {
+    /// The env var or the config file key, e.g. `MoonZoon.toml: redirect.port (REDIRECT_PORT)`.
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+impl Error for ConfigError {}
+
+// ------ ConfigErrors ------
+
+#[derive(Debug, Clone, Default)]
+pub struct ConfigErrors(pub Vec<ConfigError>);
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for error in &self.0 {
+            writeln!(f, "  - {error}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ConfigErrors {}
+
+// ====== ====== TESTS ====== ======
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Redirect {
+        #[serde(default)]
+        port: u16,
+        #[serde(default)]
+        enabled: bool,
+        #[serde(default)]
+        hosts: Vec<String>,
+    }
+
+    fn var(name: &str, value: &str) -> (String, ConfigVar) {
+        let var = ConfigVar {
+            value: value.to_owned(),
+            location: format!("env var {name}"),
+        };
+        (name.to_owned(), var)
+    }
+
+    #[test]
+    fn test_deserialize_vars_collects_all_errors() {
+        // ------ ARRANGE ------
+        let valid_vars = BTreeMap::from([
+            var("REDIRECT_PORT", "8081"),
+            var("REDIRECT_HOSTS", "a.com, b.com"),
+        ]);
+        let invalid_vars =
+            BTreeMap::from([var("REDIRECT_PORT", "http"), var("REDIRECT_ENABLED", "yes")]);
+
+        // ------ ACT ------
+        let redirect = deserialize_vars::<Redirect>("Redirect", "REDIRECT_", valid_vars);
+        let errors = deserialize_vars::<Redirect>("Redirect", "REDIRECT_", invalid_vars);
+
+        // ------ ASSERT ------
+        let redirect = redirect.unwrap();
+        assert_eq!(redirect.port, 8081);
+        assert!(!redirect.enabled);
+        assert_eq!(redirect.hosts, ["a.com", "b.com"]);
+
+        let locations = errors
+            .unwrap_err()
+            .into_iter()
+            .map(|error| error.location)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            locations,
+            ["env var REDIRECT_ENABLED", "env var REDIRECT_PORT"]
+        );
     }
 }
@@ -0,0 +1,225 @@
+use super::ConfigError;
+use once_cell::sync::Lazy;
+use std::{collections::BTreeMap, env, fs, io, path::Path};
+
+/// The env var with the path to `MoonZoon.toml`.
+const CONFIG_FILE_PATH_VAR: &str = "MOON_CONFIG_FILE";
+/// The env var with the path to `MoonZoonCustom.toml`.
+const CUSTOM_CONFIG_FILE_PATH_VAR: &str = "MOON_CUSTOM_CONFIG_FILE";
+/// The env var with the name of the profile applied from config files, e.g. `release`.
+const PROFILE_VAR: &str = "MOON_PROFILE";
+
+static CONFIG_SOURCE: Lazy<ConfigSource> = Lazy::new(ConfigSource::load);
+
+/// `MOON_PROFILE` or `dev` / `release` according to the backend build.
+pub fn config_profile() -> String {
+    env::var(PROFILE_VAR).unwrap_or_else(|_| {
+        if cfg!(debug_assertions) {
+            "dev".to_owned()
+        } else {
+            "release".to_owned()
+        }
+    })
+}
+
+pub(super) fn vars_with_prefix(prefix: &str) -> BTreeMap<String, ConfigVar> {
+    CONFIG_SOURCE
+        .vars
+        .range(prefix.to_owned()..)
+        .take_while(|(name, _)| name.starts_with(prefix))
+        .map(|(name, var)| (name.clone(), var.clone()))
+        .collect()
+}
+
+pub(super) fn file_errors() -> Vec<ConfigError> {
+    CONFIG_SOURCE.file_errors.clone()
+}
+
+// ------ ConfigVar ------
+
+#[derive(Debug, Clone)]
+pub(super) struct ConfigVar {
+    pub(super) value: String,
+    pub(super) location: String,
+}
+
+// ------ ConfigSource ------
+
+struct ConfigSource {
+    vars: BTreeMap<String, ConfigVar>,
+    file_errors: Vec<ConfigError>,
+}
+
+impl ConfigSource {
+    /// Env vars override values from config files.
+    fn load() -> Self {
+        let mut this = Self {
+            vars: BTreeMap::new(),
+            file_errors: Vec::new(),
+        };
+        let profile = config_profile();
+        for (path_var, default_path) in [
+            (CONFIG_FILE_PATH_VAR, "MoonZoon.toml"),
+            (CUSTOM_CONFIG_FILE_PATH_VAR, "MoonZoonCustom.toml"),
+        ] {
+            let path = env::var(path_var).unwrap_or_else(|_| default_path.to_owned());
+            this.add_file(Path::new(&path), &profile);
+        }
+        // vars with non-Unicode names or values are skipped
+        let env_vars = env::vars_os().filter_map(|(name, value)| {
+            Some((name.into_string().ok()?, value.into_string().ok()?))
+        });
+        for (name, value) in env_vars {
+            let location = format!("env var {name}");
+            this.vars.insert(name, ConfigVar { value, location });
+        }
+        this
+    }
+
+    fn add_file(&mut self, path: &Path, profile: &str) {
+        let file_name = path.display().to_string();
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            // config files are optional
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return,
+            Err(error) => {
+                return self.file_errors.push(ConfigError {
+                    location: file_name,
+                    message: error.to_string(),
+                })
+            }
+        };
+        let table = match content.parse::<toml::Table>() {
+            Ok(table) => table,
+            Err(error) => {
+                return self.file_errors.push(ConfigError {
+                    location: file_name,
+                    message: error.to_string(),
+                })
+            }
+        };
+        self.add_table(&file_name, apply_profile(table, profile), "", "");
+    }
+
+    fn add_table(
+        &mut self,
+        file_name: &str,
+        table: toml::Table,
+        var_prefix: &str,
+        key_prefix: &str,
+    ) {
+        for (key, value) in table {
+            let var_name = format!("{var_prefix}{}", key.to_ascii_uppercase());
+            let key_path = format!("{key_prefix}{key}");
+            if let toml::Value::Table(table) = value {
+                self.add_table(
+                    file_name,
+                    table,
+                    &format!("{var_name}_"),
+                    &format!("{key_path}."),
+                );
+                continue;
+            }
+            let location = format!("{file_name}: {key_path} ({var_name})");
+            match value_into_string(value) {
+                Ok(value) => {
+                    self.vars.insert(var_name, ConfigVar { value, location });
+                }
+                Err(message) => self.file_errors.push(ConfigError { location, message }),
+            }
+        }
+    }
+}
+
+/// Merges `[profile.<profile>]` into the root table.
+fn apply_profile(mut table: toml::Table, profile: &str) -> toml::Table {
+    let profile_table = match table.remove("profile") {
+        Some(toml::Value::Table(mut profiles)) => profiles.remove(profile),
+        _ => None,
+    };
+    if let Some(toml::Value::Table(profile_table)) = profile_table {
+        merge_tables(&mut table, profile_table);
+    }
+    table
+}
+
+fn merge_tables(table: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(table)), toml::Value::Table(overlay)) => {
+                merge_tables(table, overlay)
+            }
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Arrays are joined with commas like in env vars.
+fn value_into_string(value: toml::Value) -> Result<String, String> {
+    let value = match value {
+        toml::Value::Table(_) => Err("tables in arrays are not supported".to_owned())?,
+        toml::Value::Boolean(value) => value.to_string(),
+        toml::Value::Float(value) => value.to_string(),
+        toml::Value::Integer(value) => value.to_string(),
+        toml::Value::String(value) => value,
+        toml::Value::Datetime(value) => value.to_string(),
+        toml::Value::Array(values) => values
+            .into_iter()
+            .map(value_into_string)
+            .collect::<Result<Vec<_>, _>>()?
+            .join(","),
+    };
+    Ok(value)
+}
+
+// ====== ====== TESTS ====== ======
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_overrides_nested_values() {
+        // ------ ARRANGE ------
+        let table = r#"
+            port = 8080
+            [redirect]
+            port = 8081
+            enabled = false
+            [profile.release]
+            port = 443
+            [profile.release.redirect]
+            enabled = true
+        "#
+        .parse::<toml::Table>()
+        .unwrap();
+        let mut source = ConfigSource {
+            vars: BTreeMap::new(),
+            file_errors: Vec::new(),
+        };
+
+        // ------ ACT ------
+        source.add_table("MoonZoon.toml", apply_profile(table, "release"), "", "");
+
+        // ------ ASSERT ------
+        let values = source
+            .vars
+            .iter()
+            .map(|(name, var)| (name.as_str(), var.value.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            [
+                ("PORT", "443"),
+                ("REDIRECT_ENABLED", "true"),
+                ("REDIRECT_PORT", "8081")
+            ]
+        );
+        assert_eq!(
+            source.vars["REDIRECT_PORT"].location,
+            "MoonZoon.toml: redirect.port (REDIRECT_PORT)"
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use super::config_source::ConfigVar;
+use serde::de::{
+    self,
+    value::{MapDeserializer, SeqDeserializer},
+    Deserializer, IntoDeserializer, Visitor,
+};
+use std::{collections::BTreeMap, error::Error, fmt};
+
+// ------ VarsDeserializer ------
+
+/// Deserializes a struct from vars.
+/// Field names are var names without `prefix` in lowercase.
+pub(super) struct VarsDeserializer<'a> {
+    fields: BTreeMap<String, &'a str>,
+}
+
+impl<'a> VarsDeserializer<'a> {
+    pub(super) fn new(prefix: &str, vars: &'a BTreeMap<String, ConfigVar>) -> Self {
+        let fields = vars
+            .iter()
+            .filter_map(|(name, var)| {
+                let field = name.strip_prefix(prefix)?.to_ascii_lowercase();
+                Some((field, var.value.as_str()))
+            })
+            .collect();
+        Self { fields }
+    }
+}
+
+impl<'de, 'a> Deserializer<'de> for VarsDeserializer<'a> {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        let fields = self.fields.into_iter().map(|(field, value)| {
+            let var_deserializer = VarDeserializer {
+                field: field.clone(),
+                value,
+            };
+            (field, var_deserializer)
+        });
+        visitor.visit_map(MapDeserializer::new(fields))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+// ------ VarDeserializer ------
+
+/// Deserializes a field from the var value.
+/// Sequences are comma-separated values.
+struct VarDeserializer<'a> {
+    field: String,
+    value: &'a str,
+}
+
+impl<'a> VarDeserializer<'a> {
+    fn attach_field(&self, mut error: DeError) -> DeError {
+        error.field.get_or_insert_with(|| self.field.clone());
+        error
+    }
+
+    fn invalid_value(&self, error: impl fmt::Display) -> DeError {
+        DeError {
+            field: Some(self.field.clone()),
+            message: format!("invalid value `{}`: {error}", self.value),
+        }
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident),* $(,)?) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+            let value = self
+                .value
+                .trim()
+                .parse()
+                .map_err(|error| self.invalid_value(error))?;
+            visitor
+                .$visit(value)
+                .map_err(|error| self.attach_field(error))
+        }
+    )*};
+}
+
+impl<'de, 'a> Deserializer<'de> for VarDeserializer<'a> {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor
+            .visit_str(self.value)
+            .map_err(|error| self.attach_field(error))
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        let items = self
+            .value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| VarDeserializer {
+                field: self.field.clone(),
+                value: item,
+            });
+        visitor
+            .visit_seq(SeqDeserializer::new(items))
+            .map_err(|error| self.attach_field(error))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        visitor
+            .visit_enum(self.value.trim().into_deserializer())
+            .map_err(|error| self.attach_field(error))
+    }
+
+    serde::forward_to_deserialize_any! {
+        char str string bytes byte_buf unit unit_struct tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'de, 'a> IntoDeserializer<'de, DeError> for VarDeserializer<'a> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+// ------ DeError ------
+
+#[derive(Debug)]
+pub(super) struct DeError {
+    /// The field name is the var name without the prefix in lowercase.
+    pub(super) field: Option<String>,
+    pub(super) message: String,
+}
+
+impl DeError {
+    pub(super) fn var_name(&self, prefix: &str) -> Option<String> {
+        let field = self.field.as_ref()?;
+        Some(format!("{prefix}{}", field.to_ascii_uppercase()))
+    }
+}
+
+impl de::Error for DeError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        Self {
+            field: None,
+            message: message.to_string(),
+        }
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        Self {
+            field: Some(field.to_owned()),
+            message: "missing value".to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for DeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for DeError {}
@@ -1,12 +1,77 @@
-use crate::CONFIG;
+use crate::{parse_cookie, CONFIG};
+use actix_http::header::{self, HeaderMap};
 use lang::Lang;
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashMap};
 use tokio::fs;
 
+const RECONNECTING_EVENT_SOURCE_JS_CODE: &str =
+    include_str!("../js/ReconnectingEventSource.min.js");
+const SSE_AUTO_RELOAD_JS_CODE: &str = include_str!("../js/sse_auto_reload.js");
+
+/// The id of the element with the public assets manifest read by Zoon.
+const PUBLIC_ASSETS_MANIFEST_ID: &str = "moon_public_assets";
+
+/// The attribute marks the element with the markup rendered by Moon.
+/// Zoon replaces the markup when the app starts.
+const PRE_RENDERED_ATTRIBUTE: &str = "data-pre-rendered";
+
+// ------ FrontendRequest ------
+
+/// Passed to the `frontend` builder to render route-specific pages.
+#[derive(Debug, Clone)]
+pub struct FrontendRequest {
+    pub(crate) path: String,
+    pub(crate) query: HashMap<String, String>,
+    pub(crate) headers: HeaderMap,
+    pub(crate) csp_nonce: Option<String>,
+}
+
+impl FrontendRequest {
+    /// The requested URL path, e.g. `/articles/42`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Decoded query parameters. Empty when the query string is invalid.
+    pub fn query(&self) -> &HashMap<String, String> {
+        &self.query
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn header(&self, name: impl header::AsHeaderName) -> Option<&str> {
+        self.headers.get(name)?.to_str().ok()
+    }
+
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        parse_cookie(&self.headers, name)
+    }
+
+    /// The nonce generated for this response when `CSP_ENABLED`.
+    /// Moon adds it to elements rendered by `Frontend`; add it to your inline elements, too.
+    pub fn csp_nonce(&self) -> Option<&str> {
+        self.csp_nonce.as_deref()
+    }
+
+    /// The language from `supported` preferred in the `Accept-Language` header.
+    /// Returns `None` when the header is missing or it doesn't match any supported language.
+    pub fn preferred_lang(&self, supported: &[Lang]) -> Option<Lang> {
+        Lang::negotiate(self.header(header::ACCEPT_LANGUAGE)?, supported)
+    }
+}
+
+// ------ Frontend ------
+
 pub struct Frontend {
     pub(crate) lang: Option<Lang>,
     pub(crate) index_by_robots: bool,
     pub(crate) title: Cow<'static, str>,
+    pub(crate) description: Option<Cow<'static, str>>,
+    pub(crate) canonical_url: Option<Cow<'static, str>>,
+    pub(crate) nonce: Option<Cow<'static, str>>,
+    pub(crate) open_graph: Vec<(Cow<'static, str>, Cow<'static, str>)>,
     pub(crate) default_styles: bool,
     pub(crate) append_to_head: String,
     pub(crate) body_content: Cow<'static, str>,
@@ -18,6 +83,10 @@ impl Default for Frontend {
             lang: None,
             index_by_robots: true,
             title: Cow::from("MoonZoon app"),
+            description: None,
+            canonical_url: None,
+            nonce: None,
+            open_graph: Vec::new(),
             default_styles: true,
             append_to_head: String::new(),
             body_content: Cow::from(r#"<section id="app"></section>"#),
@@ -34,6 +103,32 @@ impl Frontend {
             .unwrap_or_default()
     }
 
+    /// The manifest created by `mzoon build` when `public_assets.fingerprint` is enabled.
+    async fn public_assets_manifest() -> Option<String> {
+        fs::read_to_string("frontend/pkg/public_manifest.json")
+            .await
+            .ok()
+    }
+
+    async fn cache_busting_string() -> Cow<'static, str> {
+        if CONFIG.cache_busting {
+            Cow::from(format!("_{}", Self::build_id().await))
+        } else {
+            Cow::from("")
+        }
+    }
+
+    /// Scripts served by `/_api/bootstrap/{file}` when `CSP_EXTERNAL_SCRIPTS` is enabled.
+    pub(crate) async fn bootstrap_js_code(file: &str) -> Option<Cow<'static, str>> {
+        let js_code = match file {
+            "reconnecting_event_source.js" => Cow::from(RECONNECTING_EVENT_SOURCE_JS_CODE),
+            "sse_auto_reload.js" => Cow::from(SSE_AUTO_RELOAD_JS_CODE),
+            "start_app.js" => Cow::from(start_app_js_code(&Self::cache_busting_string().await)),
+            _ => return None,
+        };
+        Some(js_code)
+    }
+
     pub fn new() -> Self {
         Self::default()
     }
@@ -53,6 +148,38 @@ impl Frontend {
         self
     }
 
+    /// Sets `<meta name="description">`.
+    pub fn description(mut self, description: impl Into<Cow<'static, str>>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Sets `<link rel="canonical">`.
+    pub fn canonical_url(mut self, url: impl Into<Cow<'static, str>>) -> Self {
+        self.canonical_url = Some(url.into());
+        self
+    }
+
+    /// The nonce is added to all `<script>` and `<style>` elements rendered by `Frontend`.
+    /// Add it to your elements in `append_to_head` and `body_content`, too.
+    ///
+    /// It's replaced with `FrontendRequest::csp_nonce` when `CSP_ENABLED`.
+    pub fn nonce(mut self, nonce: impl Into<Cow<'static, str>>) -> Self {
+        self.nonce = Some(nonce.into());
+        self
+    }
+
+    /// Adds the Open Graph tag `<meta property="og:{property}">`,
+    /// e.g. `.open_graph("image", "https://example.com/cover.png")`.
+    pub fn open_graph(
+        mut self,
+        property: impl Into<Cow<'static, str>>,
+        content: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        self.open_graph.push((property.into(), content.into()));
+        self
+    }
+
     pub fn default_styles(mut self, enabled: bool) -> Self {
         self.default_styles = enabled;
         self
@@ -68,21 +195,43 @@ impl Frontend {
         self
     }
 
+    /// Renders `markup` into `<section id="app">` so the page has content before the Wasm loads.
+    /// Zoon replaces it with the app elements in `start_app("app", ..)`.
+    ///
+    /// Use `data-pre-rendered` on your element in `body_content` to pre-render a custom root.
+    pub fn pre_rendered_body(mut self, markup: impl AsRef<str>) -> Self {
+        self.body_content = Cow::from(format!(
+            r#"<section id="app" {PRE_RENDERED_ATTRIBUTE}>{}</section>"#,
+            markup.as_ref()
+        ));
+        self
+    }
+
     pub async fn into_html(self) -> String {
         let Frontend {
             lang,
             index_by_robots,
             title,
+            description,
+            canonical_url,
+            nonce,
+            open_graph,
             default_styles,
             append_to_head,
             body_content,
         } = self;
 
-        let cache_busting_string = if CONFIG.cache_busting {
-            Cow::from(format!("_{}", Self::build_id().await))
-        } else {
-            Cow::from("")
-        };
+        let cache_busting_string = Self::cache_busting_string().await;
+
+        // Zoon's `public_url` resolves fingerprinted assets with the manifest
+        let public_assets_manifest = Self::public_assets_manifest()
+            .await
+            .map(|manifest| {
+                // the JSON must not end the script element
+                let manifest = manifest.replace("</", "<\\/");
+                format!(r#"<script type="application/json" id="{PUBLIC_ASSETS_MANIFEST_ID}">{manifest}</script>"#)
+            })
+            .unwrap_or_default();
 
         let meta_robots = if index_by_robots {
             ""
@@ -90,33 +239,66 @@ impl Frontend {
             r#"<meta name="robots" content="noindex">"#
         };
 
+        let title = escape_html(&title);
+
+        let mut meta_tags = String::new();
+        if let Some(description) = description {
+            let description = escape_html(&description);
+            meta_tags.push_str(&format!(
+                r#"<meta name="description" content="{description}">"#
+            ));
+        }
+        if let Some(canonical_url) = canonical_url {
+            let canonical_url = escape_html(&canonical_url);
+            meta_tags.push_str(&format!(r#"<link rel="canonical" href="{canonical_url}">"#));
+        }
+        for (property, content) in open_graph {
+            let (property, content) = (escape_html(&property), escape_html(&content));
+            meta_tags.push_str(&format!(
+                r#"<meta property="og:{property}" content="{content}">"#
+            ));
+        }
+
+        let nonce_attribute = nonce
+            .map(|nonce| format!(r#" nonce="{}""#, escape_html(&nonce)))
+            .unwrap_or_default();
+
         let default_styles = if default_styles {
-            concat!(
-                "<style>",
-                include_str!("../css/modern-normalize.min.css"),
-                "</style>",
-                "<style>",
-                include_str!("../css/basic.css"),
-                "</style>"
-            )
+            let modern_normalize_css = include_str!("../css/modern-normalize.min.css");
+            let basic_css = include_str!("../css/basic.css");
+            Cow::from(format!(
+                "<style{nonce_attribute}>{modern_normalize_css}</style>\
+                <style{nonce_attribute}>{basic_css}</style>"
+            ))
         } else {
-            ""
+            Cow::from("")
         };
 
         let scripts = if CONFIG.frontend_dist {
             String::new()
+        } else if CONFIG.csp.external_scripts {
+            let sse_script = if CONFIG.frontend_auto_reload {
+                format!(
+                    r#"<script src="/_api/bootstrap/sse_auto_reload.js"{nonce_attribute}></script>"#
+                )
+            } else {
+                String::new()
+            };
+            format!(
+                r#"<script src="/_api/bootstrap/reconnecting_event_source.js"{nonce_attribute}></script>
+                {sse_script}"#
+            )
         } else {
-            let reconnecting_event_source_js_code =
-                include_str!("../js/ReconnectingEventSource.min.js");
+            let reconnecting_event_source_js_code = RECONNECTING_EVENT_SOURCE_JS_CODE;
 
             let sse_js_code = if CONFIG.frontend_auto_reload {
-                include_str!("../js/sse_auto_reload.js")
+                SSE_AUTO_RELOAD_JS_CODE
             } else {
                 ""
             };
 
             format!(
-                r#"<script type="text/javascript">
+                r#"<script type="text/javascript"{nonce_attribute}>
                   {reconnecting_event_source_js_code}
                   {sse_js_code}
                 </script>"#
@@ -129,21 +311,25 @@ impl Frontend {
             Cow::from("<html>")
         };
 
-        let start_main_wasm_script = if CONFIG.frontend_multithreading {
-            // @TODO Add object with `module_or_path` like in the `else` below to resolve warning in dev console?
-            format!(
-                r#"<script src="/_api/pkg/frontend{cache_busting_string}.js"></script>
-               <script>
-                 wasm_bindgen("/_api/pkg/frontend_bg{cache_busting_string}.wasm");
-               </script>"#
-            )
-        } else {
-            format!(
-                r#"<script type="module">
-                  import init from '/_api/pkg/frontend{cache_busting_string}.js';
-                  init({{ module_or_path: '/_api/pkg/frontend_bg{cache_busting_string}.wasm' }});
-                </script>"#
-            )
+        let start_app_js_code = start_app_js_code(&cache_busting_string);
+        let start_main_wasm_script = match (
+            CONFIG.frontend_multithreading,
+            CONFIG.csp.external_scripts,
+        ) {
+            (true, false) => format!(
+                r#"<script src="/_api/pkg/frontend{cache_busting_string}.js"{nonce_attribute}></script>
+               <script{nonce_attribute}>{start_app_js_code}</script>"#
+            ),
+            (true, true) => format!(
+                r#"<script src="/_api/pkg/frontend{cache_busting_string}.js"{nonce_attribute}></script>
+               <script src="/_api/bootstrap/start_app.js"{nonce_attribute}></script>"#
+            ),
+            (false, false) => {
+                format!(r#"<script type="module"{nonce_attribute}>{start_app_js_code}</script>"#)
+            }
+            (false, true) => format!(
+                r#"<script type="module" src="/_api/bootstrap/start_app.js"{nonce_attribute}></script>"#
+            ),
         };
 
         format!(
@@ -155,8 +341,10 @@ impl Frontend {
           <meta name="viewport" content="width=device-width, initial-scale=1, shrink-to-fit=no" />
           {meta_robots}
           <title>{title}</title>
+          {meta_tags}
           <link rel="preload" href="/_api/pkg/frontend_bg{cache_busting_string}.wasm" as="fetch" type="application/wasm" crossorigin>
           <link rel="modulepreload" href="/_api/pkg/frontend{cache_busting_string}.js" crossorigin>
+          {public_assets_manifest}
           {default_styles}
           {append_to_head}
         </head>
@@ -173,3 +361,33 @@ impl Frontend {
         )
     }
 }
+
+fn start_app_js_code(cache_busting_string: &str) -> String {
+    if CONFIG.frontend_multithreading {
+        // @TODO Add object with `module_or_path` like in the `else` below to resolve warning in dev console?
+        format!(r#"wasm_bindgen("/_api/pkg/frontend_bg{cache_busting_string}.wasm");"#)
+    } else {
+        format!(
+            r#"import init from '/_api/pkg/frontend{cache_busting_string}.js';
+            init({{ module_or_path: '/_api/pkg/frontend_bg{cache_busting_string}.wasm' }});"#
+        )
+    }
+}
+
+fn escape_html(text: &str) -> Cow<'_, str> {
+    if !text.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::from(text);
+    }
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            character => escaped.push(character),
+        }
+    }
+    Cow::from(escaped)
+}
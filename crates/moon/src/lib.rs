@@ -7,28 +7,33 @@ use actix_http::{
 use actix_web::http::header::{CacheControl, CacheDirective, ContentType, ETag, EntityTag};
 use actix_web::{
     body::MessageBody,
-    dev::{ServiceFactory, ServiceRequest, ServiceResponse},
+    dev::{ServerHandle, ServiceFactory, ServiceRequest, ServiceResponse},
     error::{self, Error},
     http::StatusCode,
     middleware::{Compat, Condition, ErrorHandlers, Logger},
-    web, App, HttpRequest, HttpResponse, HttpServer, Responder, Result,
+    rt, web, App, HttpRequest, HttpResponse, HttpServer, Responder, Result,
 };
+use base64::{engine::general_purpose::STANDARD, Engine};
 use cargo_metadata::MetadataCommand;
 use rustls::{Certificate, PrivateKey, ServerConfig as RustlsServerConfig};
 use rustls_pemfile::{certs, pkcs8_private_keys};
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, HashMap};
 use std::fs::File;
 use std::io::{self, BufReader};
 use std::net::SocketAddr;
 use std::ops::Deref;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{self, Instant};
 use tokio::fs;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::time::interval;
 
 pub use actix_cors;
 pub use actix_files;
 pub use actix_http;
 pub use actix_web;
+pub use actix_ws;
 pub use apply::{Also, Apply};
 pub use async_trait::async_trait;
 pub use chashmap;
@@ -51,33 +56,46 @@ pub use trait_set::trait_set;
 pub use uuid;
 
 mod actor;
+pub mod auth;
 pub mod config;
 pub mod error_handler;
 mod from_env_vars;
 mod frontend;
 mod lazy_message_writer;
+mod metrics;
 mod not;
+mod rate_limiter;
+mod readiness;
 mod redirect;
+mod shutdown;
 mod sse;
+mod up_msg_handler_result;
 mod up_msg_request;
 
+use auth::AuthError;
 use config::CONFIG;
 use lazy_message_writer::LazyMessageWriter;
+use rate_limiter::{client_ip, RateLimiters};
 use sse::{ShareableSSE, ShareableSSEMethods, SSE};
 
+use actor::sessions::DownMsgChannel;
+
 pub use actor::{
     sessions::{self, SessionActor},
-    ActorId, ActorInstance, Index, PVar,
+    storage::{self, FileStorage, MemoryStorage, Storage},
+    ActorId, ActorInstance, ActorRef, Index, PVar, WaitForError,
 };
-pub use from_env_vars::FromEnvVars;
-pub use frontend::Frontend;
+pub use auth::Identity;
+pub use from_env_vars::{config_profile, ConfigError, ConfigErrors, FromEnvVars};
+pub use frontend::{Frontend, FrontendRequest};
 pub use not::not;
+pub use readiness::add_readiness_check;
 pub use redirect::Redirect;
+pub use shutdown::{is_shutting_down, on_shutdown};
+pub use up_msg_handler_result::UpMsgHandlerResult;
 pub use up_msg_request::UpMsgRequest;
 
-// @TODO make it configurable
-// const MAX_UP_MSG_BYTES: usize = 2 * 1_048_576;
-const MAX_UP_MSG_BYTES: usize = usize::MAX;
+const WEB_SOCKET_HEARTBEAT_INTERVAL: time::Duration = time::Duration::from_secs(10);
 
 #[derive(Copy, Clone)]
 struct SharedData {
@@ -88,14 +106,17 @@ struct SharedData {
     pkg_path: &'static str,
 }
 
-#[derive(Clone)]
-struct ReloadSSE(ShareableSSE);
+struct ReloadSSE {
+    sse: ShareableSSE,
+    /// The latest build errors sent by `mzoon start` as a JSON array.
+    build_errors: parking_lot::Mutex<String>,
+}
 
 impl Deref for ReloadSSE {
     type Target = ShareableSSE;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.sse
     }
 }
 
@@ -113,23 +134,51 @@ impl Deref for MessageSSE {
 // trait aliases
 trait_set! {
     pub trait FrontBuilderOutput = Future<Output = Frontend> + 'static;
-    pub trait FrontBuilder<FRBO: FrontBuilderOutput> = Fn() -> FRBO + Send + Sync + 'static;
 
-    pub trait UpHandlerOutput = Future<Output = ()> + 'static;
+    pub trait UpHandlerOutput = Future<Output: UpMsgHandlerResult> + 'static;
     pub trait UpHandler<UPHO: UpHandlerOutput, UMsg> = Fn(UpMsgRequest<UMsg>) -> UPHO + Send + Sync + 'static;
 }
 
+/// Implemented for `Fn() -> impl Future<Output = Frontend>` and
+/// `Fn(FrontendRequest) -> impl Future<Output = Frontend>`.
+///
+/// `FRBA` is inferred from the builder's arguments.
+pub trait FrontBuilder<FRBA, FRBO: FrontBuilderOutput>: Send + Sync + 'static {
+    fn build(&self, request: FrontendRequest) -> FRBO;
+}
+
+impl<F, FRBO> FrontBuilder<(), FRBO> for F
+where
+    F: Fn() -> FRBO + Send + Sync + 'static,
+    FRBO: FrontBuilderOutput,
+{
+    fn build(&self, _: FrontendRequest) -> FRBO {
+        self()
+    }
+}
+
+impl<F, FRBO> FrontBuilder<(FrontendRequest,), FRBO> for F
+where
+    F: Fn(FrontendRequest) -> FRBO + Send + Sync + 'static,
+    FRBO: FrontBuilderOutput,
+{
+    fn build(&self, request: FrontendRequest) -> FRBO {
+        self(request)
+    }
+}
+
 // ------ ------
 //     Start
 // ------ ------
 
-pub async fn start<FRB, FRBO, UPH, UPHO, UMsg>(
+pub async fn start<FRB, FRBA, FRBO, UPH, UPHO, UMsg>(
     frontend: FRB,
     up_msg_handler: UPH,
     service_config: impl Fn(&mut web::ServiceConfig) + Send + Sync + 'static,
 ) -> io::Result<()>
 where
-    FRB: FrontBuilder<FRBO>,
+    FRB: FrontBuilder<FRBA, FRBO>,
+    FRBA: 'static,
     FRBO: FrontBuilderOutput,
     UPH: UpHandler<UPHO, UMsg>,
     UPHO: UpHandlerOutput,
@@ -171,14 +220,15 @@ where
     start_with_app(frontend, up_msg_handler, app, service_config).await
 }
 
-pub async fn start_with_app<FRB, FRBO, UPH, UPHO, UMsg, AT, AB, ABE>(
+pub async fn start_with_app<FRB, FRBA, FRBO, UPH, UPHO, UMsg, AT, AB, ABE>(
     frontend: FRB,
     up_msg_handler: UPH,
     app: impl Fn() -> App<AT> + Send + Sync + 'static,
     service_config: impl Fn(&mut web::ServiceConfig) + Send + Sync + 'static,
 ) -> io::Result<()>
 where
-    FRB: FrontBuilder<FRBO>,
+    FRB: FrontBuilder<FRBA, FRBO>,
+    FRBA: 'static,
     FRBO: FrontBuilderOutput,
     UPH: UpHandler<UPHO, UMsg>,
     UPHO: UpHandlerOutput,
@@ -195,12 +245,19 @@ where
 {
     // ------ Init ------
 
+    if let Err(errors) = config::loaded_config() {
+        eprintln!("Invalid Moon config:\n{errors}");
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, errors.clone()));
+    }
     println!("Moon config: {:?}", *CONFIG);
 
     env_logger::builder()
         .filter_level(CONFIG.backend_log_level)
         .init();
 
+    // opens the configured storage
+    sessions::remove_stale_sessions();
+
     let shared_data = SharedData {
         backend_build_id: backend_build_id().await,
         frontend_build_id: Frontend::build_id().await,
@@ -208,8 +265,14 @@ where
         compressed_pkg: CONFIG.compressed_pkg,
         pkg_path: "frontend/pkg",
     };
-    let reload_sse = ReloadSSE(SSE::start());
-    let message_sse = MessageSSE(SSE::start());
+    let reload_sse = ReloadSSE {
+        sse: SSE::start(),
+        build_errors: parking_lot::Mutex::new("[]".to_owned()),
+    };
+    let message_sse = MessageSSE(SSE::start_with_replay_buffer(
+        CONFIG.message_sse.replay_buffer_size,
+        time::Duration::from_secs(CONFIG.message_sse.replay_buffer_max_age),
+    ));
     let address = SocketAddr::from(([0, 0, 0, 0], CONFIG.port));
 
     let mut lazy_message_writer = LazyMessageWriter::new();
@@ -221,6 +284,8 @@ where
     let data_up_msg_handler = web::Data::new(up_msg_handler);
     let data_reload_sse = web::Data::new(reload_sse);
     let data_message_sse = web::Data::new(message_sse);
+    let shutdown_message_sse = data_message_sse.clone();
+    let data_rate_limiters = web::Data::from(RateLimiters::start());
 
     let app = Arc::new(app);
 
@@ -233,6 +298,7 @@ where
             .app_data(data_up_msg_handler.clone())
             .app_data(data_reload_sse.clone())
             .app_data(data_message_sse.clone())
+            .app_data(data_rate_limiters.clone())
             .configure(service_config.clone())
             .service(
                 Files::new("_api/public", "public").default_handler(web::to(|| async {
@@ -246,6 +312,7 @@ where
                         web::post().to(up_msg_handler_responder::<UPH, UPHO, UMsg>),
                     )
                     .route("reload", web::post().to(reload_responder))
+                    .route("build_errors", web::post().to(build_errors_responder))
                     .route("pkg/{file:.*}", web::get().to(pkg_responder))
                     .route(
                         "web_workers/{crate_name}/pkg/{file:.*}",
@@ -255,8 +322,16 @@ where
                         "message_sse/{session_id}",
                         web::get().to(message_sse_responder),
                     )
+                    .route(
+                        "ws/{session_id}",
+                        web::get().to(message_ws_responder::<UPH, UPHO, UMsg>),
+                    )
                     .route("reload_sse", web::get().to(reload_sse_responder))
+                    .route("bootstrap/{file}", web::get().to(bootstrap_responder))
                     .route("ping", web::to(|| async { "pong" }))
+                    .route("health", web::get().to(health_responder))
+                    .route("ready", web::get().to(ready_responder))
+                    .route("metrics", web::get().to(metrics_responder))
                     .route(
                         "{path:.*}",
                         web::to(|| async {
@@ -264,7 +339,7 @@ where
                         }),
                     ),
             )
-            .default_service(web::get().to(frontend_responder::<FRB, FRBO>))
+            .default_service(web::get().to(frontend_responder::<FRB, FRBA, FRBO>))
     });
 
     // ------ Bind ------
@@ -286,15 +361,35 @@ where
 
     // ------ Run ------
 
-    let server = server.run();
+    let server = server
+        .disable_signals()
+        .shutdown_timeout(CONFIG.shutdown_timeout)
+        .run();
+    rt::spawn(shut_down_on_signal(server.handle(), shutdown_message_sse));
     if not(CONFIG.frontend_dist) {
         lazy_message_writer.write_all()?;
     }
     server.await?;
+    web::block(|| storage::storage().flush())
+        .await
+        .map_err(io::Error::other)?;
 
     Ok(println!("Stop Moon"))
 }
 
+async fn shut_down_on_signal(server_handle: ServerHandle, message_sse: web::Data<MessageSSE>) {
+    shutdown::signal().await;
+    println!("Shutting down Moon...");
+
+    shutdown::begin();
+    // Zoon reconnects to the restarted Moon later
+    let _ = message_sse.broadcast("server_shutdown", "");
+    shutdown::drain().await;
+
+    message_sse.close_all_connections();
+    server_handle.stop(true).await;
+}
+
 async fn backend_build_id() -> u128 {
     fs::read_to_string("backend/private/build_id")
         .await
@@ -339,37 +434,100 @@ async fn up_msg_handler_responder<UPH, UPHO, UMsg>(
     req: HttpRequest,
     payload: web::Payload,
     up_msg_handler: web::Data<UPH>,
+    rate_limiters: web::Data<RateLimiters>,
 ) -> Result<HttpResponse, Error>
 where
     UPH: UpHandler<UPHO, UMsg>,
     UPHO: UpHandlerOutput,
     UMsg: DeserializeOwned,
 {
+    let started_at = Instant::now();
+    let result = handle_up_msg(req, payload, up_msg_handler, rate_limiters).await;
+    let status = match &result {
+        Ok(response) => response.status(),
+        Err(error) => error.as_response_error().status_code(),
+    };
+    metrics::record_http_up_msg(status.as_u16(), started_at.elapsed());
+    result
+}
+
+async fn handle_up_msg<UPH, UPHO, UMsg>(
+    req: HttpRequest,
+    payload: web::Payload,
+    up_msg_handler: web::Data<UPH>,
+    rate_limiters: web::Data<RateLimiters>,
+) -> Result<HttpResponse, Error>
+where
+    UPH: UpHandler<UPHO, UMsg>,
+    UPHO: UpHandlerOutput,
+    UMsg: DeserializeOwned,
+{
+    let _handler_guard = shutdown::handler_guard().ok_or_else(shutdown::shutting_down_error)?;
     let headers = req.headers();
+    let codec = parse_codec(headers)?;
+
+    let session_id = parse_session_id(headers)?;
+    rate_limiters.check_up_msg(session_id, client_ip(&req))?;
+    let auth_token = parse_auth_token(headers)?;
+    let identity = authenticate(auth::authenticator(), auth_token.as_ref())
+        .await
+        .map_err(error::ErrorUnauthorized)?;
+    authorize_session(session_id, identity.as_ref())?;
 
     let up_msg_request = UpMsgRequest {
-        up_msg: parse_up_msg(payload).await?,
-        session_id: parse_session_id(headers)?,
+        up_msg: parse_up_msg(payload, codec).await?,
+        session_id,
         cor_id: parse_cor_id(headers)?,
-        auth_token: parse_auth_token(headers)?,
+        auth_token,
+        identity,
     };
-    up_msg_handler.get_ref()(up_msg_request).await;
-    Ok(HttpResponse::Ok().finish())
+    match up_msg_handler.get_ref()(up_msg_request)
+        .await
+        .encode_error(codec)
+    {
+        None => Ok(HttpResponse::Ok().finish()),
+        Some(Ok(error)) => Ok(HttpResponse::build(StatusCode::UNPROCESSABLE_ENTITY)
+            .content_type(codec.content_type())
+            .body(error)),
+        Some(Err(error)) => Err(error::ErrorInternalServerError(error)),
+    }
 }
 
 #[cfg(feature = "serde")]
-async fn parse_up_msg<UMsg: DeserializeOwned>(mut payload: web::Payload) -> Result<UMsg, Error> {
+async fn parse_up_msg<UMsg: DeserializeOwned>(
+    mut payload: web::Payload,
+    codec: Codec,
+) -> Result<UMsg, Error> {
+    let limit = CONFIG.up_msg_max_bytes;
     let mut body = web::BytesMut::new();
     while let Some(chunk) = payload.next().await {
         let chunk = chunk?;
-        if (body.len() + chunk.len()) > MAX_UP_MSG_BYTES {
-            Err(error::JsonPayloadError::Overflow {
-                limit: MAX_UP_MSG_BYTES,
-            })?
+        if (body.len() + chunk.len()) > limit {
+            Err(error::ErrorPayloadTooLarge(format!(
+                "UpMsg is larger than {limit} bytes"
+            )))?
         }
         body.extend_from_slice(&chunk);
     }
-    Ok(serde_json::from_slice(&body).map_err(error::JsonPayloadError::Deserialize)?)
+    codec.decode(&body).map_err(error::ErrorBadRequest)
+}
+
+fn parse_codec(headers: &HeaderMap) -> Result<Codec, Error> {
+    let Some(content_type) = headers.get(header::CONTENT_TYPE) else {
+        return Ok(Codec::default());
+    };
+    let content_type = content_type
+        .to_str()
+        .map_err(error::ErrorBadRequest)?
+        .parse::<mime::Mime>()
+        .map_err(error::ErrorBadRequest)?;
+    // `fetch` sends `text/plain` for string bodies
+    if content_type.essence_str() == mime::TEXT_PLAIN.essence_str() {
+        return Ok(Codec::Json);
+    }
+    Codec::from_content_type(content_type.essence_str()).ok_or_else(|| {
+        error::ErrorUnsupportedMediaType(format!("unsupported UpMsg codec '{content_type}'"))
+    })
 }
 
 fn parse_session_id(headers: &HeaderMap) -> Result<SessionId, Error> {
@@ -403,13 +561,126 @@ fn parse_auth_token(headers: &HeaderMap) -> Result<Option<AuthToken>, Error> {
     Ok(None)
 }
 
+/// Returns `None` when there is no token or no `Authenticator` is configured.
+/// Handlers pass `auth::authenticator()`, tests pass their own authenticators.
+async fn authenticate(
+    authenticator: Option<&dyn auth::Authenticator>,
+    auth_token: Option<&AuthToken>,
+) -> Result<Option<Identity>, AuthError> {
+    let (Some(auth_token), Some(authenticator)) = (auth_token, authenticator) else {
+        return Ok(None);
+    };
+    authenticator.authenticate(auth_token).await.map(Some)
+}
+
+/// Sessions bound to a user can be used only by the same user.
+fn authorize_session(session_id: SessionId, identity: Option<&Identity>) -> Result<(), Error> {
+    let Some(user_id) = sessions::session_user_id(session_id) else {
+        return Ok(());
+    };
+    if identity.is_some_and(|identity| identity.user_id == user_id) {
+        return Ok(());
+    }
+    Err(error::ErrorForbidden("the session belongs to another user"))
+}
+
+// ------ health_responder ------
+
+/// Moon is running. Use it as a liveness probe.
+async fn health_responder() -> impl Responder {
+    "ok"
+}
+
+// ------ ready_responder ------
+
+/// Moon can serve requests. Use it as a readiness probe.
+async fn ready_responder() -> impl Responder {
+    let failed_checks = readiness::failed_readiness_checks().await;
+    if failed_checks.is_empty() {
+        return HttpResponse::Ok().body("ready");
+    }
+    HttpResponse::ServiceUnavailable().body(failed_checks.join("\n"))
+}
+
+// ------ metrics_responder ------
+
+async fn metrics_responder(
+    req: HttpRequest,
+    sse: web::Data<MessageSSE>,
+) -> Result<HttpResponse, Error> {
+    if not(CONFIG.metrics.enabled) {
+        Err(error::ErrorNotFound("metrics are disabled"))?
+    }
+    authorize_metrics_request(&req)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(metrics::render(sse.connection_count())))
+}
+
+fn authorize_metrics_request(req: &HttpRequest) -> Result<(), Error> {
+    let allowed_ips = &CONFIG.metrics.allowed_ips;
+    if not(allowed_ips.is_empty())
+        && not(client_ip(req).is_some_and(|client_ip| allowed_ips.contains(&client_ip)))
+    {
+        Err(error::ErrorForbidden(
+            "the client IP is not allowed to read metrics",
+        ))?
+    }
+    let Some(token) = &CONFIG.metrics.token else {
+        return Ok(());
+    };
+    let bearer_token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|authorization| authorization.to_str().ok())
+        .and_then(|authorization| authorization.strip_prefix("Bearer "));
+    if bearer_token != Some(token.as_str()) {
+        Err(error::ErrorUnauthorized(
+            "metrics token is missing or invalid",
+        ))?
+    }
+    Ok(())
+}
+
 // ------ reload_responder ------
 
-async fn reload_responder(sse: web::Data<ReloadSSE>) -> impl Responder {
-    let _ = sse.broadcast("reload", "");
+/// `?hot=true` lets Zoon preserve its state during the reload.
+async fn reload_responder(
+    sse: web::Data<ReloadSSE>,
+    query: web::Query<HashMap<String, String>>,
+) -> impl Responder {
+    let event = if query.get("hot").is_some_and(|hot| hot == "true") {
+        "hot_reload"
+    } else {
+        "reload"
+    };
+    let _ = sse.broadcast(event, "");
     HttpResponse::Ok()
 }
 
+// ------ build_errors_responder ------
+
+/// `mzoon start` sends compilation errors to display them in the browser.
+/// An empty array hides them.
+async fn build_errors_responder(
+    sse: web::Data<ReloadSSE>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    if !CONFIG.frontend_auto_reload {
+        Err(error::ErrorNotFound(
+            "build errors are available only in the dev mode",
+        ))?
+    }
+    let build_errors =
+        serde_json::from_slice::<Vec<serde_json::Value>>(&body).map_err(error::ErrorBadRequest)?;
+    // SSE data have to be on a single line
+    let build_errors = serde_json::to_string(&build_errors)?;
+    let _ = sse.broadcast("build_errors", &build_errors);
+    *sse.build_errors.lock() = build_errors;
+    Ok(HttpResponse::Ok().finish())
+}
+
 // ------ pkg_responder ------
 
 async fn pkg_responder(
@@ -434,7 +705,13 @@ async fn pkg_responder(
         .disable_content_disposition()
         .customize();
 
-    let mut responder = if shared_data.cache_busting {
+    let mut responder = if file.starts_with("public/") {
+        // public assets fingerprinted by `mzoon build`
+        named_file.insert_header(CacheControl(vec![
+            CacheDirective::MaxAge(31536000),
+            CacheDirective::Extension("immutable".to_owned(), None),
+        ]))
+    } else if shared_data.cache_busting {
         named_file.insert_header(CacheControl(vec![CacheDirective::MaxAge(31536000)]))
     } else {
         named_file.insert_header(ETag(EntityTag::new(
@@ -606,13 +883,26 @@ fn web_worker_workspace_members() -> Result<Vec<WorkspaceMember>> {
     Ok(members)
 }
 
+// ------ bootstrap_responder ------
+
+async fn bootstrap_responder(file: web::Path<String>) -> Result<HttpResponse, Error> {
+    let js_code = Frontend::bootstrap_js_code(&file)
+        .await
+        .ok_or_else(|| error::ErrorNotFound("bootstrap script not found"))?;
+    Ok(HttpResponse::Ok()
+        .content_type(mime::APPLICATION_JAVASCRIPT_UTF_8)
+        // `start_app.js` changes with each frontend build
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .body(js_code))
+}
+
 // ------ reload_sse_responder ------
 
 async fn reload_sse_responder(
     sse: web::Data<ReloadSSE>,
     shared_data: web::Data<SharedData>,
 ) -> impl Responder {
-    let (connection, event_stream) = sse.new_connection(None);
+    let (connection, event_stream) = sse.new_connection(None, None);
     let backend_build_id = shared_data.backend_build_id.to_string();
 
     if connection
@@ -623,6 +913,12 @@ async fn reload_sse_responder(
             .reason("sending backend_build_id failed")
             .finish();
     }
+    let build_errors = sse.build_errors.lock().clone();
+    if build_errors != "[]" && connection.send("build_errors", &build_errors).is_err() {
+        return HttpResponse::InternalServerError()
+            .reason("sending build_errors failed")
+            .finish();
+    }
 
     HttpResponse::Ok()
         .insert_header(ContentType(mime::TEXT_EVENT_STREAM))
@@ -633,12 +929,30 @@ async fn reload_sse_responder(
 // ------ message_sse_responder ------
 
 async fn message_sse_responder(
+    req: HttpRequest,
     session_id: web::Path<String>,
     sse: web::Data<MessageSSE>,
+    rate_limiters: web::Data<RateLimiters>,
 ) -> Result<HttpResponse, Error> {
+    if is_shutting_down() {
+        Err(shutdown::shutting_down_error())?
+    }
     let session_id = session_id.parse().map_err(error::ErrorBadRequest)?;
-    let (_, event_stream) = sse.new_connection(Some(session_id));
-    SessionActor::create(session_id, MessageSSE::clone(&sse));
+    rate_limiters.check_connection(session_id, client_ip(&req))?;
+    let query = parse_query(&req)?;
+    let codec = parse_codec_param(&query)?;
+    let last_event_id = parse_last_event_id(req.headers(), &query)?;
+    let identity =
+        authenticate_session(auth::authenticator(), req.headers(), &query, session_id).await?;
+    let (_, event_stream) = sse.new_connection(Some(session_id), last_event_id);
+    let session_actor = SessionActor::create_with_channel(
+        session_id,
+        DownMsgChannel::SSE(MessageSSE::clone(&sse)),
+        codec,
+    );
+    if let Some(identity) = identity {
+        session_actor.set_user_id(identity.user_id);
+    }
 
     Ok(HttpResponse::Ok()
         .insert_header(ContentType(mime::TEXT_EVENT_STREAM))
@@ -646,11 +960,292 @@ async fn message_sse_responder(
         .streaming(event_stream))
 }
 
+fn parse_query(req: &HttpRequest) -> Result<HashMap<String, String>, Error> {
+    web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .map(web::Query::into_inner)
+        .map_err(error::ErrorBadRequest)
+}
+
+fn parse_codec_param(query: &HashMap<String, String>) -> Result<Codec, Error> {
+    query
+        .get(Codec::QUERY_PARAM)
+        .map(|codec| codec.parse().map_err(error::ErrorBadRequest))
+        .unwrap_or(Ok(Codec::default()))
+}
+
+/// Authenticates the message SSE or WebSocket handshake.
+async fn authenticate_session(
+    authenticator: Option<&dyn auth::Authenticator>,
+    headers: &HeaderMap,
+    query: &HashMap<String, String>,
+    session_id: SessionId,
+) -> Result<Option<Identity>, Error> {
+    // `EventSource` can't send custom headers
+    let auth_token = parse_auth_token_cookie(headers)
+        .or_else(|| query.get(AuthToken::QUERY_PARAM).map(AuthToken::new));
+    let identity = authenticate(authenticator, auth_token.as_ref())
+        .await
+        .map_err(error::ErrorUnauthorized)?;
+    if identity.is_none() && CONFIG.auth.require_for_message_sse {
+        Err(error::ErrorUnauthorized("auth token is required"))?
+    }
+    authorize_session(session_id, identity.as_ref())?;
+    Ok(identity)
+}
+
+fn parse_auth_token_cookie(headers: &HeaderMap) -> Option<AuthToken> {
+    parse_cookie(headers, AuthToken::COOKIE_NAME).map(AuthToken::new)
+}
+
+fn parse_cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .filter_map(|cookies| cookies.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .find_map(|cookie| {
+            let (cookie_name, value) = cookie.trim().split_once('=')?;
+            (cookie_name == name).then_some(value)
+        })
+}
+
+fn parse_last_event_id(
+    headers: &HeaderMap,
+    query: &HashMap<String, String>,
+) -> Result<Option<u64>, Error> {
+    if let Some(last_event_id) = headers.get("Last-Event-ID") {
+        let last_event_id = last_event_id
+            .to_str()
+            .map_err(error::ErrorBadRequest)?
+            .parse()
+            .map_err(error::ErrorBadRequest)?;
+        return Ok(Some(last_event_id));
+    }
+    // `ReconnectingEventSource` sends the id in the query because
+    // it creates a new `EventSource` on each reconnect
+    query
+        .get("lastEventId")
+        .map(|last_event_id| last_event_id.parse().map_err(error::ErrorBadRequest))
+        .transpose()
+}
+
+// ------ message_ws_responder ------
+
+async fn message_ws_responder<UPH, UPHO, UMsg>(
+    req: HttpRequest,
+    session_id: web::Path<String>,
+    payload: web::Payload,
+    up_msg_handler: web::Data<UPH>,
+    message_sse: web::Data<MessageSSE>,
+    rate_limiters: web::Data<RateLimiters>,
+) -> Result<HttpResponse, Error>
+where
+    UPH: UpHandler<UPHO, UMsg>,
+    UPHO: UpHandlerOutput,
+    UMsg: 'static + DeserializeOwned,
+{
+    if is_shutting_down() {
+        Err(shutdown::shutting_down_error())?
+    }
+    let session_id = session_id.parse().map_err(error::ErrorBadRequest)?;
+    let client_ip = client_ip(&req);
+    rate_limiters.check_connection(session_id, client_ip)?;
+    let query = parse_query(&req)?;
+    let codec = parse_codec_param(&query)?;
+    let identity =
+        authenticate_session(auth::authenticator(), req.headers(), &query, session_id).await?;
+    let (response, mut ws_session, msg_stream) = actix_ws::handle(&req, payload)?;
+    let mut msg_stream = msg_stream.max_frame_size(CONFIG.up_msg_max_bytes);
+
+    let (down_msg_sender, mut down_msg_receiver) = unbounded_channel::<Vec<u8>>();
+    let up_msg_result_sender = down_msg_sender.clone();
+    let connection_id = message_sse.new_web_socket_connection(session_id);
+    let session_actor = SessionActor::create_with_channel(
+        session_id,
+        DownMsgChannel::WebSocket(down_msg_sender),
+        codec,
+    );
+    if let Some(identity) = identity {
+        session_actor.set_user_id(identity.user_id);
+    }
+
+    rt::spawn(async move {
+        let _connection_guard = metrics::WebSocketConnectionGuard::new();
+        let mut heartbeat = interval(WEB_SOCKET_HEARTBEAT_INTERVAL);
+        let mut close_reason = None;
+        loop {
+            tokio::select! {
+                down_msg = down_msg_receiver.recv() => {
+                    let Some(down_msg) = down_msg else { break };
+                    let sent = if codec.is_binary() {
+                        ws_session.binary(down_msg).await
+                    } else {
+                        ws_session.text(String::from_utf8_lossy(&down_msg).into_owned()).await
+                    };
+                    if sent.is_err() {
+                        break;
+                    }
+                }
+                message = msg_stream.recv() => match message {
+                    Some(Ok(actix_ws::Message::Text(text))) => {
+                        spawn_ws_up_msg_handler::<UPH, UPHO, UMsg>(
+                            &up_msg_handler,
+                            &up_msg_result_sender,
+                            session_id,
+                            codec,
+                            text.as_bytes(),
+                            rate_limiters.check_up_msg(session_id, client_ip),
+                        );
+                    }
+                    Some(Ok(actix_ws::Message::Binary(bytes))) => {
+                        spawn_ws_up_msg_handler::<UPH, UPHO, UMsg>(
+                            &up_msg_handler,
+                            &up_msg_result_sender,
+                            session_id,
+                            codec,
+                            &bytes,
+                            rate_limiters.check_up_msg(session_id, client_ip),
+                        );
+                    }
+                    Some(Ok(actix_ws::Message::Ping(bytes))) => {
+                        if ws_session.pong(&bytes).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(actix_ws::Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => (),
+                },
+                _ = heartbeat.tick() => {
+                    if ws_session.ping(b"").await.is_err() {
+                        break;
+                    }
+                }
+                _ = shutdown::drained() => {
+                    // Zoon reconnects to the restarted Moon later
+                    close_reason = Some(actix_ws::CloseCode::Restart.into());
+                    break;
+                }
+            }
+        }
+        // the session is kept for the reconnecting client
+        message_sse.remove_web_socket_connection(session_id, connection_id);
+        let _ = ws_session.close(close_reason).await;
+    });
+
+    Ok(response)
+}
+
+/// Zoon is notified about the result of each `UpMsg` with a known `CorId`.
+fn spawn_ws_up_msg_handler<UPH, UPHO, UMsg>(
+    up_msg_handler: &web::Data<UPH>,
+    up_msg_result_sender: &UnboundedSender<Vec<u8>>,
+    session_id: SessionId,
+    codec: Codec,
+    bytes: &[u8],
+    rate_limit: Result<(), Error>,
+) where
+    UPH: UpHandler<UPHO, UMsg>,
+    UPHO: UpHandlerOutput,
+    UMsg: 'static + DeserializeOwned,
+{
+    let mut up_msg_request = match parse_ws_up_msg_request(session_id, codec, bytes) {
+        Ok(up_msg_request) => up_msg_request,
+        Err(error) => {
+            metrics::record_web_socket_up_msg("rejected");
+            return eprintln!("Failed to parse UpMsg received through WebSocket: {error}");
+        }
+    };
+    let cor_id = up_msg_request.cor_id;
+    let up_msg_result_sender = up_msg_result_sender.clone();
+    let send_up_msg_result = move |result: Result<(), UpMsgFailure>| {
+        let frame =
+            WebSocketFrameForSer::<()>::UpMsgResult(UpMsgResultTransporter { cor_id, result });
+        match codec.encode(&frame) {
+            Ok(frame) => {
+                let _ = up_msg_result_sender.send(frame);
+            }
+            Err(error) => eprintln!("Failed to encode UpMsg result: {error}"),
+        }
+    };
+    let reject = {
+        let send_up_msg_result = send_up_msg_result.clone();
+        move |reason: String| {
+            metrics::record_web_socket_up_msg("rejected");
+            eprintln!("UpMsg received through WebSocket has been rejected: {reason}");
+            send_up_msg_result(Err(UpMsgFailure::Rejected(reason)));
+        }
+    };
+
+    if let Err(error) = rate_limit {
+        return reject(error.to_string());
+    }
+    let Some(handler_guard) = shutdown::handler_guard() else {
+        return reject("Moon is shutting down".to_owned());
+    };
+    let up_msg_handler = up_msg_handler.clone();
+    rt::spawn(async move {
+        let _handler_guard = handler_guard;
+        let authorized_identity =
+            authenticate(auth::authenticator(), up_msg_request.auth_token.as_ref())
+                .await
+                .map_err(error::ErrorUnauthorized)
+                .and_then(|identity| {
+                    authorize_session(session_id, identity.as_ref())?;
+                    Ok(identity)
+                });
+        match authorized_identity {
+            Ok(identity) => up_msg_request.identity = identity,
+            Err(error) => return reject(error.to_string()),
+        }
+        let result = match up_msg_handler.get_ref()(up_msg_request)
+            .await
+            .encode_error(codec)
+        {
+            None => {
+                metrics::record_web_socket_up_msg("ok");
+                Ok(())
+            }
+            Some(Ok(error)) => {
+                metrics::record_web_socket_up_msg("handler_error");
+                Err(UpMsgFailure::HandlerError(error))
+            }
+            Some(Err(error)) => {
+                metrics::record_web_socket_up_msg("handler_error");
+                eprintln!("Failed to encode UpMsg handler error: {error}");
+                Err(UpMsgFailure::Rejected(error.to_string()))
+            }
+        };
+        send_up_msg_result(result);
+    });
+}
+
+#[cfg(feature = "serde")]
+fn parse_ws_up_msg_request<UMsg: DeserializeOwned>(
+    session_id: SessionId,
+    codec: Codec,
+    bytes: &[u8],
+) -> Result<UpMsgRequest<UMsg>, CodecError> {
+    let UpMsgTransporterForDe {
+        up_msg,
+        cor_id,
+        auth_token,
+    } = codec.decode(bytes)?;
+    Ok(UpMsgRequest {
+        up_msg,
+        session_id,
+        cor_id,
+        auth_token,
+        identity: None,
+    })
+}
+
 // ------ frontend_responder ------
 
-async fn frontend_responder<FRB, FRBO>(frontend: web::Data<FRB>) -> impl Responder
+async fn frontend_responder<FRB, FRBA, FRBO>(
+    req: HttpRequest,
+    frontend: web::Data<FRB>,
+) -> impl Responder
 where
-    FRB: FrontBuilder<FRBO>,
+    FRB: FrontBuilder<FRBA, FRBO>,
     FRBO: FrontBuilderOutput,
 {
     let mut responder = HttpResponse::Ok();
@@ -663,7 +1258,35 @@ where
             .insert_header(("Cross-Origin-Embedder-Policy", "require-corp"));
     }
 
-    responder.body(frontend.get_ref()().await.into_html().await)
+    let csp_nonce = CONFIG.csp.enabled.then(csp_nonce);
+    if let Some(csp_nonce) = &csp_nonce {
+        let header_name = if CONFIG.csp.report_only {
+            header::CONTENT_SECURITY_POLICY_REPORT_ONLY
+        } else {
+            header::CONTENT_SECURITY_POLICY
+        };
+        responder
+            .insert_header((header_name, CONFIG.csp.policy.replace("{nonce}", csp_nonce)))
+            // cached pages would reuse the nonce
+            .insert_header(CacheControl(vec![CacheDirective::NoStore]));
+    }
+
+    let request = FrontendRequest {
+        path: req.path().to_owned(),
+        query: parse_query(&req).unwrap_or_default(),
+        headers: req.headers().clone(),
+        csp_nonce: csp_nonce.clone(),
+    };
+    let mut frontend = frontend.build(request).await;
+    if let Some(csp_nonce) = csp_nonce {
+        frontend = frontend.nonce(csp_nonce);
+    }
+    responder.body(frontend.into_html().await)
+}
+
+/// A random UUID (v4) encoded in Base64.
+fn csp_nonce() -> String {
+    STANDARD.encode(uuid::Uuid::new_v4().as_bytes())
 }
 
 // ====== ====== TESTS ====== ======
@@ -811,4 +1434,123 @@ mod tests {
         );
         assert_eq!(body::to_bytes(resp.into_body()).await.unwrap(), css_content,);
     }
+
+    #[actix_rt::test]
+    async fn test_authenticated_message_sse_handshake() {
+        // ------ ARRANGE ------
+        let authenticator = auth::HmacAuthenticator::new("secret");
+        let auth_token = authenticator.issue_token(&Identity::new("alice"));
+        let session_id = SessionId::new();
+
+        // Zoon passes the auth token in the query because `EventSource` can't send headers
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/_api/message_sse/{session_id}?{}=json&{}={}",
+                Codec::QUERY_PARAM,
+                AuthToken::QUERY_PARAM,
+                auth_token.as_str()
+            ))
+            .to_http_request();
+        let anonymous_req = test::TestRequest::get()
+            .uri(&format!("/_api/message_sse/{session_id}"))
+            .to_http_request();
+
+        // ------ ACT ------
+        let identity = authenticate_session(
+            Some(&authenticator),
+            req.headers(),
+            &parse_query(&req).unwrap(),
+            session_id,
+        )
+        .await;
+        let anonymous_identity = authenticate_session(
+            Some(&authenticator),
+            anonymous_req.headers(),
+            &parse_query(&anonymous_req).unwrap(),
+            session_id,
+        )
+        .await;
+
+        // ------ ASSERT ------
+        assert_eq!(identity.unwrap().unwrap().user_id, "alice");
+        assert!(anonymous_identity.unwrap().is_none());
+    }
+
+    #[actix_rt::test]
+    async fn test_codec_negotiation() {
+        // ------ ARRANGE ------
+        let request_with_content_type = |content_type| {
+            test::TestRequest::post()
+                .insert_header((header::CONTENT_TYPE, content_type))
+                .to_http_request()
+        };
+        let query =
+            |codec: &str| HashMap::from([(Codec::QUERY_PARAM.to_owned(), codec.to_owned())]);
+
+        // ------ ACT ------
+        let without_content_type =
+            parse_codec(test::TestRequest::post().to_http_request().headers());
+        let json = parse_codec(request_with_content_type("application/json").headers());
+        let text_plain =
+            parse_codec(request_with_content_type("text/plain;charset=UTF-8").headers());
+        let text_html = parse_codec(request_with_content_type("text/html").headers());
+        let json_param = parse_codec_param(&query("json"));
+        let unknown_param = parse_codec_param(&query("xml"));
+
+        // ------ ASSERT ------
+        assert_eq!(without_content_type.unwrap(), Codec::Json);
+        assert_eq!(json.unwrap(), Codec::Json);
+        assert_eq!(text_plain.unwrap(), Codec::Json);
+        assert_eq!(
+            text_html.unwrap_err().as_response_error().status_code(),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+        assert_eq!(json_param.unwrap(), Codec::Json);
+        assert_eq!(
+            unknown_param.unwrap_err().as_response_error().status_code(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_web_socket_up_msg_handler_error_is_sent_to_zoon() {
+        // ------ ARRANGE ------
+        let up_msg_handler =
+            Data::new(|_: UpMsgRequest<String>| async { Err::<(), _>("invalid UpMsg".to_owned()) });
+        let (up_msg_result_sender, mut up_msg_result_receiver) = unbounded_channel();
+        let cor_id = CorId::new();
+        let up_msg_transporter = Codec::Json
+            .encode(&UpMsgTransporterForSer {
+                up_msg: &"UpMsg".to_owned(),
+                cor_id,
+                auth_token: None,
+            })
+            .unwrap();
+
+        // ------ ACT ------
+        spawn_ws_up_msg_handler(
+            &up_msg_handler,
+            &up_msg_result_sender,
+            SessionId::new(),
+            Codec::Json,
+            &up_msg_transporter,
+            Ok(()),
+        );
+        let frame = up_msg_result_receiver.recv().await.unwrap();
+
+        // ------ ASSERT ------
+        let WebSocketFrameForDe::<()>::UpMsgResult(up_msg_result) =
+            Codec::Json.decode(&frame).unwrap()
+        else {
+            panic!("UpMsgResult frame expected");
+        };
+        assert_eq!(up_msg_result.cor_id, cor_id);
+        let Err(UpMsgFailure::HandlerError(error)) = up_msg_result.result else {
+            panic!("UpMsg handler error expected");
+        };
+        assert_eq!(
+            Codec::Json.decode::<String>(&error).unwrap(),
+            "invalid UpMsg"
+        );
+    }
 }
@@ -0,0 +1,145 @@
+use crate::{sessions, shutdown, Index};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+/// Upper bounds (in seconds) of the `UpMsg` request duration histogram buckets.
+const DURATION_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1., 2.5, 5., 10.];
+
+static METRICS: Lazy<Metrics> = Lazy::new(Metrics::default);
+
+#[derive(Default)]
+struct Metrics {
+    /// Keys are `(transport, status)`.
+    up_msgs: Mutex<BTreeMap<(&'static str, String), u64>>,
+    up_msg_duration_buckets: [AtomicU64; DURATION_BUCKETS.len()],
+    up_msg_duration_count: AtomicU64,
+    up_msg_duration_sum_micros: AtomicU64,
+    web_socket_connections: AtomicUsize,
+}
+
+/// Records an `UpMsg` received through `/_api/up_msg_handler`.
+pub(crate) fn record_http_up_msg(status: u16, duration: Duration) {
+    record_up_msg("http", status.to_string());
+    let seconds = duration.as_secs_f64();
+    for (bucket, upper_bound) in METRICS.up_msg_duration_buckets.iter().zip(DURATION_BUCKETS) {
+        if seconds <= upper_bound {
+            bucket.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    METRICS
+        .up_msg_duration_count
+        .fetch_add(1, Ordering::Relaxed);
+    METRICS
+        .up_msg_duration_sum_micros
+        .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+}
+
+/// Records an `UpMsg` received through the message WebSocket.
+/// `outcome` is `ok`, `handler_error` or `rejected`.
+pub(crate) fn record_web_socket_up_msg(outcome: &'static str) {
+    record_up_msg("websocket", outcome.to_owned());
+}
+
+fn record_up_msg(transport: &'static str, status: String) {
+    *METRICS
+        .up_msgs
+        .lock()
+        .entry((transport, status))
+        .or_default() += 1;
+}
+
+// ------ WebSocketConnectionGuard ------
+
+/// Counts the open message WebSocket connection while it lives.
+pub(crate) struct WebSocketConnectionGuard(());
+
+impl WebSocketConnectionGuard {
+    pub(crate) fn new() -> Self {
+        METRICS
+            .web_socket_connections
+            .fetch_add(1, Ordering::Relaxed);
+        Self(())
+    }
+}
+
+impl Drop for WebSocketConnectionGuard {
+    fn drop(&mut self) {
+        METRICS
+            .web_socket_connections
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// ------ render ------
+
+/// Renders metrics in the Prometheus text format.
+pub(crate) fn render(message_sse_connections: usize) -> String {
+    let mut output = String::new();
+    // writing to `String` can't fail
+    let _ = write_metrics(&mut output, message_sse_connections);
+    output
+}
+
+fn write_metrics(output: &mut String, message_sse_connections: usize) -> std::fmt::Result {
+    write_gauge(
+        output,
+        "moon_sessions",
+        "Active sessions.",
+        sessions::by_session_id().len(),
+    )?;
+    write_gauge(
+        output,
+        "moon_message_sse_connections",
+        "Open message SSE connections.",
+        message_sse_connections,
+    )?;
+    write_gauge(
+        output,
+        "moon_message_web_socket_connections",
+        "Open message WebSocket connections.",
+        METRICS.web_socket_connections.load(Ordering::Relaxed),
+    )?;
+    write_gauge(
+        output,
+        "moon_running_up_msg_handlers",
+        "UpMsg handlers being executed.",
+        shutdown::running_handlers(),
+    )?;
+
+    writeln!(output, "# HELP moon_up_msgs_total Received UpMsgs.")?;
+    writeln!(output, "# TYPE moon_up_msgs_total counter")?;
+    for ((transport, status), count) in METRICS.up_msgs.lock().iter() {
+        writeln!(
+            output,
+            r#"moon_up_msgs_total{{transport="{transport}",status="{status}"}} {count}"#
+        )?;
+    }
+
+    let name = "moon_up_msg_request_duration_seconds";
+    writeln!(
+        output,
+        "# HELP {name} Duration of /_api/up_msg_handler requests."
+    )?;
+    writeln!(output, "# TYPE {name} histogram")?;
+    for (bucket, upper_bound) in METRICS.up_msg_duration_buckets.iter().zip(DURATION_BUCKETS) {
+        let count = bucket.load(Ordering::Relaxed);
+        writeln!(output, r#"{name}_bucket{{le="{upper_bound}"}} {count}"#)?;
+    }
+    let count = METRICS.up_msg_duration_count.load(Ordering::Relaxed);
+    writeln!(output, r#"{name}_bucket{{le="+Inf"}} {count}"#)?;
+    let sum = METRICS.up_msg_duration_sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.;
+    writeln!(output, "{name}_sum {sum}")?;
+    writeln!(output, "{name}_count {count}")
+}
+
+fn write_gauge(output: &mut String, name: &str, help: &str, value: usize) -> std::fmt::Result {
+    writeln!(output, "# HELP {name} {help}")?;
+    writeln!(output, "# TYPE {name} gauge")?;
+    writeln!(output, "{name} {value}")
+}
@@ -0,0 +1,217 @@
+use crate::{config::CONFIG, sessions, Index};
+use actix_web::{
+    error::{self, Error, InternalError},
+    http::header,
+    rt, HttpRequest, HttpResponse,
+};
+use moonlight::SessionId;
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::time::interval;
+
+const BUCKET_CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+// ------ RateLimiters ------
+
+/// Protects `up_msg_handler` and message connections configured by `CONFIG.rate_limit`.
+pub struct RateLimiters {
+    up_msgs_by_session: RateLimiter<SessionId>,
+    up_msgs_by_ip: RateLimiter<IpAddr>,
+    connections_by_session: RateLimiter<SessionId>,
+    connections_by_ip: RateLimiter<IpAddr>,
+}
+
+impl RateLimiters {
+    /// Spawns the cleanup of unused buckets.
+    pub fn start() -> Arc<Self> {
+        let config = &CONFIG.rate_limit;
+        let connections_per_second = config.connections_per_minute / 60.;
+        let this = Arc::new(Self {
+            up_msgs_by_session: RateLimiter::new(config.up_msg_burst, config.up_msgs_per_second),
+            up_msgs_by_ip: RateLimiter::new(config.ip_up_msg_burst, config.ip_up_msgs_per_second),
+            connections_by_session: RateLimiter::new(
+                config.connection_burst,
+                connections_per_second,
+            ),
+            connections_by_ip: RateLimiter::new(config.connection_burst, connections_per_second),
+        });
+        if config.enabled {
+            let this = Arc::clone(&this);
+            rt::spawn(async move {
+                let mut interval = interval(BUCKET_CLEANUP_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    this.up_msgs_by_session.remove_full_buckets();
+                    this.up_msgs_by_ip.remove_full_buckets();
+                    this.connections_by_session.remove_full_buckets();
+                    this.connections_by_ip.remove_full_buckets();
+                }
+            });
+        }
+        this
+    }
+
+    pub fn check_up_msg(
+        &self,
+        session_id: SessionId,
+        client_ip: Option<IpAddr>,
+    ) -> Result<(), Error> {
+        if !CONFIG.rate_limit.enabled {
+            return Ok(());
+        }
+        if let Some(client_ip) = client_ip {
+            self.up_msgs_by_ip
+                .check(client_ip)
+                .map_err(too_many_requests)?;
+        }
+        self.up_msgs_by_session
+            .check(session_id)
+            .map_err(too_many_requests)
+    }
+
+    /// Checks the message SSE or WebSocket connection and the session count.
+    pub fn check_connection(
+        &self,
+        session_id: SessionId,
+        client_ip: Option<IpAddr>,
+    ) -> Result<(), Error> {
+        if !CONFIG.rate_limit.enabled {
+            return Ok(());
+        }
+        if let Some(client_ip) = client_ip {
+            self.connections_by_ip
+                .check(client_ip)
+                .map_err(too_many_requests)?;
+        }
+        self.connections_by_session
+            .check(session_id)
+            .map_err(too_many_requests)?;
+
+        let max_sessions = CONFIG.rate_limit.max_sessions;
+        let by_session_id = sessions::by_session_id();
+        // reconnecting sessions don't increase the session count
+        if max_sessions > 0
+            && by_session_id.get(session_id).is_none()
+            && by_session_id.len() >= max_sessions
+        {
+            Err(error::ErrorServiceUnavailable(
+                "the maximum number of sessions has been reached",
+            ))?
+        }
+        Ok(())
+    }
+}
+
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    if !CONFIG.rate_limit.trust_proxy {
+        return req.peer_addr().map(|address| address.ip());
+    }
+    let address = req.connection_info().realip_remote_addr()?.to_owned();
+    address
+        .parse::<IpAddr>()
+        .or_else(|_| address.parse::<SocketAddr>().map(|address| address.ip()))
+        .ok()
+}
+
+fn too_many_requests(retry_after: Duration) -> Error {
+    let retry_after = retry_after.as_secs_f64().ceil().min(f64::from(u32::MAX)) as u32;
+    let response = HttpResponse::TooManyRequests()
+        .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+        .finish();
+    InternalError::from_response("too many requests", response).into()
+}
+
+// ------ RateLimiter ------
+
+/// Token bucket per key. Each request takes one token,
+/// tokens are refilled continuously up to the `burst`.
+pub struct RateLimiter<K> {
+    burst: f64,
+    per_second: f64,
+    buckets: Mutex<HashMap<K, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    pub fn new(burst: u32, per_second: f64) -> Self {
+        Self {
+            burst: f64::from(burst),
+            per_second,
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// Returns the time after which the request would be allowed if it's rejected.
+    pub fn check(&self, key: K) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock();
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: self.burst,
+            updated_at: now,
+        });
+        bucket.tokens = self.refilled_tokens(bucket, now);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1. {
+            bucket.tokens -= 1.;
+            return Ok(());
+        }
+        if self.per_second <= 0. {
+            return Err(Duration::MAX);
+        }
+        Err(Duration::from_secs_f64(
+            (1. - bucket.tokens) / self.per_second,
+        ))
+    }
+
+    /// Full buckets are equivalent to missing ones.
+    pub fn remove_full_buckets(&self) {
+        let now = Instant::now();
+        self.buckets
+            .lock()
+            .retain(|_, bucket| self.refilled_tokens(bucket, now) < self.burst);
+    }
+
+    fn refilled_tokens(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        (bucket.tokens + elapsed * self.per_second).min(self.burst)
+    }
+}
+
+// ====== ====== TESTS ====== ======
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter_allows_burst_and_then_rejects() {
+        // ------ ARRANGE ------
+        let rate_limiter = RateLimiter::new(2, 1.);
+
+        // ------ ACT ------
+        let results = [
+            rate_limiter.check("a"),
+            rate_limiter.check("a"),
+            rate_limiter.check("a"),
+            rate_limiter.check("b"),
+        ];
+
+        // ------ ASSERT ------
+        assert!(results[0].is_ok());
+        assert!(results[1].is_ok());
+        let retry_after = results[2].unwrap_err();
+        assert!(retry_after > Duration::from_millis(900) && retry_after <= Duration::from_secs(1));
+        assert!(results[3].is_ok());
+    }
+}
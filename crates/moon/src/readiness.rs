@@ -0,0 +1,42 @@
+use crate::shutdown;
+use futures::future::{join_all, BoxFuture};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::{future::Future, sync::Arc, time::Duration};
+use tokio::time::timeout;
+
+const READINESS_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+type ReadinessCheck = Arc<dyn Fn() -> BoxFuture<'static, Result<(), String>> + Send + Sync>;
+
+static READINESS_CHECKS: Lazy<RwLock<Vec<(&'static str, ReadinessCheck)>>> =
+    Lazy::new(RwLock::default);
+
+/// Registers a check called on each `/_api/ready` request (e.g. a DB ping).
+///
+/// Moon is ready when all checks return `Ok` within 5 seconds.
+pub fn add_readiness_check<F>(name: &'static str, check: impl Fn() -> F + Send + Sync + 'static)
+where
+    F: Future<Output = Result<(), String>> + Send + 'static,
+{
+    READINESS_CHECKS
+        .write()
+        .push((name, Arc::new(move || Box::pin(check()))));
+}
+
+/// Returns failed checks in the format `name: error`.
+pub(crate) async fn failed_readiness_checks() -> Vec<String> {
+    if shutdown::is_shutting_down() {
+        return vec!["shutdown: Moon is shutting down".to_owned()];
+    }
+    let checks = READINESS_CHECKS.read().clone();
+    let results = join_all(checks.into_iter().map(|(name, check)| async move {
+        match timeout(READINESS_CHECK_TIMEOUT, check()).await {
+            Ok(Ok(())) => None,
+            Ok(Err(error)) => Some(format!("{name}: {error}")),
+            Err(_) => Some(format!("{name}: timed out")),
+        }
+    }))
+    .await;
+    results.into_iter().flatten().collect()
+}
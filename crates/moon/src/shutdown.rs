@@ -0,0 +1,155 @@
+use crate::config::CONFIG;
+use actix_web::{
+    error::{Error, InternalError},
+    http::header,
+    rt::signal,
+    HttpResponse,
+};
+use futures::future::BoxFuture;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::{
+    future::Future,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+use tokio::{sync::watch, time::timeout};
+
+/// Zoon should wait at least this long before sending the rejected `UpMsg` again.
+const RETRY_AFTER_SECONDS: u32 = 5;
+
+type ShutdownHook = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+
+static SHUTDOWN: Lazy<Shutdown> = Lazy::new(|| Shutdown {
+    phase: watch::channel(Phase::Running).0,
+    running_handlers: AtomicUsize::new(0),
+    handler_finished: watch::channel(()).0,
+    hooks: Mutex::new(Vec::new()),
+});
+
+/// Registers an async hook called during the graceful shutdown
+/// after running `up_msg_handler`s have finished (e.g. to close DB pools).
+///
+/// Hooks are called in the registration order.
+pub fn on_shutdown<F>(hook: impl FnOnce() -> F + Send + 'static)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    SHUTDOWN
+        .hooks
+        .lock()
+        .push(Box::new(move || Box::pin(hook())));
+}
+
+pub fn is_shutting_down() -> bool {
+    *SHUTDOWN.phase.borrow() != Phase::Running
+}
+
+// ------ Shutdown ------
+
+struct Shutdown {
+    phase: watch::Sender<Phase>,
+    running_handlers: AtomicUsize,
+    handler_finished: watch::Sender<()>,
+    hooks: Mutex<Vec<ShutdownHook>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Phase {
+    Running,
+    /// New `UpMsg`s are rejected, running handlers and hooks are finishing.
+    Draining,
+    /// Connections can be closed.
+    Drained,
+}
+
+/// Resolves on SIGTERM or ctrl-c.
+pub(crate) async fn signal() {
+    #[cfg(unix)]
+    {
+        let mut sigterm = signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM");
+        tokio::select! {
+            _ = signal::ctrl_c() => (),
+            _ = sigterm.recv() => (),
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = signal::ctrl_c().await;
+}
+
+pub(crate) fn running_handlers() -> usize {
+    SHUTDOWN.running_handlers.load(Ordering::SeqCst)
+}
+
+/// Returns `None` when Moon is shutting down and it doesn't accept new `UpMsg`s.
+pub(crate) fn handler_guard() -> Option<HandlerGuard> {
+    // The counter is incremented before the check so `drain` can't miss the handler.
+    SHUTDOWN.running_handlers.fetch_add(1, Ordering::SeqCst);
+    let handler_guard = HandlerGuard;
+    (!is_shutting_down()).then_some(handler_guard)
+}
+
+/// `503 Service Unavailable` with `Retry-After`.
+pub(crate) fn shutting_down_error() -> Error {
+    let response = HttpResponse::ServiceUnavailable()
+        .insert_header((header::RETRY_AFTER, RETRY_AFTER_SECONDS.to_string()))
+        .finish();
+    InternalError::from_response("Moon is shutting down", response).into()
+}
+
+/// Stops accepting new `UpMsg`s and message connections.
+pub(crate) fn begin() {
+    SHUTDOWN.phase.send_replace(Phase::Draining);
+}
+
+/// Waits for running handlers and calls shutdown hooks.
+/// Both steps are limited by `CONFIG.shutdown_timeout`.
+pub(crate) async fn drain() {
+    let shutdown_timeout = Duration::from_secs(CONFIG.shutdown_timeout);
+
+    let mut handler_finished = SHUTDOWN.handler_finished.subscribe();
+    let wait_for_handlers = async {
+        while SHUTDOWN.running_handlers.load(Ordering::SeqCst) > 0 {
+            let _ = handler_finished.changed().await;
+        }
+    };
+    if timeout(shutdown_timeout, wait_for_handlers).await.is_err() {
+        eprintln!(
+            "Running UpMsg handlers haven't finished in {} seconds",
+            CONFIG.shutdown_timeout
+        );
+    }
+
+    let hooks = SHUTDOWN.hooks.lock().drain(..).collect::<Vec<_>>();
+    let call_hooks = async {
+        for hook in hooks {
+            hook().await;
+        }
+    };
+    if timeout(shutdown_timeout, call_hooks).await.is_err() {
+        eprintln!(
+            "Shutdown hooks haven't finished in {} seconds",
+            CONFIG.shutdown_timeout
+        );
+    }
+    SHUTDOWN.phase.send_replace(Phase::Drained);
+}
+
+/// Resolves when connections can be closed.
+pub(crate) async fn drained() {
+    let mut phase = SHUTDOWN.phase.subscribe();
+    // the sender lives in a static so it's never dropped
+    let _ = phase.wait_for(|phase| *phase == Phase::Drained).await;
+}
+
+// ------ HandlerGuard ------
+
+pub(crate) struct HandlerGuard;
+
+impl Drop for HandlerGuard {
+    fn drop(&mut self) {
+        SHUTDOWN.running_handlers.fetch_sub(1, Ordering::SeqCst);
+        SHUTDOWN.handler_finished.send_replace(());
+    }
+}
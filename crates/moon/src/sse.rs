@@ -1,11 +1,15 @@
-use crate::actor::{sessions, Index};
+use crate::actor::sessions;
+use crate::not;
 use actix_web::web::Bytes;
 use actix_web::{rt, Error};
 use chashmap::CHashMap;
 use futures::Stream;
 use moonlight::SessionId;
+use parking_lot::Mutex;
 use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::Duration;
@@ -20,27 +24,108 @@ pub struct Connection {
     remove_session_actor_on_remove: bool,
     session_id: SessionId,
     sender: UnboundedSender<Bytes>,
+    replay_buffer: Arc<Mutex<ReplayBuffer>>,
 }
 
 impl Connection {
-    fn new(session_id: Option<SessionId>) -> (Arc<Connection>, EventStream) {
-        let (sender, receiver) = unbounded_channel();
-        let connection = Arc::new(Self {
-            remove_session_actor_on_remove: session_id.is_some(),
-            session_id: session_id.unwrap_or_else(SessionId::new),
-            sender,
-        });
-        (connection, EventStream(receiver))
-    }
-
     fn session_id(&self) -> SessionId {
         self.session_id
     }
 
     pub fn send(&self, event: &str, data: &str) -> Result<(), SendError<Bytes>> {
-        let message = Bytes::from(["event: ", event, "\n", "data: ", data, "\n\n"].concat());
+        self.replay_buffer.lock().send(event, data)
+    }
+
+    fn send_ping(&self) -> Result<(), SendError<Bytes>> {
+        self.sender
+            .send(Bytes::from_static(b"event: ping\ndata: \n\n"))
+    }
+}
+
+// ------ ReplayBuffer ------
+
+struct BufferedEvent {
+    id: u64,
+    created_at: Instant,
+    message: Bytes,
+}
+
+/// Assigns monotonically increasing ids to the session's events
+/// and keeps the latest ones to replay them to the reconnected client.
+///
+/// Events are sent to the session's latest connection,
+/// so they can't overtake the replayed ones.
+struct ReplayBuffer {
+    sender: UnboundedSender<Bytes>,
+    last_event_id: u64,
+    events: VecDeque<BufferedEvent>,
+    max_events: usize,
+    max_age: Duration,
+}
+
+impl ReplayBuffer {
+    fn new(sender: UnboundedSender<Bytes>, max_events: usize, max_age: Duration) -> Self {
+        Self {
+            sender,
+            last_event_id: 0,
+            events: VecDeque::new(),
+            max_events,
+            max_age,
+        }
+    }
+
+    fn send(&mut self, event: &str, data: &str) -> Result<(), SendError<Bytes>> {
+        self.last_event_id += 1;
+        let id = self.last_event_id.to_string();
+        let message = Bytes::from(
+            [
+                "id: ",
+                id.as_str(),
+                "\n",
+                "event: ",
+                event,
+                "\n",
+                "data: ",
+                data,
+                "\n\n",
+            ]
+            .concat(),
+        );
+        if self.max_events > 0 {
+            self.events.push_back(BufferedEvent {
+                id: self.last_event_id,
+                created_at: Instant::now(),
+                message: message.clone(),
+            });
+            if self.events.len() > self.max_events {
+                self.events.pop_front();
+            }
+            self.remove_expired();
+        }
         self.sender.send(message)
     }
+
+    /// Events are replayed only to the client that knows its last event id,
+    /// other clients (e.g. reloaded pages) would receive already processed events again.
+    fn replay(&mut self, last_event_id: Option<u64>, sender: UnboundedSender<Bytes>) {
+        if let Some(last_event_id) = last_event_id {
+            for event in &self.events {
+                if event.id > last_event_id {
+                    let _ = sender.send(event.message.clone());
+                }
+            }
+        }
+        self.sender = sender;
+    }
+
+    fn remove_expired(&mut self) {
+        while let Some(event) = self.events.front() {
+            if event.created_at.elapsed() <= self.max_age {
+                break;
+            }
+            self.events.pop_front();
+        }
+    }
 }
 
 // ------ EventStream ------
@@ -63,17 +148,107 @@ impl Stream for EventStream {
 
 pub struct SSE {
     connections: CHashMap<SessionId, Arc<Connection>>,
+    replay_buffers: CHashMap<SessionId, Arc<Mutex<ReplayBuffer>>>,
+    /// Sessions with lost connections and the moments they were lost.
+    /// They're kept alive for `replay_buffer_max_age` so the clients can reconnect.
+    disconnected_sessions: CHashMap<SessionId, Instant>,
+    /// Ids of the latest WebSocket connections of sessions.
+    web_socket_connections: CHashMap<SessionId, u64>,
+    next_web_socket_connection_id: AtomicU64,
+    replay_buffer_size: usize,
+    replay_buffer_max_age: Duration,
 }
 
 impl SSE {
     pub fn start() -> ShareableSSE {
+        Self::start_with_replay_buffer(0, Duration::ZERO)
+    }
+
+    pub fn start_with_replay_buffer(
+        replay_buffer_size: usize,
+        replay_buffer_max_age: Duration,
+    ) -> ShareableSSE {
         let sse = SSE {
             connections: CHashMap::new(),
+            replay_buffers: CHashMap::new(),
+            disconnected_sessions: CHashMap::new(),
+            web_socket_connections: CHashMap::new(),
+            next_web_socket_connection_id: AtomicU64::new(0),
+            replay_buffer_size,
+            replay_buffer_max_age,
         };
         let this = Arc::new(sse);
         this.spawn_connection_remover();
         this
     }
+
+    fn connection_lost(&self, connection: &Connection) {
+        if connection.remove_session_actor_on_remove {
+            self.disconnected_sessions
+                .insert(connection.session_id(), Instant::now());
+        }
+    }
+
+    /// The session is removed by the connection remover
+    /// unless it's reconnected within `replay_buffer_max_age`.
+    ///
+    /// Returns the connection id for `remove_web_socket_connection`.
+    pub fn new_web_socket_connection(&self, session_id: SessionId) -> u64 {
+        let connection_id = self
+            .next_web_socket_connection_id
+            .fetch_add(1, Ordering::Relaxed);
+        self.disconnected_sessions.remove(&session_id);
+        self.web_socket_connections
+            .insert(session_id, connection_id);
+        connection_id
+    }
+
+    /// The session isn't marked as disconnected if it has already opened a new connection.
+    pub fn remove_web_socket_connection(&self, session_id: SessionId, connection_id: u64) {
+        let mut is_latest_connection = false;
+        self.web_socket_connections
+            .alter(session_id, |latest_connection_id| {
+                is_latest_connection = latest_connection_id == Some(connection_id);
+                latest_connection_id.filter(|_| not(is_latest_connection))
+            });
+        // a mark of the reconnected session is removed by the connection remover
+        if is_latest_connection {
+            self.disconnected_sessions
+                .insert(session_id, Instant::now());
+        }
+    }
+
+    fn remove_inactive_connections(&self) {
+        self.connections.retain(|_, connection| {
+            let active = connection.send_ping().is_ok();
+            if not(active) {
+                self.connection_lost(connection);
+            }
+            active
+        });
+        self.disconnected_sessions
+            .retain(|session_id, disconnected_at| {
+                if self.connections.contains_key(session_id)
+                    || self.web_socket_connections.contains_key(session_id)
+                {
+                    return false;
+                }
+                if disconnected_at.elapsed() <= self.replay_buffer_max_age {
+                    return true;
+                }
+                sessions::remove_session(*session_id);
+                false
+            });
+        // keep buffers of disconnected sessions until they expire
+        // so the reconnecting clients can still receive their events
+        self.replay_buffers.retain(|session_id, replay_buffer| {
+            if self.connections.contains_key(session_id) {
+                return true;
+            }
+            replay_buffer.lock().remove_expired();
+            self.disconnected_sessions.contains_key(session_id)
+        });
+    }
 }
 
 // ------ ShareableSSEMethods ------
@@ -81,7 +256,11 @@ impl SSE {
 pub trait ShareableSSEMethods {
     fn spawn_connection_remover(&self);
 
-    fn new_connection(&self, session_id: Option<SessionId>) -> (Arc<Connection>, EventStream);
+    fn new_connection(
+        &self,
+        session_id: Option<SessionId>,
+        last_event_id: Option<u64>,
+    ) -> (Arc<Connection>, EventStream);
 
     fn broadcast(&self, event: &str, data: &str) -> Result<(), Vec<SendError<Bytes>>>;
 
@@ -95,6 +274,11 @@ pub trait ShareableSSEMethods {
     // @TODO why is it a dead code since Rust 1.78.0?
     #[allow(dead_code)]
     fn remove_connection(&self, session_id: &SessionId);
+
+    /// Ends all event streams, e.g. on shutdown.
+    fn close_all_connections(&self);
+
+    fn connection_count(&self) -> usize;
 }
 
 impl ShareableSSEMethods for ShareableSSE {
@@ -104,24 +288,47 @@ impl ShareableSSEMethods for ShareableSSE {
             let mut interval = interval_at(Instant::now(), Duration::from_secs(10));
             loop {
                 interval.tick().await;
-                this.connections.retain(|session_id, connection| {
-                    let active = connection.send("ping", "").is_ok();
-                    if !active && connection.remove_session_actor_on_remove {
-                        if let Some(session_actor) = sessions::by_session_id().get(session_id) {
-                            session_actor.remove();
-                        }
-                    }
-                    active
-                });
+                this.remove_inactive_connections();
             }
         });
     }
 
-    fn new_connection(&self, session_id: Option<SessionId>) -> (Arc<Connection>, EventStream) {
-        let (connection, event_stream) = Connection::new(session_id);
+    fn new_connection(
+        &self,
+        session_id: Option<SessionId>,
+        last_event_id: Option<u64>,
+    ) -> (Arc<Connection>, EventStream) {
+        let (sender, receiver) = unbounded_channel();
+
+        let existing_replay_buffer = session_id
+            .and_then(|session_id| self.replay_buffers.get(&session_id))
+            .map(|replay_buffer| replay_buffer.clone());
+
+        let replay_buffer = if let Some(replay_buffer) = existing_replay_buffer {
+            replay_buffer.lock().replay(last_event_id, sender.clone());
+            replay_buffer
+        } else {
+            Arc::new(Mutex::new(ReplayBuffer::new(
+                sender.clone(),
+                self.replay_buffer_size,
+                self.replay_buffer_max_age,
+            )))
+        };
+
+        let connection = Arc::new(Connection {
+            remove_session_actor_on_remove: session_id.is_some(),
+            session_id: session_id.unwrap_or_else(SessionId::new),
+            sender,
+            replay_buffer,
+        });
+        self.disconnected_sessions.remove(&connection.session_id());
+        self.replay_buffers.insert(
+            connection.session_id(),
+            Arc::clone(&connection.replay_buffer),
+        );
         self.connections
             .insert(connection.session_id(), connection.clone());
-        (connection, event_stream)
+        (connection, EventStream(receiver))
     }
 
     fn broadcast(&self, event: &str, data: &str) -> Result<(), Vec<SendError<Bytes>>> {
@@ -145,21 +352,115 @@ impl ShareableSSEMethods for ShareableSSE {
         event: &str,
         data: &str,
     ) -> Option<Result<(), SendError<Bytes>>> {
-        // @TODO Last-Event-Id
-        self.connections
-            .get(session_id)
-            .map(|connection| connection.send(event, data))
+        if let Some(connection) = self.connections.get(session_id) {
+            return Some(connection.send(event, data));
+        }
+        // the event is buffered for the reconnecting client
+        let replay_buffer = self.replay_buffers.get(session_id)?;
+        let _ = replay_buffer.lock().send(event, data);
+        Some(Ok(()))
     }
 
     fn remove_connection(&self, session_id: &SessionId) {
-        let connection = self.connections.remove(session_id);
+        if let Some(connection) = self.connections.remove(session_id) {
+            self.connection_lost(&connection);
+        }
+    }
 
-        if let Some(connection) = connection {
-            if connection.remove_session_actor_on_remove {
-                if let Some(session_actor) = sessions::by_session_id().get(session_id) {
-                    session_actor.remove();
-                }
-            }
+    fn close_all_connections(&self) {
+        // Replay buffers own senders, too.
+        self.connections.clear();
+        self.replay_buffers.clear();
+        self.disconnected_sessions.clear();
+        self.web_socket_connections.clear();
+    }
+
+    fn connection_count(&self) -> usize {
+        self.connections.len()
+    }
+}
+
+// ====== ====== TESTS ====== ======
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_buffer_replays_events_after_last_event_id() {
+        // ------ ARRANGE ------
+        let (sender, _receiver) = unbounded_channel();
+        let mut replay_buffer = ReplayBuffer::new(sender, 2, Duration::from_secs(60));
+        for data in ["a", "b", "c"] {
+            replay_buffer.send("down_msg", data).unwrap();
         }
+        let (new_sender, mut new_receiver) = unbounded_channel();
+
+        // ------ ACT ------
+        replay_buffer.replay(Some(2), new_sender);
+        replay_buffer.send("down_msg", "d").unwrap();
+
+        // ------ ASSERT ------
+        assert_eq!(
+            new_receiver.try_recv().unwrap(),
+            Bytes::from_static(b"id: 3\nevent: down_msg\ndata: c\n\n")
+        );
+        assert_eq!(
+            new_receiver.try_recv().unwrap(),
+            Bytes::from_static(b"id: 4\nevent: down_msg\ndata: d\n\n")
+        );
+        assert!(new_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_replay_buffer_doesnt_replay_events_without_last_event_id() {
+        // ------ ARRANGE ------
+        let (sender, _receiver) = unbounded_channel();
+        let mut replay_buffer = ReplayBuffer::new(sender, 2, Duration::from_secs(60));
+        replay_buffer.send("down_msg", "a").unwrap();
+        let (new_sender, mut new_receiver) = unbounded_channel();
+
+        // ------ ACT ------
+        replay_buffer.replay(None, new_sender);
+        replay_buffer.send("down_msg", "b").unwrap();
+
+        // ------ ASSERT ------
+        assert_eq!(
+            new_receiver.try_recv().unwrap(),
+            Bytes::from_static(b"id: 2\nevent: down_msg\ndata: b\n\n")
+        );
+        assert!(new_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_client_reconnects_after_connection_remover_has_run() {
+        // ------ ARRANGE ------
+        let sse: ShareableSSE = Arc::new(SSE {
+            connections: CHashMap::new(),
+            replay_buffers: CHashMap::new(),
+            disconnected_sessions: CHashMap::new(),
+            web_socket_connections: CHashMap::new(),
+            next_web_socket_connection_id: AtomicU64::new(0),
+            replay_buffer_size: 10,
+            replay_buffer_max_age: Duration::from_secs(60),
+        });
+        let session_id = SessionId::new();
+        let (_, event_stream) = sse.new_connection(Some(session_id), None);
+        sse.send(&session_id, "down_msg", "a").unwrap().unwrap();
+        drop(event_stream);
+
+        // ------ ACT ------
+        sse.remove_inactive_connections();
+        let sent_while_disconnected = sse.send(&session_id, "down_msg", "b");
+        let (_, EventStream(mut receiver)) = sse.new_connection(Some(session_id), Some(1));
+
+        // ------ ASSERT ------
+        assert!(matches!(sent_while_disconnected, Some(Ok(()))));
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            Bytes::from_static(b"id: 2\nevent: down_msg\ndata: b\n\n")
+        );
+        assert!(receiver.try_recv().is_err());
+        assert!(sse.disconnected_sessions.is_empty());
     }
 }
@@ -0,0 +1,26 @@
+use moonlight::{Codec, CodecError};
+
+#[cfg(feature = "serde")]
+use moonlight::Serialize;
+
+/// The output of `up_msg_handler`.
+///
+/// Return `Err(error)` to send `error` back to Zoon,
+/// where it's available as `SendUpMsgError::UpMsgHandlerError(error)`.
+pub trait UpMsgHandlerResult {
+    /// Returns `None` if the `UpMsg` has been handled successfully.
+    fn encode_error(self, codec: Codec) -> Option<Result<Vec<u8>, CodecError>>;
+}
+
+impl UpMsgHandlerResult for () {
+    fn encode_error(self, _: Codec) -> Option<Result<Vec<u8>, CodecError>> {
+        None
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<E: Serialize> UpMsgHandlerResult for Result<(), E> {
+    fn encode_error(self, codec: Codec) -> Option<Result<Vec<u8>, CodecError>> {
+        self.err().map(|error| codec.encode(&error))
+    }
+}
@@ -1,3 +1,4 @@
+use crate::auth::Identity;
 use moonlight::{AuthToken, CorId, SessionId};
 
 #[derive(Debug)]
@@ -6,4 +7,7 @@ pub struct UpMsgRequest<UMsg> {
     pub session_id: SessionId,
     pub cor_id: CorId,
     pub auth_token: Option<AuthToken>,
+    /// Verified by the configured `Authenticator`.
+    /// `None` if there is no auth token or no `Authenticator` is configured.
+    pub identity: Option<Identity>,
 }
@@ -4,6 +4,12 @@ use crate::*;
 pub struct AuthToken(String);
 
 impl AuthToken {
+    /// Cookie read by the message SSE and WebSocket handshakes.
+    pub const COOKIE_NAME: &'static str = "moon_auth_token";
+    /// Query parameter read by the message SSE and WebSocket handshakes
+    /// when the cookie isn't set.
+    pub const QUERY_PARAM: &'static str = "auth_token";
+
     pub fn new(token: impl ToString) -> Self {
         AuthToken(token.to_string())
     }
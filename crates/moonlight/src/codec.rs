@@ -0,0 +1,442 @@
+use crate::*;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use std::{error::Error, fmt, hash, str::FromStr, sync::RwLock};
+
+/// Codecs registered by `Codec::register`.
+static REGISTERED_CODECS: RwLock<Vec<Codec>> = RwLock::new(Vec::new());
+
+// ------ CodecFormat ------
+
+/// Format of the serialized `UpMsg`s and `DownMsg`s.
+///
+/// Implement it and call `Codec::register` on both Moon and Zoon to add a codec.
+/// Messages are passed as `EncodeMessage` and `DecodeMessage` so the codec can be a trait object,
+/// a custom codec transcodes one of the built-in formats.
+pub trait CodecFormat: Send + Sync + 'static {
+    /// Selects the codec in the query parameter `Codec::QUERY_PARAM`.
+    fn name(&self) -> &'static str;
+
+    /// Value of the header `Content-Type` for the `UpMsg` requests.
+    fn content_type(&self) -> &'static str;
+
+    /// Binary payloads are encoded to Base64 when they have to be sent as text (e.g. in SSE).
+    fn is_binary(&self) -> bool;
+
+    #[cfg(feature = "serde")]
+    fn encode(&self, message: &dyn EncodeMessage) -> Result<Vec<u8>, CodecError>;
+
+    /// Stores the decoded message in `message`.
+    #[cfg(feature = "serde")]
+    fn decode(&self, bytes: &[u8], message: &mut dyn DecodeMessage) -> Result<(), CodecError>;
+}
+
+// ------ EncodeMessage ------
+
+/// A message that can be serialized to the built-in formats. Implemented for all `Serialize` types.
+#[cfg(feature = "serde")]
+pub trait EncodeMessage {
+    fn encode_json(&self) -> Result<Vec<u8>, CodecError>;
+
+    #[cfg(feature = "msgpack")]
+    fn encode_msgpack(&self) -> Result<Vec<u8>, CodecError>;
+}
+
+#[cfg(feature = "serde")]
+impl<T: Serialize> EncodeMessage for T {
+    fn encode_json(&self) -> Result<Vec<u8>, CodecError> {
+        serde_json::to_vec(self).map_err(CodecError::Json)
+    }
+
+    #[cfg(feature = "msgpack")]
+    fn encode_msgpack(&self) -> Result<Vec<u8>, CodecError> {
+        rmp_serde::to_vec_named(self).map_err(CodecError::MessagePackEncode)
+    }
+}
+
+// ------ DecodeMessage ------
+
+/// A slot for a message deserialized from the built-in formats.
+/// Implemented for `Option<T>` where `T: DeserializeOwned`.
+#[cfg(feature = "serde")]
+pub trait DecodeMessage {
+    fn decode_json(&mut self, json: &[u8]) -> Result<(), CodecError>;
+
+    #[cfg(feature = "msgpack")]
+    fn decode_msgpack(&mut self, bytes: &[u8]) -> Result<(), CodecError>;
+}
+
+#[cfg(feature = "serde")]
+impl<T: DeserializeOwned> DecodeMessage for Option<T> {
+    fn decode_json(&mut self, json: &[u8]) -> Result<(), CodecError> {
+        *self = Some(serde_json::from_slice(json).map_err(CodecError::Json)?);
+        Ok(())
+    }
+
+    #[cfg(feature = "msgpack")]
+    fn decode_msgpack(&mut self, bytes: &[u8]) -> Result<(), CodecError> {
+        *self = Some(rmp_serde::from_slice(bytes).map_err(CodecError::MessagePackDecode)?);
+        Ok(())
+    }
+}
+
+// ------ JsonCodec ------
+
+pub struct JsonCodec;
+
+impl CodecFormat for JsonCodec {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+
+    fn is_binary(&self) -> bool {
+        false
+    }
+
+    #[cfg(feature = "serde")]
+    fn encode(&self, message: &dyn EncodeMessage) -> Result<Vec<u8>, CodecError> {
+        message.encode_json()
+    }
+
+    #[cfg(feature = "serde")]
+    fn decode(&self, bytes: &[u8], message: &mut dyn DecodeMessage) -> Result<(), CodecError> {
+        message.decode_json(bytes)
+    }
+}
+
+// ------ MessagePackCodec ------
+
+#[cfg(feature = "msgpack")]
+pub struct MessagePackCodec;
+
+#[cfg(feature = "msgpack")]
+impl CodecFormat for MessagePackCodec {
+    fn name(&self) -> &'static str {
+        "msgpack"
+    }
+
+    fn content_type(&self) -> &'static str {
+        "application/msgpack"
+    }
+
+    fn is_binary(&self) -> bool {
+        true
+    }
+
+    fn encode(&self, message: &dyn EncodeMessage) -> Result<Vec<u8>, CodecError> {
+        message.encode_msgpack()
+    }
+
+    fn decode(&self, bytes: &[u8], message: &mut dyn DecodeMessage) -> Result<(), CodecError> {
+        message.decode_msgpack(bytes)
+    }
+}
+
+// ------ Codec ------
+
+/// The `CodecFormat` selected for a connection. Codecs are equal when they have the same name.
+#[derive(Clone, Copy)]
+pub struct Codec(&'static dyn CodecFormat);
+
+// The built-in codecs are named like variants to keep `Codec::Json` working.
+#[allow(non_upper_case_globals)]
+impl Codec {
+    pub const Json: Self = Self(&JsonCodec);
+
+    #[cfg(feature = "msgpack")]
+    pub const MessagePack: Self = Self(&MessagePackCodec);
+}
+
+impl Codec {
+    /// Query parameter that selects the codec for the message SSE and WebSocket.
+    pub const QUERY_PARAM: &'static str = "codec";
+
+    /// Makes the codec available to `Codec::from_str` and `Codec::from_content_type`.
+    /// Built-in and already registered codecs aren't replaced.
+    pub fn register(format: &'static dyn CodecFormat) -> Self {
+        let mut registered_codecs = REGISTERED_CODECS.write().unwrap();
+        if let Some(codec) = Self::find(&registered_codecs, |codec| codec.name() == format.name()) {
+            return codec;
+        }
+        let codec = Self(format);
+        registered_codecs.push(codec);
+        codec
+    }
+
+    fn built_in() -> impl Iterator<Item = Self> {
+        [
+            Self::Json,
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack,
+        ]
+        .into_iter()
+    }
+
+    fn find(registered_codecs: &[Self], predicate: impl Fn(&Self) -> bool) -> Option<Self> {
+        Self::built_in()
+            .chain(registered_codecs.iter().copied())
+            .find(predicate)
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.0.name()
+    }
+
+    /// Value of the header `Content-Type` for the `UpMsg` requests.
+    pub fn content_type(&self) -> &'static str {
+        self.0.content_type()
+    }
+
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        Self::find(&REGISTERED_CODECS.read().unwrap(), |codec| {
+            codec.content_type() == content_type
+        })
+    }
+
+    pub fn is_binary(&self) -> bool {
+        self.0.is_binary()
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+        self.0.encode(value)
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn encode_to_string<T: Serialize>(&self, value: &T) -> Result<String, CodecError> {
+        let bytes = self.encode(value)?;
+        if self.is_binary() {
+            return Ok(BASE64.encode(bytes));
+        }
+        String::from_utf8(bytes).map_err(|error| CodecError::Other(Box::new(error)))
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, CodecError> {
+        let mut message = None;
+        self.0.decode(bytes, &mut message)?;
+        message.ok_or_else(|| {
+            CodecError::Other(format!("codec '{self}' hasn't decoded the message").into())
+        })
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn decode_from_str<T: DeserializeOwned>(&self, text: &str) -> Result<T, CodecError> {
+        if self.is_binary() {
+            let bytes = BASE64.decode(text).map_err(CodecError::Base64)?;
+            return self.decode(&bytes);
+        }
+        self.decode(text.as_bytes())
+    }
+}
+
+impl Default for Codec {
+    fn default() -> Self {
+        Self::Json
+    }
+}
+
+impl PartialEq for Codec {
+    fn eq(&self, other: &Self) -> bool {
+        self.name() == other.name()
+    }
+}
+
+impl Eq for Codec {}
+
+impl hash::Hash for Codec {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.name().hash(state)
+    }
+}
+
+impl fmt::Debug for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Codec").field(&self.name()).finish()
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Codec {
+    type Err = CodecError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::find(&REGISTERED_CODECS.read().unwrap(), |codec| {
+            codec.name() == name
+        })
+        .ok_or_else(|| CodecError::UnknownCodec(name.to_owned()))
+    }
+}
+
+// ------ CodecError ------
+
+#[derive(Debug)]
+pub enum CodecError {
+    UnknownCodec(String),
+    Json(serde_json::Error),
+    #[cfg(feature = "msgpack")]
+    MessagePackEncode(rmp_serde::encode::Error),
+    #[cfg(feature = "msgpack")]
+    MessagePackDecode(rmp_serde::decode::Error),
+    Base64(base64::DecodeError),
+    /// Errors of custom codecs.
+    Other(Box<dyn Error + Send + Sync>),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownCodec(name) => write!(f, "unknown codec '{name}'"),
+            Self::Json(error) => write!(f, "JSON (de)serialization failed: {error}"),
+            #[cfg(feature = "msgpack")]
+            Self::MessagePackEncode(error) => {
+                write!(f, "MessagePack serialization failed: {error}")
+            }
+            #[cfg(feature = "msgpack")]
+            Self::MessagePackDecode(error) => {
+                write!(f, "MessagePack deserialization failed: {error}")
+            }
+            Self::Base64(error) => write!(f, "Base64 decoding failed: {error}"),
+            Self::Other(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl Error for CodecError {}
+
+// ====== ====== TESTS ====== ======
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Message {
+        id: u128,
+        bytes: Vec<u8>,
+        ratio: f64,
+        names: HashMap<u32, String>,
+    }
+
+    fn message(ratio: f64) -> Message {
+        Message {
+            id: u128::MAX,
+            bytes: vec![0, 1, 255],
+            ratio,
+            names: HashMap::from([(1, "a".to_owned())]),
+        }
+    }
+
+    /// JSON with a custom content type.
+    struct TestCodec;
+
+    impl CodecFormat for TestCodec {
+        fn name(&self) -> &'static str {
+            "test"
+        }
+
+        fn content_type(&self) -> &'static str {
+            "application/x-test"
+        }
+
+        fn is_binary(&self) -> bool {
+            false
+        }
+
+        fn encode(&self, message: &dyn EncodeMessage) -> Result<Vec<u8>, CodecError> {
+            message.encode_json()
+        }
+
+        fn decode(&self, bytes: &[u8], message: &mut dyn DecodeMessage) -> Result<(), CodecError> {
+            message.decode_json(bytes)
+        }
+    }
+
+    #[test]
+    fn test_built_in_codec_negotiation() {
+        // ------ ACT ------
+        let by_name = "json".parse::<Codec>();
+        let by_content_type = Codec::from_content_type("application/json");
+        let unknown_name = "xml".parse::<Codec>();
+        let unknown_content_type = Codec::from_content_type("text/plain");
+
+        // ------ ASSERT ------
+        assert_eq!(by_name.unwrap(), Codec::Json);
+        assert_eq!(by_content_type, Some(Codec::Json));
+        assert!(matches!(unknown_name, Err(CodecError::UnknownCodec(name)) if name == "xml"));
+        assert_eq!(unknown_content_type, None);
+    }
+
+    #[test]
+    fn test_registered_codec_negotiation() {
+        // ------ ACT ------
+        let codec = Codec::register(&TestCodec);
+        let registered_again = Codec::register(&TestCodec);
+        let by_name = "test".parse::<Codec>();
+        let by_content_type = Codec::from_content_type("application/x-test");
+        let encoded = codec.encode_to_string(&message(0.5)).unwrap();
+
+        // ------ ASSERT ------
+        assert_eq!(registered_again, codec);
+        assert_eq!(by_name.unwrap(), codec);
+        assert_eq!(by_content_type, Some(codec));
+        assert_ne!(codec, Codec::Json);
+        assert_eq!(
+            codec.decode_from_str::<Message>(&encoded).unwrap(),
+            message(0.5)
+        );
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        // ------ ARRANGE ------
+        let codec = Codec::Json;
+
+        // ------ ACT ------
+        let bytes = codec.encode(&message(0.5)).unwrap();
+        let text = codec.encode_to_string(&message(0.5)).unwrap();
+
+        // ------ ASSERT ------
+        assert_eq!(codec.decode::<Message>(&bytes).unwrap(), message(0.5));
+        assert_eq!(
+            codec.decode_from_str::<Message>(&text).unwrap(),
+            message(0.5)
+        );
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_message_pack_round_trip() {
+        // ------ ARRANGE ------
+        let codec = Codec::MessagePack;
+
+        // ------ ACT ------
+        let bytes = codec.encode(&message(f64::INFINITY)).unwrap();
+        let text = codec.encode_to_string(&message(f64::INFINITY)).unwrap();
+
+        // ------ ASSERT ------
+        assert_eq!(
+            bytes,
+            rmp_serde::to_vec_named(&message(f64::INFINITY)).unwrap()
+        );
+        assert_eq!(
+            codec.decode::<Message>(&bytes).unwrap(),
+            message(f64::INFINITY)
+        );
+        assert_eq!(
+            codec.decode_from_str::<Message>(&text).unwrap(),
+            message(f64::INFINITY)
+        );
+        assert_eq!("msgpack".parse::<Codec>().unwrap(), codec);
+        assert_eq!(Codec::from_content_type("application/msgpack"), Some(codec));
+    }
+}
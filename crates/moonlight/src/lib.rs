@@ -16,6 +16,13 @@ pub use chrono::{self, prelude::*, Duration};
 mod auth_token;
 pub use auth_token::AuthToken;
 
+mod codec;
+#[cfg(feature = "msgpack")]
+pub use codec::MessagePackCodec;
+pub use codec::{Codec, CodecError, CodecFormat, JsonCodec};
+#[cfg(feature = "serde")]
+pub use codec::{DecodeMessage, EncodeMessage};
+
 mod cor_id;
 pub use cor_id::CorId;
 
@@ -27,3 +34,11 @@ pub use entity_id::EntityId;
 
 mod session_id;
 pub use session_id::SessionId;
+
+mod up_msg_transporter;
+pub use up_msg_transporter::{UpMsgTransporterForDe, UpMsgTransporterForSer};
+
+mod web_socket_frame;
+pub use web_socket_frame::{
+    UpMsgFailure, UpMsgResultTransporter, WebSocketFrameForDe, WebSocketFrameForSer,
+};
@@ -0,0 +1,16 @@
+use crate::*;
+
+#[derive(Serialize)]
+pub struct UpMsgTransporterForSer<'a, UMsg: Serialize> {
+    pub up_msg: &'a UMsg,
+    pub cor_id: CorId,
+    pub auth_token: Option<AuthToken>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+pub struct UpMsgTransporterForDe<UMsg> {
+    pub up_msg: UMsg,
+    pub cor_id: CorId,
+    pub auth_token: Option<AuthToken>,
+}
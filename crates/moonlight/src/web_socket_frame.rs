@@ -0,0 +1,32 @@
+use crate::*;
+
+/// Messages sent from Moon to Zoon through WebSocket.
+#[derive(Serialize)]
+pub enum WebSocketFrameForSer<'a, DMsg: Serialize> {
+    DownMsg(DownMsgTransporterForSer<'a, DMsg>),
+    UpMsgResult(UpMsgResultTransporter),
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+pub enum WebSocketFrameForDe<DMsg> {
+    DownMsg(DownMsgTransporterForDe<DMsg>),
+    UpMsgResult(UpMsgResultTransporter),
+}
+
+/// Sent once the `UpMsg` with the `cor_id` has been handled or rejected.
+#[derive(Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+pub struct UpMsgResultTransporter {
+    pub cor_id: CorId,
+    pub result: Result<(), UpMsgFailure>,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+pub enum UpMsgFailure {
+    /// The error returned from the `up_msg_handler`, encoded by the connection's `Codec`.
+    HandlerError(Vec<u8>),
+    /// The `UpMsg` hasn't been passed to the `up_msg_handler`, e.g. because of an invalid auth token.
+    Rejected(String),
+}
@@ -1,10 +1,11 @@
+use crate::cargo_diagnostics::{collect_diagnostics, CompilationError, MESSAGE_FORMAT_ARG};
 use crate::BuildMode;
 use anyhow::{anyhow, Context, Error};
 use apply::Apply;
-use bool_ext::BoolExt;
 use fehler::throws;
 use rcgen::{Certificate, CertificateParams};
 use std::path::Path;
+use std::process::Stdio;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::{fs, process::Command, try_join};
 use uuid::Uuid;
@@ -19,7 +20,7 @@ pub async fn build_backend(build_mode: BuildMode, https: bool) {
         write_new_certificate_if_not_present().await?;
     }
 
-    let mut args = vec!["build", "--bin", "backend"];
+    let mut args = vec!["build", "--bin", "backend", MESSAGE_FORMAT_ARG];
     match build_mode {
         BuildMode::Dev => (),
         BuildMode::Profiling => args.extend(["--profile", "profiling"]),
@@ -40,14 +41,28 @@ pub async fn build_backend(build_mode: BuildMode, https: bool) {
         .into_iter()
         .map(|(key, value)| (format!("CARGO_PROFILE_{profile_env_name}_{key}"), value));
 
-    Command::new("cargo")
+    let mut process = Command::new("cargo")
         .args(&args)
         .envs(envs)
-        .status()
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to start backend compilation")?;
+    let stdout = process
+        .stdout
+        .take()
+        .ok_or(anyhow!("Failed to capture backend compilation output"))?;
+    let diagnostics = collect_diagnostics("backend", stdout).await;
+    let success = process
+        .wait()
         .await
-        .context("Failed to get frontend build status")?
-        .success()
-        .err(anyhow!("Failed to build backend"))?;
+        .context("Failed to get backend build status")?
+        .success();
+    if !success {
+        Err(CompilationError {
+            crate_name: "backend".to_owned(),
+            diagnostics,
+        })?
+    }
 
     write_new_build_id().await?;
     println!("Backend built");
@@ -1,24 +1,46 @@
+use crate::cargo_diagnostics::{
+    collect_diagnostics, CompilationError, CompilationStopped, MESSAGE_FORMAT_ARG,
+};
+use crate::config::Tools;
 use crate::helper::{
-    visit_files,
+    compressed_file_path, visit_files,
     workspace_member::{web_worker_workspace_members, WorkspaceMember},
     AsyncReadToVec, BrotliFileCompressor, FileCompressor, GzipFileCompressor,
 };
-use crate::wasm_bindgen::{build_with_wasm_bindgen, check_or_install_wasm_bindgen};
-use crate::wasm_opt::{check_or_install_wasm_opt, optimize_with_wasm_opt};
+use crate::wasm_bindgen::{build_with_wasm_bindgen, check_or_install_wasm_bindgen, WasmBindgen};
+use crate::wasm_opt::{self, check_or_install_wasm_opt, optimize_with_wasm_opt};
 use crate::BuildMode;
 use anyhow::{anyhow, Context, Error};
-use bool_ext::BoolExt;
+use cargo_metadata::MetadataCommand;
 use fehler::throws;
-use futures::TryStreamExt;
+use fs_extra::dir;
+use futures::{stream, TryStreamExt};
+use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
 use std::{
+    collections::BTreeMap,
     env, future,
+    num::NonZeroUsize,
     path::{Path, PathBuf},
+    process::Stdio,
     str,
     sync::Arc,
+    thread,
 };
-use tokio::{fs, process::Command, select, sync::watch, try_join};
+use tokio::{fs, process::Command, select, sync::watch, task, try_join};
 use uuid::Uuid;
 
+const PUBLIC_DIR: &str = "public";
+const PUBLIC_ASSETS_DIR: &str = "frontend/pkg/public";
+/// Maps paths relative to `public` to fingerprinted paths relative to `frontend/pkg/public`.
+const PUBLIC_ASSETS_MANIFEST_PATH: &str = "frontend/pkg/public_manifest.json";
+
+/// Compiled `pkg`s are cached in `target/wasm32-unknown-unknown/<profile>/mzoon_pkg/<crate>`.
+const PKG_CACHE_DIR: &str = "mzoon_pkg";
+const PKG_CACHE_FINGERPRINT_FILE: &str = "fingerprint";
+/// Brotli and Gzip.
+const COMPRESSED_EXTENSIONS: [&str; 2] = ["br", "gz"];
+
 // -- public --
 
 #[throws]
@@ -27,6 +49,8 @@ pub async fn build_frontend(
     cache_busting: bool,
     frontend_dist: bool,
     frontend_multithreading: bool,
+    fingerprint_public_assets: bool,
+    tools: &Tools,
     compilation_killer: Option<watch::Receiver<()>>,
 ) {
     println!("Building frontend...");
@@ -36,91 +60,207 @@ pub async fn build_frontend(
 
     let web_workers = web_worker_workspace_members()?;
 
+    let mut pkg_crates = vec![PkgCrate {
+        name: "frontend",
+        path: Path::new("frontend"),
+        wasm_bindgen_target: if frontend_multithreading {
+            "no-modules"
+        } else {
+            "web"
+        },
+    }];
+    pkg_crates.extend(
+        web_workers
+            .iter()
+            .map(|WorkspaceMember { name, path, .. }| PkgCrate {
+                name,
+                path,
+                wasm_bindgen_target: "no-modules",
+            }),
+    );
+
+    let bin_crates = pkg_crates
+        .iter()
+        .map(|pkg_crate| pkg_crate.name)
+        .collect::<Vec<_>>();
     compile_with_cargo(
         build_mode,
-        "frontend",
+        &bin_crates,
         frontend_multithreading,
-        compilation_killer.clone(),
+        compilation_killer,
     )
     .await?;
-    for WorkspaceMember { name, .. } in &web_workers {
-        compile_with_cargo(
-            build_mode,
-            name,
-            frontend_multithreading,
-            compilation_killer.clone(),
-        )
-        .await?;
-    }
 
-    remove_pkg(Path::new("frontend/pkg")).await?;
-    for WorkspaceMember { path, .. } in &web_workers {
-        remove_pkg(&path.join("pkg")).await?;
-    }
-
-    check_or_install_wasm_bindgen().await?;
+    let wasm_bindgen = check_or_install_wasm_bindgen(tools).await?;
+    let wasm_opt_path = if build_mode.is_not_dev() {
+        Some(check_or_install_wasm_opt(tools).await?)
+    } else {
+        None
+    };
 
-    build_with_wasm_bindgen(
+    let target_path = MetadataCommand::new().no_deps().exec()?.target_directory;
+    let target_profile_path = target_path
+        .join("wasm32-unknown-unknown")
+        .join(build_mode.target_profile_folder())
+        .into_std_path_buf();
+    let options = PkgOptions {
+        build_id,
         build_mode,
-        "frontend",
-        Path::new("frontend"),
-        if frontend_multithreading {
-            "no-modules"
-        } else {
-            "web"
-        },
-    )
-    .await?;
-    for WorkspaceMember { name, path, .. } in &web_workers {
-        build_with_wasm_bindgen(build_mode, name, path, "no-modules").await?;
-    }
+        cache_busting,
+        compress: build_mode.is_not_dev() && !frontend_dist,
+        target_profile_path: &target_profile_path,
+        wasm_bindgen: &wasm_bindgen,
+        wasm_opt_path: wasm_opt_path.as_deref(),
+    };
+    stream::iter(pkg_crates.into_iter().map(Ok::<_, Error>))
+        .try_for_each_concurrent(Some(pkg_build_jobs()), |pkg_crate| {
+            build_pkg(&options, pkg_crate)
+        })
+        .await?;
 
     write_build_id(build_id).await?;
 
-    if build_mode.is_not_dev() {
-        check_or_install_wasm_opt().await?;
-        optimize_with_wasm_opt(build_mode, "frontend", Path::new("frontend")).await?;
-        for WorkspaceMember { name, path, .. } in &web_workers {
-            optimize_with_wasm_opt(build_mode, name, path).await?;
-        }
+    // fingerprinted assets aren't updated on `public` changes in the dev mode
+    if fingerprint_public_assets && build_mode.is_not_dev() {
+        copy_fingerprinted_public_assets(!frontend_dist).await?;
     }
 
-    rename_and_compress_pkg_files(
-        build_id,
-        build_mode,
-        cache_busting,
-        frontend_dist,
-        "frontend",
-        Path::new("frontend"),
-    )
-    .await?;
-    for WorkspaceMember { name, path, .. } in &web_workers {
-        rename_and_compress_pkg_files(
-            build_id,
-            build_mode,
-            cache_busting,
-            frontend_dist,
+    println!("Frontend built");
+}
+
+// -- private --
+
+/// A crate compiled to Wasm and processed into its `pkg` folder.
+struct PkgCrate<'a> {
+    name: &'a str,
+    path: &'a Path,
+    wasm_bindgen_target: &'static str,
+}
+
+/// Settings shared by all `PkgCrate`s in one build.
+struct PkgOptions<'a> {
+    build_id: u128,
+    build_mode: BuildMode,
+    cache_busting: bool,
+    compress: bool,
+    /// E.g. `target/wasm32-unknown-unknown/release`.
+    target_profile_path: &'a Path,
+    wasm_bindgen: &'a WasmBindgen,
+    /// `wasm-opt` is used only in the release and profiling modes.
+    wasm_opt_path: Option<&'a Path>,
+}
+
+/// The maximum number of `pkg`s processed by `wasm-bindgen`, `wasm-opt` and compressors at once.
+fn pkg_build_jobs() -> usize {
+    thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// Runs `wasm-bindgen`, `wasm-opt` and compressors only when the compiled Wasm file
+/// or related settings have changed since the last build, otherwise the cached `pkg` is used.
+#[throws]
+async fn build_pkg(options: &PkgOptions<'_>, pkg_crate: PkgCrate<'_>) {
+    let PkgCrate {
+        name,
+        path,
+        wasm_bindgen_target,
+    } = pkg_crate;
+    let pkg_path = path.join("pkg");
+    remove_pkg(&pkg_path).await?;
+
+    let wasm_path = options.target_profile_path.join(format!("{name}.wasm"));
+    let cache_path = options.target_profile_path.join(PKG_CACHE_DIR).join(name);
+    let fingerprint = pkg_fingerprint(options, &wasm_path, wasm_bindgen_target).await?;
+
+    if cached_pkg_fingerprint(&cache_path).await.as_ref() == Some(&fingerprint) {
+        println!("{name} Wasm file hasn't changed, using cached pkg");
+        copy_dir(cache_path.join("pkg"), pkg_path.clone()).await?;
+    } else {
+        build_with_wasm_bindgen(
+            options.build_mode,
+            &options.wasm_bindgen.path,
             name,
             path,
+            &wasm_path,
+            wasm_bindgen_target,
         )
         .await?;
+        if let Some(wasm_opt_path) = options.wasm_opt_path {
+            optimize_with_wasm_opt(options.build_mode, wasm_opt_path, name, path).await?;
+        }
+        if options.compress {
+            compress_wasm_and_snippets(&pkg_path, name).await?;
+        }
+        cache_pkg(&pkg_path, &cache_path, &fingerprint).await?;
     }
 
-    println!("Frontend built");
+    rename_and_compress_pkg_files(options, name, path).await?;
 }
 
-// -- private --
+/// Changes when the compiled Wasm file, the `pkg` settings or tool versions change.
+#[throws]
+async fn pkg_fingerprint(
+    options: &PkgOptions<'_>,
+    wasm_path: &Path,
+    wasm_bindgen_target: &str,
+) -> String {
+    let wasm = fs::read(wasm_path)
+        .await
+        .with_context(|| format!("Failed to read the Wasm file {wasm_path:?}"))?;
+    let settings = format!(
+        "target={wasm_bindgen_target} compress={} wasm-bindgen={} wasm-opt={}",
+        options.compress,
+        options.wasm_bindgen.version,
+        wasm_opt::VERSION
+    );
+    Sha256::new()
+        .chain_update(settings)
+        .chain_update(wasm)
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+async fn cached_pkg_fingerprint(cache_path: &Path) -> Option<String> {
+    fs::read_to_string(cache_path.join(PKG_CACHE_FINGERPRINT_FILE))
+        .await
+        .ok()
+}
+
+/// The fingerprint is written last so an interrupted caching invalidates the cache.
+#[throws]
+async fn cache_pkg(pkg_path: &Path, cache_path: &Path, fingerprint: &str) {
+    if fs::metadata(cache_path).await.is_ok() {
+        fs::remove_dir_all(cache_path)
+            .await
+            .context("Failed to remove the cached pkg")?;
+    }
+    fs::create_dir_all(cache_path).await?;
+    copy_dir(pkg_path.to_owned(), cache_path.join("pkg"))
+        .await
+        .context("Failed to cache pkg")?;
+    fs::write(cache_path.join(PKG_CACHE_FINGERPRINT_FILE), fingerprint).await?;
+}
+
+#[throws]
+async fn copy_dir(from: PathBuf, to: PathBuf) {
+    task::spawn_blocking(move || dir::copy(from, to, &dir::CopyOptions::new().content_only(true)))
+        .await??;
+}
 
 #[throws]
 async fn rename_and_compress_pkg_files(
-    build_id: u128,
-    build_mode: BuildMode,
-    cache_busting: bool,
-    frontend_dist: bool,
+    options: &PkgOptions<'_>,
     crate_name: &str,
     crate_path: &Path,
 ) {
-    let (wasm_file_path, js_file_path, snippets_path) = try_join!(
+    let PkgOptions {
+        build_id,
+        cache_busting,
+        compress,
+        ..
+    } = *options;
+    let (_, js_file_path, _) = try_join!(
         rename_wasm_file(build_id, cache_busting, crate_name, crate_path),
         rename_js_file(build_id, cache_busting, crate_name, crate_path),
         rename_snippets_folder(build_id, cache_busting, crate_name, crate_path),
@@ -128,15 +268,16 @@ async fn rename_and_compress_pkg_files(
 
     update_snippet_paths_in_js_file(build_id, cache_busting, &js_file_path).await?;
 
-    if build_mode.is_not_dev() && !frontend_dist {
-        compress_pkg(wasm_file_path, js_file_path, snippets_path).await?;
+    // the Wasm file and snippets have been compressed before renaming to be cached
+    if compress {
+        create_compressed_files(js_file_path).await?;
     }
 }
 
 #[throws]
 async fn compile_with_cargo(
     build_mode: BuildMode,
-    bin_crate: &str,
+    bin_crates: &[&str],
     frontend_multithreading: bool,
     compilation_killer: Option<watch::Receiver<()>>,
 ) {
@@ -172,14 +313,12 @@ async fn compile_with_cargo(
             .to_owned();
         args.push(&active_toolchain);
     }
-    args.extend(vec![
-        "cargo",
-        "build",
-        "--bin",
-        &bin_crate,
-        "--target",
-        "wasm32-unknown-unknown",
-    ]);
+    args.extend(["cargo", "build"]);
+    // all crates are compiled by one Cargo invocation to share dependencies and CPU cores
+    for bin_crate in bin_crates {
+        args.extend(["--bin", bin_crate]);
+    }
+    args.extend(["--target", "wasm32-unknown-unknown", MESSAGE_FORMAT_ARG]);
     if frontend_multithreading {
         args.extend(["--features", "zoon/frontend_multithreading"]);
     }
@@ -239,11 +378,17 @@ async fn compile_with_cargo(
         })
         .chain(envs);
 
+    let bin_crates = bin_crates.join(", ");
     let mut process = Command::new("rustup")
         .args(&args)
         .envs(envs)
+        .stdout(Stdio::piped())
         .spawn()
-        .context("Failed to start {bin_crate} compilation")?;
+        .with_context(|| format!("Failed to start {bin_crates} compilation"))?;
+    let stdout = process
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("Failed to capture {bin_crates} compilation output"))?;
 
     let compilation_killer_or_pending = async move {
         if let Some(mut compilation_killer) = compilation_killer {
@@ -254,18 +399,26 @@ async fn compile_with_cargo(
         }
     };
     select! {
-        result = process.wait() => {
-            result
-                .context("Failed to get {bin_crate} compilation status")?
-                .success()
-                .err(anyhow!("Failed to compile {bin_crate}"))?;
+        (result, diagnostics) = async {
+            let diagnostics = collect_diagnostics(&bin_crates, stdout).await;
+            (process.wait().await, diagnostics)
+        } => {
+            let success = result
+                .with_context(|| format!("Failed to get {bin_crates} compilation status"))?
+                .success();
+            if !success {
+                Err(CompilationError {
+                    crate_name: bin_crates,
+                    diagnostics,
+                })?
+            }
         }
         _ = compilation_killer_or_pending => {
             process
                 .kill()
                 .await
-                .context("Failed to kill {bin_crate} compilation")?;
-            Err(anyhow!("Compilation stopped"))?;
+                .with_context(|| format!("Failed to kill {bin_crates} compilation"))?;
+            Err(CompilationStopped)?;
         }
     };
 }
@@ -302,9 +455,22 @@ async fn rename_wasm_file(
 
     let new_path = pkg_path.join(format!("{crate_name}_bg_{build_id}.wasm"));
 
-    fs::rename(original_path, &new_path).await.context(format!(
-        "Failed to rename the wasm file in the pkg directory of the crate '{crate_name}'"
-    ))?;
+    fs::rename(&original_path, &new_path)
+        .await
+        .context(format!(
+            "Failed to rename the wasm file in the pkg directory of the crate '{crate_name}'"
+        ))?;
+    for extension in COMPRESSED_EXTENSIONS {
+        let original_compressed_path = compressed_file_path(&original_path, extension);
+        if fs::metadata(&original_compressed_path).await.is_ok() {
+            fs::rename(
+                original_compressed_path,
+                compressed_file_path(&new_path, extension),
+            )
+            .await
+            .with_context(|| format!("Failed to rename compressed wasm files of '{crate_name}'"))?;
+        }
+    }
     new_path
 }
 
@@ -371,17 +537,13 @@ async fn rename_js_file(
 }
 
 #[throws]
-async fn compress_pkg(
-    wasm_file_path: impl AsRef<Path>,
-    js_file_path: impl AsRef<Path>,
-    snippets_file_path: impl AsRef<Path>,
-) {
+async fn compress_wasm_and_snippets(pkg_path: &Path, crate_name: &str) {
+    let snippets_path = pkg_path.join("snippets");
     try_join!(
-        create_compressed_files(wasm_file_path),
-        create_compressed_files(js_file_path),
+        create_compressed_files(pkg_path.join(format!("{crate_name}_bg.wasm"))),
         async {
-            if fs::metadata(snippets_file_path.as_ref()).await.is_ok() {
-                visit_files(snippets_file_path.as_ref())
+            if fs::metadata(&snippets_path).await.is_ok() {
+                visit_files(&snippets_path)
                     .try_for_each_concurrent(None, |file| create_compressed_files(file.path()))
                     .await?;
             }
@@ -395,9 +557,312 @@ async fn create_compressed_files(file_path: impl AsRef<Path>) {
     let file_path = file_path.as_ref();
     let content = Arc::new(fs::File::open(&file_path).await?.read_to_vec().await?);
 
+    let [brotli_extension, gzip_extension] = COMPRESSED_EXTENSIONS;
     try_join!(
-        BrotliFileCompressor::compress_file(Arc::clone(&content), file_path, "br"),
-        GzipFileCompressor::compress_file(content, file_path, "gz"),
+        BrotliFileCompressor::compress_file(Arc::clone(&content), file_path, brotli_extension),
+        GzipFileCompressor::compress_file(content, file_path, gzip_extension),
     )
     .with_context(|| format!("Failed to create compressed files for {file_path:?}"))?
 }
+
+#[throws]
+async fn copy_fingerprinted_public_assets(compress: bool) {
+    if fs::metadata(PUBLIC_DIR).await.is_err() {
+        return;
+    }
+    let manifest = Mutex::new(BTreeMap::new());
+
+    visit_files(PUBLIC_DIR)
+        .try_for_each_concurrent(None, |file| {
+            let manifest = &manifest;
+            async move {
+                let path = file.path();
+                let relative_path = path.strip_prefix(PUBLIC_DIR)?;
+                let content = fs::read(&path).await?;
+                let fingerprinted_relative_path = fingerprinted_path(relative_path, &content);
+
+                let new_path = Path::new(PUBLIC_ASSETS_DIR).join(&fingerprinted_relative_path);
+                if let Some(parent) = new_path.parent() {
+                    fs::create_dir_all(parent).await?;
+                }
+                fs::write(&new_path, content)
+                    .await
+                    .with_context(|| format!("Failed to copy the public asset {path:?}"))?;
+                if compress {
+                    create_compressed_files(&new_path).await?;
+                }
+
+                manifest.lock().insert(
+                    url_path(relative_path),
+                    url_path(&fingerprinted_relative_path),
+                );
+                Ok(())
+            }
+        })
+        .await?;
+
+    fs::write(
+        PUBLIC_ASSETS_MANIFEST_PATH,
+        serde_json::to_string_pretty(&manifest.into_inner())?,
+    )
+    .await
+    .context("Failed to write the public assets manifest")?;
+}
+
+/// `images/logo.png` -> `images/logo.0123456789abcdef.png`
+fn fingerprinted_path(path: &Path, content: &[u8]) -> PathBuf {
+    let hash = Sha256::digest(content)
+        .iter()
+        .take(8)
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+
+    let mut file_name = path.file_stem().unwrap_or_default().to_owned();
+    file_name.push(format!(".{hash}"));
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    path.with_file_name(file_name)
+}
+
+fn url_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// ====== ====== TESTS ====== ======
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CRATE_NAME: &str = "frontend";
+
+    /// A temporary folder with the compiled Wasm file and the crate folder,
+    /// removed on drop.
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        async fn new() -> Self {
+            let test_dir = Self(env::temp_dir().join(format!("mzoon_test_{}", Uuid::new_v4())));
+            fs::create_dir_all(test_dir.crate_path()).await.unwrap();
+            fs::create_dir_all(test_dir.target_profile_path())
+                .await
+                .unwrap();
+            test_dir.write_wasm("wasm").await;
+            test_dir
+        }
+
+        fn target_profile_path(&self) -> PathBuf {
+            self.0.join("target")
+        }
+
+        fn crate_path(&self) -> PathBuf {
+            self.0.join(CRATE_NAME)
+        }
+
+        fn cache_path(&self) -> PathBuf {
+            self.target_profile_path()
+                .join(PKG_CACHE_DIR)
+                .join(CRATE_NAME)
+        }
+
+        fn wasm_path(&self) -> PathBuf {
+            self.target_profile_path()
+                .join(format!("{CRATE_NAME}.wasm"))
+        }
+
+        fn js_path(&self) -> PathBuf {
+            self.crate_path()
+                .join("pkg")
+                .join(format!("{CRATE_NAME}.js"))
+        }
+
+        /// `wasm-bindgen` doesn't exist so only cached `pkg`s can be built.
+        fn wasm_bindgen(&self, version: &str) -> WasmBindgen {
+            WasmBindgen {
+                path: self.0.join("wasm-bindgen"),
+                version: version.to_owned(),
+            }
+        }
+
+        async fn write_wasm(&self, content: &str) {
+            fs::write(self.wasm_path(), content).await.unwrap();
+        }
+
+        /// Caches a `pkg` with the JS file containing `js`.
+        async fn cache_pkg(&self, js: &str, fingerprint: &str) {
+            let pkg_path = self.crate_path().join("pkg");
+            fs::create_dir_all(&pkg_path).await.unwrap();
+            fs::write(self.js_path(), js).await.unwrap();
+            fs::write(pkg_path.join(format!("{CRATE_NAME}_bg.wasm")), "pkg wasm")
+                .await
+                .unwrap();
+            cache_pkg(&pkg_path, &self.cache_path(), fingerprint)
+                .await
+                .unwrap();
+            fs::remove_dir_all(pkg_path).await.unwrap();
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn pkg_options<'a>(
+        target_profile_path: &'a Path,
+        wasm_bindgen: &'a WasmBindgen,
+        compress: bool,
+    ) -> PkgOptions<'a> {
+        PkgOptions {
+            build_id: 1,
+            build_mode: BuildMode::Dev,
+            cache_busting: false,
+            compress,
+            target_profile_path,
+            wasm_bindgen,
+            wasm_opt_path: None,
+        }
+    }
+
+    fn pkg_crate(crate_path: &Path) -> PkgCrate<'_> {
+        PkgCrate {
+            name: CRATE_NAME,
+            path: crate_path,
+            wasm_bindgen_target: "web",
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_pkg_uses_cached_pkg_when_wasm_file_hasnt_changed() {
+        // ------ ARRANGE ------
+        let test_dir = TestDir::new().await;
+        let (target_profile_path, crate_path) =
+            (test_dir.target_profile_path(), test_dir.crate_path());
+        let wasm_bindgen = test_dir.wasm_bindgen("0.2.100");
+        let options = pkg_options(&target_profile_path, &wasm_bindgen, false);
+        let fingerprint = pkg_fingerprint(&options, &test_dir.wasm_path(), "web")
+            .await
+            .unwrap();
+        test_dir.cache_pkg("cached", &fingerprint).await;
+
+        // ------ ACT ------
+        let result = build_pkg(&options, pkg_crate(&crate_path)).await;
+
+        // ------ ASSERT ------
+        assert!(result.is_ok(), "{result:?}");
+        let js = fs::read_to_string(test_dir.js_path()).await.unwrap();
+        assert_eq!(js, "cached");
+    }
+
+    #[tokio::test]
+    async fn test_build_pkg_doesnt_use_cached_pkg_when_wasm_file_has_changed() {
+        // ------ ARRANGE ------
+        let test_dir = TestDir::new().await;
+        let (target_profile_path, crate_path) =
+            (test_dir.target_profile_path(), test_dir.crate_path());
+        let wasm_bindgen = test_dir.wasm_bindgen("0.2.100");
+        let options = pkg_options(&target_profile_path, &wasm_bindgen, false);
+        let fingerprint = pkg_fingerprint(&options, &test_dir.wasm_path(), "web")
+            .await
+            .unwrap();
+        test_dir.cache_pkg("cached", &fingerprint).await;
+        test_dir.write_wasm("changed wasm").await;
+
+        // ------ ACT ------
+        let result = build_pkg(&options, pkg_crate(&crate_path)).await;
+
+        // ------ ASSERT ------
+        // the missing `wasm-bindgen` has been called
+        assert!(result.is_err());
+        assert!(!test_dir.js_path().exists());
+    }
+
+    #[tokio::test]
+    async fn test_pkg_fingerprint_changes_with_settings_and_tool_versions() {
+        // ------ ARRANGE ------
+        let test_dir = TestDir::new().await;
+        let target_profile_path = test_dir.target_profile_path();
+        let wasm_path = test_dir.wasm_path();
+        let wasm_bindgen = test_dir.wasm_bindgen("0.2.100");
+        let new_wasm_bindgen = test_dir.wasm_bindgen("0.2.101");
+        let options = pkg_options(&target_profile_path, &wasm_bindgen, false);
+        let compress_options = pkg_options(&target_profile_path, &wasm_bindgen, true);
+        let new_tool_options = pkg_options(&target_profile_path, &new_wasm_bindgen, false);
+
+        // ------ ACT ------
+        let fingerprint = pkg_fingerprint(&options, &wasm_path, "web").await.unwrap();
+        let same_fingerprint = pkg_fingerprint(&options, &wasm_path, "web").await.unwrap();
+        let target_fingerprint = pkg_fingerprint(&options, &wasm_path, "no-modules")
+            .await
+            .unwrap();
+        let compress_fingerprint = pkg_fingerprint(&compress_options, &wasm_path, "web")
+            .await
+            .unwrap();
+        let new_tool_fingerprint = pkg_fingerprint(&new_tool_options, &wasm_path, "web")
+            .await
+            .unwrap();
+
+        // ------ ASSERT ------
+        assert_eq!(fingerprint, same_fingerprint);
+        assert_ne!(fingerprint, target_fingerprint);
+        assert_ne!(fingerprint, compress_fingerprint);
+        assert_ne!(fingerprint, new_tool_fingerprint);
+    }
+
+    #[tokio::test]
+    async fn test_build_pkg_doesnt_use_cached_pkg_when_tool_version_has_changed() {
+        // ------ ARRANGE ------
+        let test_dir = TestDir::new().await;
+        let (target_profile_path, crate_path) =
+            (test_dir.target_profile_path(), test_dir.crate_path());
+        let wasm_bindgen = test_dir.wasm_bindgen("0.2.100");
+        let options = pkg_options(&target_profile_path, &wasm_bindgen, false);
+        let fingerprint = pkg_fingerprint(&options, &test_dir.wasm_path(), "web")
+            .await
+            .unwrap();
+        test_dir.cache_pkg("cached", &fingerprint).await;
+        let new_wasm_bindgen = test_dir.wasm_bindgen("0.2.101");
+        let new_options = pkg_options(&target_profile_path, &new_wasm_bindgen, false);
+
+        // ------ ACT ------
+        let result = build_pkg(&new_options, pkg_crate(&crate_path)).await;
+
+        // ------ ASSERT ------
+        assert!(result.is_err());
+        assert!(!test_dir.js_path().exists());
+    }
+
+    #[tokio::test]
+    async fn test_pkg_cache_recovers_from_missing_fingerprint() {
+        // ------ ARRANGE ------
+        let test_dir = TestDir::new().await;
+        let (target_profile_path, crate_path) =
+            (test_dir.target_profile_path(), test_dir.crate_path());
+        let wasm_bindgen = test_dir.wasm_bindgen("0.2.100");
+        let options = pkg_options(&target_profile_path, &wasm_bindgen, false);
+        let fingerprint = pkg_fingerprint(&options, &test_dir.wasm_path(), "web")
+            .await
+            .unwrap();
+        test_dir.cache_pkg("interrupted", &fingerprint).await;
+        fs::remove_file(test_dir.cache_path().join(PKG_CACHE_FINGERPRINT_FILE))
+            .await
+            .unwrap();
+
+        // ------ ACT ------
+        let interrupted_cache_result = build_pkg(&options, pkg_crate(&crate_path)).await;
+        test_dir.cache_pkg("recached", &fingerprint).await;
+        let recached_result = build_pkg(&options, pkg_crate(&crate_path)).await;
+
+        // ------ ASSERT ------
+        assert!(interrupted_cache_result.is_err());
+        assert!(recached_result.is_ok(), "{recached_result:?}");
+        let js = fs::read_to_string(test_dir.js_path()).await.unwrap();
+        assert_eq!(js, "recached");
+    }
+}
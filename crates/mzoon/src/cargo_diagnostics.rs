@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::ChildStdout,
+};
+
+/// Cargo prints JSON messages to stdout and rendered diagnostics are printed by `collect_diagnostics`.
+pub const MESSAGE_FORMAT_ARG: &str = "--message-format=json-diagnostic-rendered-ansi";
+
+// -- public --
+
+/// Prints rendered compiler messages and returns errors.
+///
+/// `crate_name` is used when Cargo doesn't tell which crate has failed to compile.
+pub async fn collect_diagnostics(crate_name: &str, stdout: ChildStdout) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut lines = BufReader::new(stdout).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let Ok(cargo_message) = serde_json::from_str::<CargoMessage>(&line) else {
+            // e.g. output of build scripts
+            println!("{line}");
+            continue;
+        };
+        let Some(compiler_message) = cargo_message.message else {
+            continue;
+        };
+        let crate_name = cargo_message
+            .target
+            .map_or_else(|| crate_name.to_owned(), |target| target.name);
+        let rendered = compiler_message.rendered.unwrap_or_default();
+        eprint!("{rendered}");
+        if !compiler_message.level.starts_with("error") {
+            continue;
+        }
+        let primary_span = compiler_message.spans.iter().find(|span| span.is_primary);
+        diagnostics.push(Diagnostic {
+            crate_name,
+            file: primary_span.map(|span| span.file_name.clone()),
+            line: primary_span.map(|span| span.line_start),
+            column: primary_span.map(|span| span.column_start),
+            message: strip_ansi_codes(&rendered),
+        });
+    }
+    diagnostics
+}
+
+// ------ Diagnostic ------
+
+/// Sent to Moon and displayed in the browser overlay.
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub crate_name: String,
+    pub file: Option<String>,
+    pub line: Option<u64>,
+    pub column: Option<u64>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Represents errors without compiler diagnostics, e.g. a failed `wasm-bindgen` run.
+    pub fn from_error(crate_name: &str, error: &anyhow::Error) -> Self {
+        Self {
+            crate_name: crate_name.to_owned(),
+            file: None,
+            line: None,
+            column: None,
+            message: format!("{error:#}"),
+        }
+    }
+}
+
+// ------ CompilationError ------
+
+#[derive(Debug)]
+pub struct CompilationError {
+    pub crate_name: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl fmt::Display for CompilationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to compile {}", self.crate_name)
+    }
+}
+
+impl Error for CompilationError {}
+
+// ------ CompilationStopped ------
+
+/// The compilation has been killed because a newer build has been started.
+#[derive(Debug)]
+pub struct CompilationStopped;
+
+impl fmt::Display for CompilationStopped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Compilation stopped")
+    }
+}
+
+impl Error for CompilationStopped {}
+
+// -- private --
+
+/// https://doc.rust-lang.org/cargo/reference/external-tools.html#json-messages
+#[derive(Deserialize)]
+struct CargoMessage {
+    message: Option<CompilerMessage>,
+    target: Option<Target>,
+}
+
+#[derive(Deserialize)]
+struct Target {
+    name: String,
+}
+
+/// https://doc.rust-lang.org/rustc/json.html
+#[derive(Deserialize)]
+struct CompilerMessage {
+    level: String,
+    rendered: Option<String>,
+    spans: Vec<Span>,
+}
+
+#[derive(Deserialize)]
+struct Span {
+    file_name: String,
+    line_start: u64,
+    column_start: u64,
+    is_primary: bool,
+}
+
+/// Removes color escape sequences like `\x1b[1m`.
+fn strip_ansi_codes(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(char) = chars.next() {
+        if char != '\x1b' {
+            output.push(char);
+            continue;
+        }
+        if chars.next() == Some('[') {
+            // skip parameters until the final byte, e.g. `m`
+            chars.find(|char| ('@'..='~').contains(char));
+        }
+    }
+    output
+}
@@ -1,7 +1,9 @@
 mod build;
 mod new;
 mod start;
+mod test;
 
 pub use build::build;
 pub use new::new;
 pub use start::start;
+pub use test::test;
@@ -9,7 +9,7 @@ use fehler::throws;
 
 #[throws]
 pub async fn build(build_mode: BuildMode, frontend_dist: bool, hosting: Option<Hosting>) {
-    let config = Config::load_from_moonzoon_tomls().await?;
+    let config = Config::load_from_moonzoon_tomls(build_mode).await?;
     set_env_vars(&config, build_mode, frontend_dist);
 
     build_frontend(
@@ -17,6 +17,11 @@ pub async fn build(build_mode: BuildMode, frontend_dist: bool, hosting: Option<H
         config.cache_busting,
         frontend_dist,
         config.frontend_multithreading == Some(true),
+        config
+            .public_assets
+            .as_ref()
+            .is_some_and(|public_assets| public_assets.fingerprint),
+        &config.tools,
         None,
     )
     .await?;
@@ -4,7 +4,7 @@ use crate::config::Config;
 use crate::helper::localhost_url;
 use crate::run_backend::run_backend;
 use crate::set_env_vars::set_env_vars;
-use crate::watcher::{BackendWatcher, FrontendWatcher};
+use crate::watcher::{BackendWatcher, BuildErrorReporter, FrontendWatcher};
 use crate::BuildMode;
 use anyhow::{Context, Error};
 use fehler::throws;
@@ -16,14 +16,22 @@ const DEBOUNCE_TIME: Duration = Duration::from_millis(600);
 
 #[throws]
 pub async fn start(build_mode: BuildMode, open: bool) {
-    let config = Config::load_from_moonzoon_tomls().await?;
+    let config = Config::load_from_moonzoon_tomls(build_mode).await?;
     set_env_vars(&config, build_mode, false);
 
     let server = Arc::new(Mutex::new(None));
+    let build_error_reporter = Arc::new(BuildErrorReporter::new(&config));
 
-    let frontend_watcher = build_and_watch_frontend(&config, build_mode).await?;
-    let backend_watcher =
-        build_run_and_watch_backend(&config, build_mode, open, Arc::clone(&server)).await?;
+    let frontend_watcher =
+        build_and_watch_frontend(&config, build_mode, Arc::clone(&build_error_reporter)).await?;
+    let backend_watcher = build_run_and_watch_backend(
+        &config,
+        build_mode,
+        open,
+        Arc::clone(&server),
+        build_error_reporter,
+    )
+    .await?;
 
     signal::ctrl_c().await?;
 
@@ -39,19 +47,30 @@ pub async fn start(build_mode: BuildMode, open: bool) {
 }
 
 #[throws]
-async fn build_and_watch_frontend(config: &Config, build_mode: BuildMode) -> FrontendWatcher {
-    if let Err(error) = build_frontend(
+async fn build_and_watch_frontend(
+    config: &Config,
+    build_mode: BuildMode,
+    build_error_reporter: Arc<BuildErrorReporter>,
+) -> FrontendWatcher {
+    let result = build_frontend(
         build_mode,
         config.cache_busting,
         false,
         config.frontend_multithreading == Some(true),
+        config
+            .public_assets
+            .as_ref()
+            .is_some_and(|public_assets| public_assets.fingerprint),
+        &config.tools,
         None,
     )
-    .await
-    {
+    .await;
+    if let Err(error) = &result {
         eprintln!("{error:#}");
     }
-    FrontendWatcher::start(&config, build_mode, DEBOUNCE_TIME).await?
+    // errors are sent once the backend is running
+    build_error_reporter.set_frontend_error(result.as_ref().err());
+    FrontendWatcher::start(&config, build_mode, DEBOUNCE_TIME, build_error_reporter).await?
 }
 
 #[throws]
@@ -60,29 +79,41 @@ async fn build_run_and_watch_backend(
     build_mode: BuildMode,
     open: bool,
     server: Arc<Mutex<Option<Child>>>,
+    build_error_reporter: Arc<BuildErrorReporter>,
 ) -> BackendWatcher {
-    build_and_run_backend(config, build_mode, &server).await;
+    build_and_run_backend(config, build_mode, &server, &build_error_reporter).await;
     if open {
         open_in_browser(config)?;
     }
-    BackendWatcher::start(&config, build_mode, DEBOUNCE_TIME, server).await?
+    BackendWatcher::start(
+        &config,
+        build_mode,
+        DEBOUNCE_TIME,
+        server,
+        build_error_reporter,
+    )
+    .await?
 }
 
 async fn build_and_run_backend(
     config: &Config,
     build_mode: BuildMode,
     server: &Mutex<Option<Child>>,
+    build_error_reporter: &BuildErrorReporter,
 ) {
-    if let Err(error) = build_backend(build_mode, config.https).await {
+    let result = build_backend(build_mode, config.https).await;
+    if let Err(error) = &result {
         eprintln!("{error:#}");
-        return;
     }
+    build_error_reporter.set_backend_error(result.as_ref().err());
+    // the previous backend binary is started to display errors in the browser
     match run_backend(build_mode) {
         Err(error) => {
             eprintln!("{error:#}");
         }
         Ok(server_process) => {
             *server.lock() = Some(server_process);
+            build_error_reporter.send_to_new_server().await;
         }
     }
 }
@@ -0,0 +1,228 @@
+use crate::config::{Config, Tools};
+use crate::helper::workspace_member::{web_worker_workspace_members, WorkspaceMember};
+use crate::set_env_vars::set_env_vars;
+use crate::wasm_bindgen::check_or_install_wasm_bindgen_test_runner;
+use crate::BuildMode;
+use anyhow::{anyhow, Context, Error};
+use fehler::throws;
+use std::{env, process::Stdio};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+};
+
+#[throws]
+pub async fn test(build_mode: BuildMode, backend: bool, frontend: bool, filter: Option<String>) {
+    let config = Config::load_from_moonzoon_tomls(build_mode).await?;
+    set_env_vars(&config, build_mode, false);
+    // Zoon reads it with `env!` in the multithreading and Web Worker code
+    env::set_var("FRONTEND_BUILD_ID", "0");
+
+    // no flag means both
+    let (backend, frontend) = if backend || frontend {
+        (backend, frontend)
+    } else {
+        (true, true)
+    };
+    let web_workers = web_worker_workspace_members()?;
+
+    let mut summaries = Vec::new();
+    if backend {
+        let summary = test_backend(build_mode, &web_workers, filter.as_deref()).await?;
+        summaries.push(("backend", summary));
+    }
+    if frontend {
+        let summary =
+            test_frontend(build_mode, &config.tools, &web_workers, filter.as_deref()).await?;
+        summaries.push(("frontend", summary));
+    }
+
+    println!("\nTest summary:");
+    for (name, summary) in &summaries {
+        let TestSummary {
+            passed,
+            failed,
+            ignored,
+            success,
+        } = summary;
+        let status = if *success { "ok" } else { "FAILED" };
+        println!("  {name}: {status}. {passed} passed; {failed} failed; {ignored} ignored");
+    }
+    if summaries.iter().any(|(_, summary)| !summary.success) {
+        Err(anyhow!("Some tests failed"))?
+    }
+}
+
+// -- private --
+
+/// Runs tests of all workspace members except the frontend and Web Workers.
+#[throws]
+async fn test_backend(
+    build_mode: BuildMode,
+    web_workers: &[WorkspaceMember],
+    filter: Option<&str>,
+) -> TestSummary {
+    println!("Testing backend...");
+
+    let mut args = vec!["test", "--workspace", "--exclude", "frontend"];
+    for WorkspaceMember { name, .. } in web_workers {
+        args.extend(["--exclude", name.as_str()]);
+    }
+    push_build_mode_args(&mut args, build_mode);
+    if let Some(filter) = filter {
+        args.extend(["--", filter]);
+    }
+    run_cargo_test(&mut Command::new("cargo"), &args)
+        .await
+        .context("Failed to run backend tests")?
+}
+
+/// Runs `wasm32-unknown-unknown` tests of the frontend and Web Workers with `wasm-bindgen-test-runner`.
+///
+/// Tests run in Node.js by default and in a headless browser
+/// when they're configured with `wasm_bindgen_test_configure!(run_in_browser)`.
+#[throws]
+async fn test_frontend(
+    build_mode: BuildMode,
+    tools: &Tools,
+    web_workers: &[WorkspaceMember],
+    filter: Option<&str>,
+) -> TestSummary {
+    println!("Testing frontend...");
+
+    let mut runner_path = check_or_install_wasm_bindgen_test_runner(tools).await?;
+    // Cargo runs test binaries from crate folders so the runner path has to be absolute,
+    // a single file name is searched on `PATH`
+    if runner_path.components().count() > 1 {
+        runner_path = env::current_dir()?.join(runner_path);
+    }
+
+    let mut args = vec![
+        "test",
+        "--target",
+        "wasm32-unknown-unknown",
+        "--package",
+        "frontend",
+    ];
+    for WorkspaceMember { name, .. } in web_workers {
+        args.extend(["--package", name.as_str()]);
+    }
+    push_build_mode_args(&mut args, build_mode);
+    if let Some(filter) = filter {
+        args.extend(["--", filter]);
+    }
+    let mut command = Command::new("cargo");
+    command.env("CARGO_TARGET_WASM32_UNKNOWN_UNKNOWN_RUNNER", runner_path);
+    run_cargo_test(&mut command, &args)
+        .await
+        .context("Failed to run frontend tests")?
+}
+
+fn push_build_mode_args(args: &mut Vec<&str>, build_mode: BuildMode) {
+    match build_mode {
+        BuildMode::Dev => (),
+        BuildMode::Profiling => args.extend(["--profile", "profiling"]),
+        BuildMode::Release => args.push("--release"),
+    }
+}
+
+/// Prints the test output and collects results from all test binaries.
+#[throws]
+async fn run_cargo_test(command: &mut Command, args: &[&str]) -> TestSummary {
+    let mut child = command.args(args).stdout(Stdio::piped()).spawn()?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or(anyhow!("Failed to capture stdout"))?;
+
+    let mut summary = TestSummary::default();
+    let mut lines = BufReader::new(stdout).lines();
+    while let Some(line) = lines.next_line().await? {
+        println!("{line}");
+        summary.add_result_line(&line);
+    }
+    summary.success = child.wait().await?.success();
+    summary
+}
+
+// ------ TestSummary ------
+
+#[derive(Default)]
+struct TestSummary {
+    passed: usize,
+    failed: usize,
+    ignored: usize,
+    success: bool,
+}
+
+impl TestSummary {
+    /// Parses lines like `test result: ok. 3 passed; 0 failed; 1 ignored; 0 measured; ...`
+    /// printed by the Rust test harness and `wasm-bindgen-test-runner`.
+    fn add_result_line(&mut self, line: &str) {
+        let Some(results) = line.trim().strip_prefix("test result: ") else {
+            return;
+        };
+        for result in results.split(';') {
+            let mut words = result.split_whitespace().rev();
+            let (Some(kind), Some(Ok(count))) =
+                (words.next(), words.next().map(str::parse::<usize>))
+            else {
+                continue;
+            };
+            match kind {
+                "passed" => self.passed += count,
+                "failed" => self.failed += count,
+                "ignored" => self.ignored += count,
+                _ => (),
+            }
+        }
+    }
+}
+
+// ====== ====== TESTS ====== ======
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_aggregates_results_of_all_test_binaries() {
+        // ------ ARRANGE ------
+        let output = [
+            "     Running unittests src/lib.rs (target/debug/deps/backend-1a2b3c)",
+            "test tests::test_a ... ok",
+            "test result: ok. 3 passed; 0 failed; 1 ignored; 0 measured; 0 filtered out; finished in 0.01s",
+            "     Running tests/api.rs (target/debug/deps/api-4d5e6f)",
+            "test result: FAILED. 2 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.02s",
+            "   Doc-tests backend",
+            "test result: ok. 0 passed; 0 failed; 2 ignored; 0 measured; 0 filtered out; finished in 0.00s",
+        ];
+        let mut summary = TestSummary::default();
+
+        // ------ ACT ------
+        for line in output {
+            summary.add_result_line(line);
+        }
+
+        // ------ ASSERT ------
+        assert_eq!(summary.passed, 5);
+        assert_eq!(summary.failed, 1);
+        assert_eq!(summary.ignored, 3);
+    }
+
+    #[test]
+    fn test_summary_ignores_other_lines() {
+        // ------ ARRANGE ------
+        let mut summary = TestSummary::default();
+
+        // ------ ACT ------
+        summary.add_result_line("test tests::test_result ... ok");
+        summary.add_result_line("running 3 tests");
+        summary.add_result_line("test result: unknown format");
+
+        // ------ ASSERT ------
+        assert_eq!(summary.passed, 0);
+        assert_eq!(summary.failed, 0);
+        assert_eq!(summary.ignored, 0);
+    }
+}
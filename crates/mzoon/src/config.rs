@@ -1,11 +1,15 @@
-use crate::helper::{
-    tree_into_pairs::{tree_into_pairs, NodeContent},
-    TryIntoString,
+use crate::{
+    helper::{
+        tree_into_pairs::{tree_into_pairs, NodeContent},
+        TryIntoString,
+    },
+    BuildMode,
 };
 use anyhow::{Context, Error};
 use fehler::throws;
 use log::LevelFilter;
 use serde::Deserialize;
+use std::path::PathBuf;
 use tokio::fs;
 
 #[derive(Debug, Deserialize)]
@@ -15,9 +19,20 @@ pub struct Config {
     pub cache_busting: bool,
     pub backend_log_level: LevelFilter,
     pub frontend_multithreading: Option<bool>,
+    pub up_msg_max_bytes: Option<usize>,
+    pub shutdown_timeout: Option<u64>,
     pub redirect: Redirect,
     pub cors: Cors,
+    pub message_sse: Option<MessageSSE>,
+    pub storage: Option<Storage>,
+    pub auth: Option<Auth>,
+    pub rate_limit: Option<RateLimit>,
+    pub metrics: Option<Metrics>,
+    pub csp: Option<Csp>,
+    pub public_assets: Option<PublicAssets>,
     pub watch: Watch,
+    #[serde(default)]
+    pub tools: Tools,
     #[serde(skip)]
     pub custom_env_vars: Vec<(String, String)>,
 }
@@ -33,17 +48,97 @@ pub struct Cors {
     pub origins: Vec<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct MessageSSE {
+    pub replay_buffer_size: usize,
+    pub replay_buffer_max_age: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RateLimit {
+    pub enabled: Option<bool>,
+    pub up_msgs_per_second: Option<f64>,
+    pub up_msg_burst: Option<u32>,
+    pub ip_up_msgs_per_second: Option<f64>,
+    pub ip_up_msg_burst: Option<u32>,
+    pub connections_per_minute: Option<f64>,
+    pub connection_burst: Option<u32>,
+    pub max_sessions: Option<usize>,
+    pub trust_proxy: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Metrics {
+    pub enabled: Option<bool>,
+    pub allowed_ips: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Csp {
+    pub enabled: Option<bool>,
+    pub policy: Option<String>,
+    pub report_only: Option<bool>,
+    pub external_scripts: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PublicAssets {
+    #[serde(default)]
+    pub fingerprint: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Auth {
+    pub token_max_age: Option<u64>,
+    #[serde(default)]
+    pub require_for_message_sse: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Storage {
+    pub backend: StorageBackend,
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    Memory,
+    File,
+}
+
+impl StorageBackend {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Memory => "memory",
+            Self::File => "file",
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Watch {
     pub frontend: Vec<String>,
     pub backend: Vec<String>,
 }
 
+/// `wasm-bindgen`, `wasm-bindgen-test-runner` and `wasm-opt` are searched in the `frontend` folder
+/// and on `PATH` when their paths aren't configured.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Tools {
+    pub wasm_bindgen: Option<PathBuf>,
+    pub wasm_bindgen_test_runner: Option<PathBuf>,
+    pub wasm_opt: Option<PathBuf>,
+    /// A folder with downloaded tool archives, they are reused instead of downloading them again.
+    pub archive_cache: Option<PathBuf>,
+}
+
 impl Config {
     #[throws]
-    pub async fn load_from_moonzoon_tomls() -> Config {
-        let mut config = read_moonzoon_toml().await?;
-        if let Some(custom_env_vars) = read_moonzoon_custom_toml().await? {
+    pub async fn load_from_moonzoon_tomls(build_mode: BuildMode) -> Config {
+        let profile = build_mode.config_profile();
+        let mut config = read_moonzoon_toml(profile).await?;
+        if let Some(custom_env_vars) = read_moonzoon_custom_toml(profile).await? {
             config.custom_env_vars = custom_env_vars;
         }
         config
@@ -51,16 +146,19 @@ impl Config {
 }
 
 #[throws]
-async fn read_moonzoon_toml() -> Config {
+async fn read_moonzoon_toml(profile: &str) -> Config {
     let config_toml = fs::read_to_string("MoonZoon.toml")
         .await
         .context("Failed to read MoonZoon.toml")?;
 
-    toml::from_str(&config_toml).context("Failed to parse MoonZoon.toml")?
+    let config = toml::from_str(&config_toml).context("Failed to parse MoonZoon.toml")?;
+    toml::Value::Table(apply_profile(config, profile))
+        .try_into()
+        .context("Failed to parse MoonZoon.toml")?
 }
 
 #[throws]
-async fn read_moonzoon_custom_toml() -> Option<Vec<(String, String)>> {
+async fn read_moonzoon_custom_toml(profile: &str) -> Option<Vec<(String, String)>> {
     if fs::metadata("MoonZoonCustom.toml").await.is_err() {
         return None;
     }
@@ -71,11 +169,38 @@ async fn read_moonzoon_custom_toml() -> Option<Vec<(String, String)>> {
 
     let custom_config =
         toml::from_str(&custom_config_toml).context("Failed to parse MoonZoonCustom.toml")?;
+    let custom_config = toml::Value::Table(apply_profile(custom_config, profile));
     let pairs =
         toml_to_env_vars(custom_config).context("Failed to parse MoonZoonCustom.toml's content")?;
     Some(pairs)
 }
 
+/// Merges `[profile.<profile>]` into the root table.
+/// Moon applies profiles the same way when it reads config files itself.
+fn apply_profile(mut table: toml::Table, profile: &str) -> toml::Table {
+    let profile_table = match table.remove("profile") {
+        Some(toml::Value::Table(mut profiles)) => profiles.remove(profile),
+        _ => None,
+    };
+    if let Some(toml::Value::Table(profile_table)) = profile_table {
+        merge_tables(&mut table, profile_table);
+    }
+    table
+}
+
+fn merge_tables(table: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(table)), toml::Value::Table(overlay)) => {
+                merge_tables(table, overlay)
+            }
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
 #[throws]
 fn toml_to_env_vars(toml: toml::Value) -> Vec<(String, String)> {
     tree_into_pairs(
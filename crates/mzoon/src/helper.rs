@@ -2,13 +2,16 @@ mod download;
 mod file_compressor;
 mod localhost_url;
 mod read_to_vec;
+pub mod tool;
 pub mod tree_into_pairs;
 mod try_into_string;
 mod visit_files;
 pub mod workspace_member;
 
 pub use download::download;
-pub use file_compressor::{BrotliFileCompressor, FileCompressor, GzipFileCompressor};
+pub use file_compressor::{
+    compressed_file_path, BrotliFileCompressor, FileCompressor, GzipFileCompressor,
+};
 pub use localhost_url::localhost_url;
 pub use read_to_vec::{AsyncReadToVec, ReadToVec};
 pub use try_into_string::TryIntoString;
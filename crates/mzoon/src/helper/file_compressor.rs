@@ -28,7 +28,8 @@ pub trait FileCompressor {
     fn compress(bytes: &[u8]) -> Result<Vec<u8>>;
 }
 
-fn compressed_file_path(path: &Path, extension: &str) -> PathBuf {
+/// `frontend_bg.wasm` -> `frontend_bg.wasm.br`
+pub fn compressed_file_path(path: &Path, extension: &str) -> PathBuf {
     let new_extension = path
         .extension()
         .unwrap_or_default()
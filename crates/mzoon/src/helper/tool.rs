@@ -0,0 +1,172 @@
+use crate::helper::download;
+use anyhow::{anyhow, Context, Error};
+use bool_ext::BoolExt;
+use fehler::throws;
+use std::path::{Path, PathBuf};
+use tokio::{fs, process::Command};
+
+// -- public --
+
+/// A path configured in `MoonZoon.toml` is the only candidate.
+/// Otherwise the tool installed by mzoon is preferred over the one on `PATH`.
+pub fn tool_candidates(
+    configured_path: Option<&Path>,
+    installed_path: &str,
+    name: &str,
+) -> Vec<PathBuf> {
+    match configured_path {
+        Some(configured_path) => vec![configured_path.to_owned()],
+        None => vec![PathBuf::from(installed_path), PathBuf::from(name)],
+    }
+}
+
+/// Returns the first candidate with the expected version.
+#[throws]
+pub async fn find_compatible_tool(
+    candidates: &[PathBuf],
+    version_arg: &str,
+    version_prefix: &str,
+    version: &str,
+) -> PathBuf {
+    let mut errors = Vec::new();
+    for candidate in candidates {
+        match check_tool_version(candidate, version_arg, version_prefix, version).await {
+            Ok(()) => return candidate.clone(),
+            Err(error) => errors.push(format!("{error:#}")),
+        }
+    }
+    Err(anyhow!(
+        "Compatible '{version_prefix}{version}' hasn't been found: {}",
+        errors.join("; ")
+    ))?
+}
+
+/// Checks the output of `<path> <version_arg>`, e.g. `wasm-bindgen 0.2.100`.
+#[throws]
+pub async fn check_tool_version(
+    path: &Path,
+    version_arg: &str,
+    version_prefix: &str,
+    version: &str,
+) {
+    let version_output = Command::new(path)
+        .arg(version_arg)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {path:?}"))?
+        .stdout;
+    let version_output = String::from_utf8_lossy(&version_output);
+
+    if !has_version(&version_output, version_prefix, version) {
+        Err(anyhow!(
+            "{path:?} has the version '{}'",
+            version_output.trim()
+        ))?;
+    }
+}
+
+/// Reads the archive from `archive_cache` if possible,
+/// otherwise downloads it and stores it to `archive_cache` for the next installations.
+#[throws]
+pub async fn download_or_read_cached(url: &str, archive_cache: Option<&Path>) -> Vec<u8> {
+    let cached_archive_path = archive_cache.map(|archive_cache| {
+        let file_name = url.rsplit('/').next().unwrap_or(url);
+        archive_cache.join(file_name)
+    });
+    if let Some(cached_archive_path) = &cached_archive_path {
+        if let Ok(archive) = fs::read(cached_archive_path).await {
+            println!("Using the cached archive {cached_archive_path:?}");
+            return archive;
+        }
+    }
+
+    let archive = download(url)
+        .await
+        .with_context(|| format!("Failed to download the url '{url}'"))?;
+
+    if let Some(cached_archive_path) = &cached_archive_path {
+        if let Some(archive_cache) = cached_archive_path.parent() {
+            fs::create_dir_all(archive_cache).await?;
+        }
+        fs::write(cached_archive_path, &archive)
+            .await
+            .with_context(|| format!("Failed to cache the archive {cached_archive_path:?}"))?;
+    }
+    archive
+}
+
+/// Compiles the tool from source when a pre-compiled binary isn't available.
+/// Binaries are installed to `<root>/bin`.
+#[throws]
+pub async fn cargo_install(crate_name: &str, version: &str, root: &str) {
+    println!("Compiling {crate_name} {version} from source with `cargo install` ...");
+    Command::new("cargo")
+        .args([
+            "install",
+            crate_name,
+            "--version",
+            version,
+            "--locked",
+            "--root",
+            root,
+        ])
+        .status()
+        .await
+        .with_context(|| format!("Failed to get {crate_name} installation status"))?
+        .success()
+        .err(anyhow!(
+            "Failed to install {crate_name} with `cargo install`"
+        ))?;
+}
+
+// -- private --
+
+/// The whole version has to match, e.g. `0.2.10` doesn't match `wasm-bindgen 0.2.100`.
+fn has_version(version_output: &str, version_prefix: &str, version: &str) -> bool {
+    version_output
+        .strip_prefix(version_prefix)
+        .and_then(|output| output.strip_prefix(version))
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+// ====== ====== TESTS ====== ======
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_has_version_matches_whole_version() {
+        // ------ ARRANGE ------
+        let version_output = "wasm-bindgen 0.2.100\n";
+
+        // ------ ACT ------
+        let has_exact_version = has_version(version_output, "wasm-bindgen ", "0.2.100");
+        let has_shorter_version = has_version(version_output, "wasm-bindgen ", "0.2.10");
+        let has_longer_version = has_version("wasm-bindgen 0.2.10", "wasm-bindgen ", "0.2.100");
+
+        // ------ ASSERT ------
+        assert!(has_exact_version);
+        assert!(!has_shorter_version);
+        assert!(!has_longer_version);
+    }
+
+    #[test]
+    fn test_has_version_allows_details_after_version() {
+        // ------ ACT ------
+        let has_version_with_details = has_version(
+            "wasm-opt version 116 (version_116)",
+            "wasm-opt version ",
+            "116",
+        );
+        let has_version_with_other_prefix = has_version(
+            "wasm-bindgen-test-runner 0.2.100",
+            "wasm-bindgen ",
+            "0.2.100",
+        );
+
+        // ------ ASSERT ------
+        assert!(has_version_with_details);
+        assert!(!has_version_with_other_prefix);
+    }
+}
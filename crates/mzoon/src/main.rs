@@ -5,6 +5,7 @@ use std::path::PathBuf;
 
 mod build_backend;
 mod build_frontend;
+mod cargo_diagnostics;
 mod command;
 mod config;
 mod frontend_dist;
@@ -45,6 +46,21 @@ enum Args {
         #[clap(value_enum)]
         hosting: Option<Hosting>,
     },
+    /// Run backend tests and frontend Wasm tests
+    Test {
+        #[clap(short, long)]
+        release: bool,
+        #[clap(short, long)]
+        profiling: bool,
+        /// Run only backend tests
+        #[clap(short, long)]
+        backend: bool,
+        /// Run only frontend tests (including Web Workers)
+        #[clap(short, long)]
+        frontend: bool,
+        /// Run only tests with names containing the filter
+        filter: Option<String>,
+    },
 }
 
 #[derive(clap::ValueEnum, Clone, Copy, Debug)]
@@ -92,6 +108,14 @@ impl BuildMode {
         }
     }
 
+    /// The `[profile.<name>]` applied from `MoonZoon.toml` and `MoonZoonCustom.toml`.
+    fn config_profile(&self) -> &'static str {
+        match self {
+            Self::Dev => "dev",
+            Self::Profiling | Self::Release => "release",
+        }
+    }
+
     fn target_profile_folder(&self) -> &str {
         match self {
             Self::Dev => "debug",
@@ -120,5 +144,20 @@ async fn main() {
             frontend_dist,
             hosting,
         } => command::build(BuildMode::new(release, profiling), frontend_dist, hosting).await?,
+        Args::Test {
+            release,
+            profiling,
+            backend,
+            frontend,
+            filter,
+        } => {
+            command::test(
+                BuildMode::new(release, profiling),
+                backend,
+                frontend,
+                filter,
+            )
+            .await?
+        }
     }
 }
@@ -15,6 +15,14 @@ pub fn set_env_vars(config: &Config, build_mode: BuildMode, frontend_dist: bool)
         "FRONTEND_MULTITHREADING",
         (config.frontend_multithreading == Some(true)).to_string(),
     );
+    // up_msg_max_bytes = 2097152
+    if let Some(up_msg_max_bytes) = config.up_msg_max_bytes {
+        env::set_var("UP_MSG_MAX_BYTES", up_msg_max_bytes.to_string());
+    }
+    // shutdown_timeout = 30 # in seconds
+    if let Some(shutdown_timeout) = config.shutdown_timeout {
+        env::set_var("SHUTDOWN_TIMEOUT", shutdown_timeout.to_string());
+    }
 
     // [redirect]
     // port = 8080
@@ -26,6 +34,135 @@ pub fn set_env_vars(config: &Config, build_mode: BuildMode, frontend_dist: bool)
     // origins = ["*", "https://example.com"]
     env::set_var("CORS_ORIGINS", config.cors.origins.join(","));
 
+    // [message_sse]
+    if let Some(message_sse) = &config.message_sse {
+        // replay_buffer_size = 100
+        env::set_var(
+            "MESSAGE_SSE_REPLAY_BUFFER_SIZE",
+            message_sse.replay_buffer_size.to_string(),
+        );
+        // replay_buffer_max_age = 60
+        env::set_var(
+            "MESSAGE_SSE_REPLAY_BUFFER_MAX_AGE",
+            message_sse.replay_buffer_max_age.to_string(),
+        );
+    }
+
+    // [storage]
+    if let Some(storage) = &config.storage {
+        // backend = "file" # "memory" / "file"
+        env::set_var("STORAGE_BACKEND", storage.backend.as_str());
+        // path = "moon_storage.jsonl"
+        if let Some(path) = &storage.path {
+            env::set_var("STORAGE_PATH", path);
+        }
+    }
+
+    // [auth]
+    // The secret is read from the env var `AUTH_SECRET` to keep it out of the repository.
+    if let Some(auth) = &config.auth {
+        // token_max_age = 86400 # in seconds
+        if let Some(token_max_age) = auth.token_max_age {
+            env::set_var("AUTH_TOKEN_MAX_AGE", token_max_age.to_string());
+        }
+        // require_for_message_sse = true
+        env::set_var(
+            "AUTH_REQUIRE_FOR_MESSAGE_SSE",
+            auth.require_for_message_sse.to_string(),
+        );
+    }
+
+    // [rate_limit]
+    if let Some(rate_limit) = &config.rate_limit {
+        let env_vars = [
+            // enabled = true
+            (
+                "RATE_LIMIT_ENABLED",
+                rate_limit.enabled.map(|v| v.to_string()),
+            ),
+            // up_msgs_per_second = 20
+            (
+                "RATE_LIMIT_UP_MSGS_PER_SECOND",
+                rate_limit.up_msgs_per_second.map(|v| v.to_string()),
+            ),
+            // up_msg_burst = 50
+            (
+                "RATE_LIMIT_UP_MSG_BURST",
+                rate_limit.up_msg_burst.map(|v| v.to_string()),
+            ),
+            // ip_up_msgs_per_second = 100
+            (
+                "RATE_LIMIT_IP_UP_MSGS_PER_SECOND",
+                rate_limit.ip_up_msgs_per_second.map(|v| v.to_string()),
+            ),
+            // ip_up_msg_burst = 200
+            (
+                "RATE_LIMIT_IP_UP_MSG_BURST",
+                rate_limit.ip_up_msg_burst.map(|v| v.to_string()),
+            ),
+            // connections_per_minute = 30
+            (
+                "RATE_LIMIT_CONNECTIONS_PER_MINUTE",
+                rate_limit.connections_per_minute.map(|v| v.to_string()),
+            ),
+            // connection_burst = 10
+            (
+                "RATE_LIMIT_CONNECTION_BURST",
+                rate_limit.connection_burst.map(|v| v.to_string()),
+            ),
+            // max_sessions = 10000 # 0 = unlimited
+            (
+                "RATE_LIMIT_MAX_SESSIONS",
+                rate_limit.max_sessions.map(|v| v.to_string()),
+            ),
+            // trust_proxy = false
+            (
+                "RATE_LIMIT_TRUST_PROXY",
+                rate_limit.trust_proxy.map(|v| v.to_string()),
+            ),
+        ];
+        for (name, value) in env_vars {
+            if let Some(value) = value {
+                env::set_var(name, value);
+            }
+        }
+    }
+
+    // [metrics]
+    // The token is read from the env var `METRICS_TOKEN` to keep it out of the repository.
+    if let Some(metrics) = &config.metrics {
+        // enabled = true
+        if let Some(enabled) = metrics.enabled {
+            env::set_var("METRICS_ENABLED", enabled.to_string());
+        }
+        // allowed_ips = ["127.0.0.1", "::1"]
+        if let Some(allowed_ips) = &metrics.allowed_ips {
+            env::set_var("METRICS_ALLOWED_IPS", allowed_ips.join(","));
+        }
+    }
+
+    // [csp]
+    if let Some(csp) = &config.csp {
+        let env_vars = [
+            // enabled = true
+            ("CSP_ENABLED", csp.enabled.map(|v| v.to_string())),
+            // policy = "default-src 'self'; script-src 'self' 'nonce-{nonce}' 'wasm-unsafe-eval'"
+            ("CSP_POLICY", csp.policy.clone()),
+            // report_only = false
+            ("CSP_REPORT_ONLY", csp.report_only.map(|v| v.to_string())),
+            // external_scripts = false
+            (
+                "CSP_EXTERNAL_SCRIPTS",
+                csp.external_scripts.map(|v| v.to_string()),
+            ),
+        ];
+        for (name, value) in env_vars {
+            if let Some(value) = value {
+                env::set_var(name, value);
+            }
+        }
+    }
+
     env::set_var(
         "COMPRESSED_PKG",
         (build_mode.is_not_dev() && !frontend_dist).to_string(),
@@ -40,6 +177,9 @@ pub fn set_env_vars(config: &Config, build_mode: BuildMode, frontend_dist: bool)
         (build_mode.is_not_release() && !frontend_dist).to_string(),
     );
 
+    // [profile.release]
+    env::set_var("MOON_PROFILE", build_mode.config_profile());
+
     // custom configs from MoonZoonCustom.toml
     for (key, value) in &config.custom_env_vars {
         env::set_var(key, value);
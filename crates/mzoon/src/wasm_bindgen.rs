@@ -1,70 +1,95 @@
-use crate::{helper::download, BuildMode};
+use crate::config::Tools;
+use crate::helper::tool::{
+    cargo_install, check_tool_version, download_or_read_cached, find_compatible_tool,
+    tool_candidates,
+};
+use crate::BuildMode;
 use anyhow::{anyhow, Context, Error};
 use apply::Apply;
 use bool_ext::BoolExt;
-use cargo_metadata::MetadataCommand;
 use cfg_if::cfg_if;
-use const_format::{concatcp, formatcp};
 use fehler::throws;
 use flate2::read::GzDecoder;
+use serde::Deserialize;
+use std::env::consts::EXE_SUFFIX;
 use std::ffi::OsStr;
-use std::path::{Path, PathBuf, MAIN_SEPARATOR as SEP};
+use std::path::{Path, PathBuf};
 use tar::Archive;
-use tokio::process::Command;
+use tokio::{fs, process::Command};
 
+/// Used when `Cargo.lock` doesn't exist yet.
 // NOTE: Sync with zoon's wasm-bindgen version.
-const VERSION: &str = "0.2.100";
+const DEFAULT_VERSION: &str = "0.2.100";
+const WASM_BINDGEN_PATH: &str = "frontend/wasm-bindgen";
+const WASM_BINDGEN_TEST_RUNNER_PATH: &str = "frontend/wasm-bindgen-test-runner";
+/// Binaries compiled by `cargo install` are moved from `<root>/bin` to the `frontend` folder.
+const CARGO_INSTALL_ROOT: &str = "frontend/wasm-bindgen-cli";
 
 // -- public --
 
-#[throws]
-pub async fn check_or_install_wasm_bindgen() {
-    if check_wasm_bindgen().await.is_ok() {
-        return;
-    }
+pub struct WasmBindgen {
+    pub path: PathBuf,
+    /// The `wasm-bindgen` crate version from `Cargo.lock`.
+    pub version: String,
+}
 
-    const TARGET: &str = env!("TARGET");
-    cfg_if! {
-        if #[cfg(target_os = "macos")] {
-            cfg_if! {
-                if #[cfg(target_arch = "aarch64")] {
-                    const NEAREST_TARGET: &str = "aarch64-apple-darwin";
-                } else {
-                    const NEAREST_TARGET: &str = "x86_64-apple-darwin";
-                }
-            }
-        } else if #[cfg(target_os = "windows")] {
-            const NEAREST_TARGET: &str = "x86_64-pc-windows-msvc";
-        } else if #[cfg(target_os = "linux")] {
-            cfg_if! {
-                if #[cfg(target_arch = "aarch64")] {
-                    const NEAREST_TARGET: &str = "aarch64-unknown-linux-gnu";
-                } else {
-                    const NEAREST_TARGET: &str = "x86_64-unknown-linux-musl";
-                }
-            }
-        } else {
-            compile_error!("wasm-bindgen pre-compiled binary hasn't been found for the target platform '{TARGET}'");
-        }
-    }
-    const DOWNLOAD_URL: &str = formatcp!(
-        "https://github.com/rustwasm/wasm-bindgen/releases/download/{VERSION}/wasm-bindgen-{VERSION}-{NEAREST_TARGET}.tar.gz"
+#[throws]
+pub async fn check_or_install_wasm_bindgen(tools: &Tools) -> WasmBindgen {
+    let version = required_version().await?;
+    let candidates = tool_candidates(
+        tools.wasm_bindgen.as_deref(),
+        WASM_BINDGEN_PATH,
+        "wasm-bindgen",
     );
+    let path = match find_compatible_tool(&candidates, "-V", "wasm-bindgen ", &version).await {
+        Ok(path) => path,
+        Err(error) if tools.wasm_bindgen.is_some() => {
+            Err(error.context("Failed to use wasm-bindgen configured in MoonZoon.toml"))?
+        }
+        Err(_) => {
+            install_wasm_bindgen(&version, tools).await?;
+            check_tool_version(
+                Path::new(WASM_BINDGEN_PATH),
+                "-V",
+                "wasm-bindgen ",
+                &version,
+            )
+            .await
+            .context("wasm-bindgen installation failed")?;
+            PathBuf::from(WASM_BINDGEN_PATH)
+        }
+    };
+    WasmBindgen { path, version }
+}
 
-    println!("Downloading & Installing wasm-bindgen {VERSION} ...");
-    if TARGET != NEAREST_TARGET {
-        println!(
-            "Pre-compiled wasm-bindgen binary '{NEAREST_TARGET}' will be used for the target platform '{TARGET}'"
-        );
+/// `wasm-bindgen-test-runner` is distributed together with `wasm-bindgen`.
+/// Returns the path to a compatible `wasm-bindgen-test-runner`.
+#[throws]
+pub async fn check_or_install_wasm_bindgen_test_runner(tools: &Tools) -> PathBuf {
+    let version = required_version().await?;
+    let candidates = tool_candidates(
+        tools.wasm_bindgen_test_runner.as_deref(),
+        WASM_BINDGEN_TEST_RUNNER_PATH,
+        "wasm-bindgen-test-runner",
+    );
+    match find_compatible_tool(&candidates, "-V", "wasm-bindgen-test-runner ", &version).await {
+        Ok(path) => path,
+        Err(error) if tools.wasm_bindgen_test_runner.is_some() => {
+            Err(error.context("Failed to use wasm-bindgen-test-runner configured in MoonZoon.toml"))?
+        }
+        Err(_) => {
+            install_wasm_bindgen(&version, tools).await?;
+            check_tool_version(
+                Path::new(WASM_BINDGEN_TEST_RUNNER_PATH),
+                "-V",
+                "wasm-bindgen-test-runner ",
+                &version,
+            )
+            .await
+            .context("wasm-bindgen-test-runner installation failed")?;
+            PathBuf::from(WASM_BINDGEN_TEST_RUNNER_PATH)
+        }
     }
-    download(DOWNLOAD_URL)
-        .await
-        .context(formatcp!(
-            "Failed to download wasm-bindgen from the url '{DOWNLOAD_URL}'"
-        ))?
-        .apply(unpack_wasm_bindgen)
-        .context("Failed to unpack wasm-bindgen")?;
-    println!("wasm-bindgen installed");
 }
 
 // https://rustwasm.github.io/wasm-bindgen/reference/cli.html
@@ -72,8 +97,10 @@ pub async fn check_or_install_wasm_bindgen() {
 #[throws]
 pub async fn build_with_wasm_bindgen(
     build_mode: BuildMode,
+    wasm_bindgen_path: &Path,
     crate_name: &str,
     crate_path: &Path,
+    wasm_path: &Path,
     target: &str,
 ) {
     let pkg_path = crate_path.join("pkg");
@@ -91,14 +118,9 @@ pub async fn build_with_wasm_bindgen(
     if build_mode.is_dev() {
         args.push("--debug".as_ref());
     }
+    args.push(wasm_path.as_os_str());
 
-    let target_path = MetadataCommand::new().no_deps().exec()?.target_directory;
-    let target_profile_folder = build_mode.target_profile_folder();
-    let wasm_path =
-        format!("{target_path}{SEP}wasm32-unknown-unknown{SEP}{target_profile_folder}{SEP}{crate_name}.wasm");
-    args.push(wasm_path.as_ref());
-
-    Command::new("frontend/wasm-bindgen")
+    Command::new(wasm_bindgen_path)
         .args(&args)
         .status()
         .await
@@ -109,44 +131,160 @@ pub async fn build_with_wasm_bindgen(
 
 // -- private --
 
+/// The CLI has to have the same version as the `wasm-bindgen` crate used by the app.
 #[throws]
-async fn check_wasm_bindgen() {
-    const EXPECTED_VERSION_OUTPUT_START: &[u8] = concatcp!("wasm-bindgen ", VERSION).as_bytes();
-
-    let version_output = Command::new("frontend/wasm-bindgen")
-        .args(&["-V"])
-        .output()
-        .await?
-        .stdout;
-
-    if !version_output.starts_with(EXPECTED_VERSION_OUTPUT_START) {
-        Err(anyhow!(concatcp!(
-            "wasm-bindgen's expected version is ",
-            VERSION
-        )))?;
+async fn required_version() -> String {
+    #[derive(Deserialize)]
+    struct CargoLock {
+        #[serde(default)]
+        package: Vec<LockedPackage>,
+    }
+
+    #[derive(Deserialize)]
+    struct LockedPackage {
+        name: String,
+        version: String,
+    }
+
+    let cargo_lock = match fs::read_to_string("Cargo.lock").await {
+        Ok(cargo_lock) => cargo_lock,
+        Err(_) => return DEFAULT_VERSION.to_owned(),
+    };
+    let cargo_lock: CargoLock =
+        toml::from_str(&cargo_lock).context("Failed to parse Cargo.lock")?;
+    let mut versions = cargo_lock
+        .package
+        .into_iter()
+        .filter(|package| package.name == "wasm-bindgen")
+        .map(|package| package.version)
+        .collect::<Vec<_>>();
+    match versions.len() {
+        0 => DEFAULT_VERSION.to_owned(),
+        1 => versions.remove(0),
+        _ => Err(anyhow!(
+            "Cargo.lock contains multiple wasm-bindgen versions: {}",
+            versions.join(", ")
+        ))?,
     }
 }
 
+#[throws]
+async fn install_wasm_bindgen(version: &str, tools: &Tools) {
+    const TARGET: &str = env!("TARGET");
+    cfg_if! {
+        if #[cfg(target_os = "macos")] {
+            cfg_if! {
+                if #[cfg(target_arch = "aarch64")] {
+                    const NEAREST_TARGET: Option<&str> = Some("aarch64-apple-darwin");
+                } else {
+                    const NEAREST_TARGET: Option<&str> = Some("x86_64-apple-darwin");
+                }
+            }
+        } else if #[cfg(target_os = "windows")] {
+            const NEAREST_TARGET: Option<&str> = Some("x86_64-pc-windows-msvc");
+        } else if #[cfg(target_os = "linux")] {
+            cfg_if! {
+                if #[cfg(target_arch = "aarch64")] {
+                    const NEAREST_TARGET: Option<&str> = Some("aarch64-unknown-linux-gnu");
+                } else if #[cfg(target_arch = "x86_64")] {
+                    const NEAREST_TARGET: Option<&str> = Some("x86_64-unknown-linux-musl");
+                } else {
+                    const NEAREST_TARGET: Option<&str> = None;
+                }
+            }
+        } else {
+            const NEAREST_TARGET: Option<&str> = None;
+        }
+    }
+
+    match NEAREST_TARGET {
+        Some(nearest_target) => {
+            if let Err(error) = install_prebuilt_wasm_bindgen(version, nearest_target, tools).await
+            {
+                eprintln!("{error:#}");
+                cargo_install_wasm_bindgen(version).await?;
+            }
+        }
+        None => {
+            println!(
+                "wasm-bindgen pre-compiled binary hasn't been found for the target platform '{TARGET}'"
+            );
+            cargo_install_wasm_bindgen(version).await?;
+        }
+    }
+    println!("wasm-bindgen installed");
+}
+
+#[throws]
+async fn install_prebuilt_wasm_bindgen(version: &str, nearest_target: &str, tools: &Tools) {
+    const TARGET: &str = env!("TARGET");
+    let download_url = format!(
+        "https://github.com/rustwasm/wasm-bindgen/releases/download/{version}/wasm-bindgen-{version}-{nearest_target}.tar.gz"
+    );
+
+    println!("Downloading & Installing wasm-bindgen {version} ...");
+    if TARGET != nearest_target {
+        println!(
+            "Pre-compiled wasm-bindgen binary '{nearest_target}' will be used for the target platform '{TARGET}'"
+        );
+    }
+    download_or_read_cached(&download_url, tools.archive_cache.as_deref())
+        .await
+        .context(format!(
+            "Failed to download wasm-bindgen from the url '{download_url}'"
+        ))?
+        .apply(unpack_wasm_bindgen)
+        .context("Failed to unpack wasm-bindgen")?;
+}
+
+/// Moves `wasm-bindgen` and `wasm-bindgen-test-runner` to the `frontend` folder.
+#[throws]
+async fn cargo_install_wasm_bindgen(version: &str) {
+    cargo_install(
+        "wasm-bindgen-cli",
+        &format!("={version}"),
+        CARGO_INSTALL_ROOT,
+    )
+    .await?;
+    let bin_path = Path::new(CARGO_INSTALL_ROOT).join("bin");
+    for (file_stem, destination) in [
+        ("wasm-bindgen", WASM_BINDGEN_PATH),
+        ("wasm-bindgen-test-runner", WASM_BINDGEN_TEST_RUNNER_PATH),
+    ] {
+        fs::rename(
+            bin_path.join(format!("{file_stem}{EXE_SUFFIX}")),
+            format!("{destination}{EXE_SUFFIX}"),
+        )
+        .await
+        .with_context(|| format!("Failed to move {file_stem} to the frontend folder"))?;
+    }
+    fs::remove_dir_all(CARGO_INSTALL_ROOT).await?;
+}
+
+/// Unpacks `wasm-bindgen` and `wasm-bindgen-test-runner` to the `frontend` folder.
 #[throws]
 fn unpack_wasm_bindgen(tar_gz: Vec<u8>) {
     let tar = GzDecoder::new(tar_gz.as_slice());
     let mut archive = Archive::new(tar);
 
+    let mut wasm_bindgen_found = false;
     for entry in archive.entries()? {
         let mut entry = entry?;
         let path = entry.path()?;
         let file_stem = path
             .file_stem()
             .ok_or(anyhow!("Entry without a file name"))?;
-        if file_stem != "wasm-bindgen" {
+        if file_stem != "wasm-bindgen" && file_stem != "wasm-bindgen-test-runner" {
             continue;
         }
+        wasm_bindgen_found |= file_stem == "wasm-bindgen";
         let mut destination = PathBuf::from("frontend");
         destination.push(path.file_name().unwrap());
         entry.unpack(destination)?;
-        return;
     }
-    Err(anyhow!(
-        "Failed to find wasm-bindgen in the downloaded archive"
-    ))?;
+    if !wasm_bindgen_found {
+        Err(anyhow!(
+            "Failed to find wasm-bindgen in the downloaded archive"
+        ))?;
+    }
 }
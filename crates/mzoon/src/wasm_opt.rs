@@ -1,25 +1,41 @@
-use crate::{helper::download, BuildMode};
+use crate::config::Tools;
+use crate::helper::tool::{
+    cargo_install, check_tool_version, download_or_read_cached, find_compatible_tool,
+    tool_candidates,
+};
+use crate::BuildMode;
 use anyhow::{anyhow, Context, Error};
 use apply::Apply;
 use bool_ext::BoolExt;
 use cfg_if::cfg_if;
-use const_format::{concatcp, formatcp};
+use const_format::concatcp;
 use fehler::throws;
 use flate2::read::GzDecoder;
 use std::fs::create_dir_all;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use tar::Archive;
 use tokio::process::Command;
 
-const VERSION: &str = "116";
+pub const VERSION: &str = "116";
+const VERSION_PREFIX: &str = "wasm-opt version ";
 static WASM_OPT_PATH: &str = "frontend/binaryen/bin/wasm-opt";
+/// `cargo install` puts `wasm-opt` to `<root>/bin` - the same place as the unpacked binaryen archive.
+const CARGO_INSTALL_ROOT: &str = "frontend/binaryen";
+/// The `wasm-opt` crate's versions follow binaryen versions, e.g. `0.116.1` contains binaryen 116.
+const CRATE_VERSION: &str = concatcp!("0.", VERSION);
 
 // -- public --
 
+/// Returns the path to a compatible `wasm-opt`.
 #[throws]
-pub async fn check_or_install_wasm_opt() {
-    if check_wasm_opt().await.is_ok() {
-        return;
+pub async fn check_or_install_wasm_opt(tools: &Tools) -> PathBuf {
+    let candidates = tool_candidates(tools.wasm_opt.as_deref(), WASM_OPT_PATH, "wasm-opt");
+    match find_compatible_tool(&candidates, "--version", VERSION_PREFIX, VERSION).await {
+        Ok(path) => return path,
+        Err(error) if tools.wasm_opt.is_some() => {
+            Err(error.context("Failed to use wasm-opt configured in MoonZoon.toml"))?
+        }
+        Err(_) => (),
     }
 
     const TARGET: &str = env!("TARGET");
@@ -27,41 +43,53 @@ pub async fn check_or_install_wasm_opt() {
         if #[cfg(target_os = "macos")] {
             cfg_if! {
                 if #[cfg(target_arch = "aarch64")] {
-                    const ARCHIVE_PLATFORM: &str = "arm64-macos";
+                    const ARCHIVE_PLATFORM: Option<&str> = Some("arm64-macos");
                 } else {
-                    const ARCHIVE_PLATFORM: &str = "x86_64-macos";
+                    const ARCHIVE_PLATFORM: Option<&str> = Some("x86_64-macos");
                 }
             }
         } else if #[cfg(target_os = "windows")] {
-            const ARCHIVE_PLATFORM: &str = "x86_64-windows";
-        } else if #[cfg(target_os = "linux")] {
-            const ARCHIVE_PLATFORM: &str = "x86_64-linux";
+            const ARCHIVE_PLATFORM: Option<&str> = Some("x86_64-windows");
+        } else if #[cfg(all(target_os = "linux", target_arch = "x86_64"))] {
+            const ARCHIVE_PLATFORM: Option<&str> = Some("x86_64-linux");
         } else {
-            compile_error!("wasm-opt pre-compiled binary hasn't been found for the target platform '{TARGET}'");
+            const ARCHIVE_PLATFORM: Option<&str> = None;
         }
     }
-    const DOWNLOAD_URL: &str = formatcp!(
-        "https://github.com/WebAssembly/binaryen/releases/download/version_{VERSION}/binaryen-version_{VERSION}-{ARCHIVE_PLATFORM}.tar.gz",
-    );
 
-    println!("Downloading & Installing wasm-opt {VERSION} ...");
-    println!(
-        "Pre-compiled wasm-opt binary '{ARCHIVE_PLATFORM}' will be used for the target platform '{TARGET}'"
-    );
-
-    download(DOWNLOAD_URL)
-        .await
-        .context(formatcp!(
-            "Failed to download wasm-opt from the url '{DOWNLOAD_URL}'"
-        ))?
-        .apply(unpack_wasm_opt)
-        .await
-        .context("Failed to unpack wasm-opt")?;
+    match ARCHIVE_PLATFORM {
+        Some(archive_platform) => {
+            if let Err(error) = install_prebuilt_wasm_opt(archive_platform, tools).await {
+                eprintln!("{error:#}");
+                cargo_install("wasm-opt", CRATE_VERSION, CARGO_INSTALL_ROOT).await?;
+            }
+        }
+        None => {
+            println!(
+                "wasm-opt pre-compiled binary hasn't been found for the target platform '{TARGET}'"
+            );
+            cargo_install("wasm-opt", CRATE_VERSION, CARGO_INSTALL_ROOT).await?;
+        }
+    }
+    check_tool_version(
+        Path::new(WASM_OPT_PATH),
+        "--version",
+        VERSION_PREFIX,
+        VERSION,
+    )
+    .await
+    .context("wasm-opt installation failed")?;
     println!("wasm-opt installed");
+    PathBuf::from(WASM_OPT_PATH)
 }
 
 #[throws]
-pub async fn optimize_with_wasm_opt(build_mode: BuildMode, crate_name: &str, crate_path: &Path) {
+pub async fn optimize_with_wasm_opt(
+    build_mode: BuildMode,
+    wasm_opt_path: &Path,
+    crate_name: &str,
+    crate_path: &Path,
+) {
     let wasm_path = crate_path.join("pkg").join(format!("{crate_name}_bg.wasm"));
     let mut args = vec![
         wasm_path.as_os_str(),
@@ -75,7 +103,7 @@ pub async fn optimize_with_wasm_opt(build_mode: BuildMode, crate_name: &str, cra
     if let BuildMode::Profiling = build_mode {
         args.push("--debuginfo".as_ref());
     }
-    Command::new(WASM_OPT_PATH)
+    Command::new(wasm_opt_path)
         .args(&args)
         .status()
         .await
@@ -87,25 +115,28 @@ pub async fn optimize_with_wasm_opt(build_mode: BuildMode, crate_name: &str, cra
 // -- private --
 
 #[throws]
-async fn check_wasm_opt() {
-    const EXPECTED_VERSION_OUTPUT_START: &[u8] = concatcp!("wasm-opt version ", VERSION).as_bytes();
+async fn install_prebuilt_wasm_opt(archive_platform: &str, tools: &Tools) {
+    const TARGET: &str = env!("TARGET");
+    let download_url = format!(
+        "https://github.com/WebAssembly/binaryen/releases/download/version_{VERSION}/binaryen-version_{VERSION}-{archive_platform}.tar.gz",
+    );
 
-    let version_output = Command::new(WASM_OPT_PATH)
-        .args(&["--version"])
-        .output()
-        .await?
-        .stdout;
+    println!("Downloading & Installing wasm-opt {VERSION} ...");
+    println!(
+        "Pre-compiled wasm-opt binary '{archive_platform}' will be used for the target platform '{TARGET}'"
+    );
 
-    if !version_output.starts_with(EXPECTED_VERSION_OUTPUT_START) {
-        Err(anyhow!(concatcp!(
-            "wasm-opt's expected version is ",
-            VERSION
-        )))?;
-    }
+    download_or_read_cached(&download_url, tools.archive_cache.as_deref())
+        .await
+        .context(format!(
+            "Failed to download wasm-opt from the url '{download_url}'"
+        ))?
+        .apply(unpack_wasm_opt)
+        .context("Failed to unpack wasm-opt")?;
 }
 
 #[throws]
-async fn unpack_wasm_opt(tar_gz: Vec<u8>) {
+fn unpack_wasm_opt(tar_gz: Vec<u8>) {
     let tar = GzDecoder::new(tar_gz.as_slice());
     let mut archive = Archive::new(tar);
 
@@ -130,8 +161,4 @@ async fn unpack_wasm_opt(tar_gz: Vec<u8>) {
         create_dir_all(output_dir)?;
         entry.unpack(format!("{output_dir}/{file_name}"))?;
     }
-
-    if let Err(error) = check_wasm_opt().await {
-        eprintln!("wasm-opt installation failed: {error:#}");
-    }
 }
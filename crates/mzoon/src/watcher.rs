@@ -1,6 +1,8 @@
 mod backend_watcher;
+mod build_error_reporter;
 mod frontend_watcher;
 mod project_watcher;
 
 pub use backend_watcher::BackendWatcher;
+pub use build_error_reporter::BuildErrorReporter;
 pub use frontend_watcher::FrontendWatcher;
@@ -1,4 +1,4 @@
-use super::project_watcher::ProjectWatcher;
+use super::{project_watcher::ProjectWatcher, BuildErrorReporter};
 use crate::build_backend::build_backend;
 use crate::config::Config;
 use crate::run_backend::run_backend;
@@ -24,6 +24,7 @@ impl BackendWatcher {
         build_mode: BuildMode,
         debounce_time: Duration,
         server: Arc<Mutex<Option<Child>>>,
+        build_error_reporter: Arc<BuildErrorReporter>,
     ) -> Self {
         let (watcher, debounced_receiver) =
             ProjectWatcher::start(&config.watch.backend, debounce_time)
@@ -35,6 +36,7 @@ impl BackendWatcher {
                 build_mode,
                 config.https,
                 server,
+                build_error_reporter,
             )),
         }
     }
@@ -52,6 +54,7 @@ async fn on_change(
     build_mode: BuildMode,
     https: bool,
     server: Arc<Mutex<Option<Child>>>,
+    build_error_reporter: Arc<BuildErrorReporter>,
 ) {
     let mut build_task = None::<JoinHandle<()>>;
 
@@ -65,7 +68,12 @@ async fn on_change(
             let _ = server.kill().await;
         }
 
-        build_task = Some(spawn(build_and_run(Arc::clone(&server), build_mode, https)));
+        build_task = Some(spawn(build_and_run(
+            Arc::clone(&server),
+            build_mode,
+            https,
+            Arc::clone(&build_error_reporter),
+        )));
     }
 
     if let Some(build_task) = build_task.take() {
@@ -73,12 +81,23 @@ async fn on_change(
     }
 }
 
-async fn build_and_run(server: Arc<Mutex<Option<Child>>>, build_mode: BuildMode, https: bool) {
-    if let Err(error) = build_backend(build_mode, https).await {
-        return eprintln!("{}", error);
+async fn build_and_run(
+    server: Arc<Mutex<Option<Child>>>,
+    build_mode: BuildMode,
+    https: bool,
+    build_error_reporter: Arc<BuildErrorReporter>,
+) {
+    let result = build_backend(build_mode, https).await;
+    if let Err(error) = &result {
+        eprintln!("{}", error);
     }
+    build_error_reporter.set_backend_error(result.as_ref().err());
+    // the previous backend binary is started to display errors in the browser
     match run_backend(build_mode) {
-        Ok(backend) => *server.lock() = Some(backend),
+        Ok(backend) => {
+            *server.lock() = Some(backend);
+            build_error_reporter.send_to_new_server().await;
+        }
         Err(error) => eprintln!("{}", error),
     }
 }
@@ -0,0 +1,84 @@
+use crate::cargo_diagnostics::{CompilationError, Diagnostic};
+use crate::config::Config;
+use parking_lot::Mutex;
+use tokio::time::{sleep, Duration};
+
+/// Attempts to send errors to the Moon server that has just been started.
+const SEND_ATTEMPTS: usize = 20;
+const SEND_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Sends the latest build errors to Moon, it displays them in the browser overlay.
+/// An empty list hides the overlay.
+pub struct BuildErrorReporter {
+    url: String,
+    frontend_diagnostics: Mutex<Vec<Diagnostic>>,
+    backend_diagnostics: Mutex<Vec<Diagnostic>>,
+}
+
+impl BuildErrorReporter {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            url: format!(
+                "{protocol}://localhost:{port}/_api/build_errors",
+                protocol = if config.https { "https" } else { "http" },
+                port = config.port
+            ),
+            frontend_diagnostics: Mutex::default(),
+            backend_diagnostics: Mutex::default(),
+        }
+    }
+
+    /// `None` means the build has succeeded.
+    pub fn set_frontend_error(&self, error: Option<&anyhow::Error>) {
+        *self.frontend_diagnostics.lock() = error_to_diagnostics("frontend", error);
+    }
+
+    /// `None` means the build has succeeded.
+    pub fn set_backend_error(&self, error: Option<&anyhow::Error>) {
+        *self.backend_diagnostics.lock() = error_to_diagnostics("backend", error);
+    }
+
+    pub async fn send(&self) {
+        self.send_with_attempts(1).await;
+    }
+
+    /// Waits until the new Moon server is ready. A new server doesn't know previous errors.
+    pub async fn send_to_new_server(&self) {
+        let has_errors = !self.frontend_diagnostics.lock().is_empty()
+            || !self.backend_diagnostics.lock().is_empty();
+        if has_errors {
+            self.send_with_attempts(SEND_ATTEMPTS).await;
+        }
+    }
+
+    async fn send_with_attempts(&self, attempts: usize) {
+        let mut diagnostics = self.frontend_diagnostics.lock().clone();
+        diagnostics.extend(self.backend_diagnostics.lock().iter().cloned());
+        let client = reqwest::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap();
+        let body = serde_json::to_string(&diagnostics).unwrap();
+        for attempt in 1..=attempts {
+            match client.post(&self.url).body(body.clone()).send().await {
+                Ok(_) => return,
+                Err(error) if attempt == attempts => {
+                    eprintln!("Failed to send build errors to the browser: {error:?}")
+                }
+                Err(_) => sleep(SEND_RETRY_DELAY).await,
+            }
+        }
+    }
+}
+
+fn error_to_diagnostics(crate_name: &str, error: Option<&anyhow::Error>) -> Vec<Diagnostic> {
+    let Some(error) = error else {
+        return Vec::new();
+    };
+    match error.downcast_ref::<CompilationError>() {
+        Some(CompilationError { diagnostics, .. }) if !diagnostics.is_empty() => {
+            diagnostics.clone()
+        }
+        _ => vec![Diagnostic::from_error(crate_name, error)],
+    }
+}
@@ -1,6 +1,7 @@
-use super::project_watcher::ProjectWatcher;
+use super::{project_watcher::ProjectWatcher, BuildErrorReporter};
 use crate::build_frontend::build_frontend;
-use crate::config::Config;
+use crate::cargo_diagnostics::CompilationStopped;
+use crate::config::{Config, Tools};
 use crate::BuildMode;
 use anyhow::{Context, Error, Result};
 use fehler::throws;
@@ -12,6 +13,16 @@ use tokio::{
     time::{sleep, Duration},
 };
 
+struct BuildSettings {
+    reload_url: String,
+    build_mode: BuildMode,
+    cache_busting: bool,
+    frontend_multithreading: bool,
+    fingerprint_public_assets: bool,
+    tools: Tools,
+    build_error_reporter: Arc<BuildErrorReporter>,
+}
+
 pub struct FrontendWatcher {
     #[allow(dead_code)]
     watcher: ProjectWatcher,
@@ -20,26 +31,37 @@ pub struct FrontendWatcher {
 
 impl FrontendWatcher {
     #[throws]
-    pub async fn start(config: &Config, build_mode: BuildMode, debounce_time: Duration) -> Self {
+    pub async fn start(
+        config: &Config,
+        build_mode: BuildMode,
+        debounce_time: Duration,
+        build_error_reporter: Arc<BuildErrorReporter>,
+    ) -> Self {
         let (watcher, debounced_receiver) =
             ProjectWatcher::start(&config.watch.frontend, debounce_time)
                 .context("Failed to start the frontend project watcher")?;
 
-        let reload_url = Arc::new(format!(
-            "{protocol}://localhost:{port}/_api/reload",
-            protocol = if config.https { "https" } else { "http" },
-            port = config.port
-        ));
+        let settings = BuildSettings {
+            // `hot` lets Zoon preserve its state during the reload
+            reload_url: format!(
+                "{protocol}://localhost:{port}/_api/reload?hot=true",
+                protocol = if config.https { "https" } else { "http" },
+                port = config.port
+            ),
+            build_mode,
+            cache_busting: config.cache_busting,
+            frontend_multithreading: config.frontend_multithreading == Some(true),
+            fingerprint_public_assets: config
+                .public_assets
+                .as_ref()
+                .is_some_and(|public_assets| public_assets.fingerprint),
+            tools: config.tools.clone(),
+            build_error_reporter,
+        };
 
         Self {
             watcher,
-            task: spawn(on_change(
-                debounced_receiver,
-                reload_url,
-                build_mode,
-                config.cache_busting,
-                config.frontend_multithreading == Some(true),
-            )),
+            task: spawn(on_change(debounced_receiver, Arc::new(settings))),
         }
     }
 
@@ -51,13 +73,7 @@ impl FrontendWatcher {
 }
 
 #[throws]
-async fn on_change(
-    mut receiver: UnboundedReceiver<()>,
-    reload_url: Arc<String>,
-    build_mode: BuildMode,
-    cache_busting: bool,
-    frontend_multithreading: bool,
-) {
+async fn on_change(mut receiver: UnboundedReceiver<()>, settings: Arc<BuildSettings>) {
     let mut build_task = None::<JoinHandle<()>>;
     let mut compilation_killer_sender = None::<watch::Sender<()>>;
 
@@ -75,10 +91,7 @@ async fn on_change(
 
         let (new_compilation_killer_sender, _) = watch::channel(());
         build_task = Some(spawn(build_and_reload(
-            Arc::clone(&reload_url),
-            build_mode,
-            cache_busting,
-            frontend_multithreading,
+            Arc::clone(&settings),
             Some(new_compilation_killer_sender.subscribe()),
         )));
         compilation_killer_sender = Some(new_compilation_killer_sender);
@@ -94,24 +107,32 @@ async fn on_change(
 }
 
 async fn build_and_reload(
-    reload_url: Arc<String>,
-    build_mode: BuildMode,
-    cache_busting: bool,
-    frontend_multithreading: bool,
+    settings: Arc<BuildSettings>,
     compilation_killer: Option<watch::Receiver<()>>,
 ) {
-    if let Err(error) = build_frontend(
-        build_mode,
-        cache_busting,
+    let result = build_frontend(
+        settings.build_mode,
+        settings.cache_busting,
         false,
-        frontend_multithreading,
+        settings.frontend_multithreading,
+        settings.fingerprint_public_assets,
+        &settings.tools,
         compilation_killer,
     )
-    .await
-    {
+    .await;
+    // the next build reports its own result
+    if let Err(error) = &result {
+        if error.is::<CompilationStopped>() {
+            return;
+        }
+    }
+    let build_error_reporter = &settings.build_error_reporter;
+    build_error_reporter.set_frontend_error(result.as_ref().err());
+    build_error_reporter.send().await;
+    if let Err(error) = result {
         return eprintln!("{error}");
     }
-    if build_mode.is_release() {
+    if settings.build_mode.is_release() {
         return println!("['Reload frontend' is deactivated in release mode]");
     }
     println!("Reload frontend");
@@ -119,7 +140,7 @@ async fn build_and_reload(
         .danger_accept_invalid_certs(true)
         .build()
         .unwrap()
-        .post(reload_url.as_str())
+        .post(settings.reload_url.as_str())
         .send()
         .await;
     if let Err(error) = response {
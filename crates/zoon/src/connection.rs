@@ -1,56 +1,183 @@
 use crate::*;
-use moonlight::SessionId;
+use moonlight::{Codec, SessionId, UpMsgFailure, UpMsgResultTransporter, UpMsgTransporterForSer};
 use std::{
+    cell::RefCell,
     collections::BTreeMap,
     error::Error,
     fmt,
     marker::PhantomData,
-    pin::Pin,
+    pin::{pin, Pin},
     sync::{Arc, Mutex},
 };
 use web_sys::{Request, RequestInit, Response};
 
+mod lifecycle;
+pub use lifecycle::ConnectionStatus;
+use lifecycle::Lifecycle;
+
+mod outbox;
+use outbox::Outbox;
+
 mod sse;
 use sse::SSE;
 
-// ------ DMsgSenders ------
+mod web_socket;
+use web_socket::WebSocket;
+
+/// See `up_msg_handler_responder` in Moon.
+const UP_MSG_HANDLER_ERROR_STATUS: u16 = 422;
+/// Moon is overloaded (`429 Too Many Requests`) or it's shutting down (`503 Service Unavailable`).
+const RETRY_LATER_STATUSES: [u16; 2] = [429, 503];
 
-struct DMsgSenders<DMsg>(Arc<Mutex<BTreeMap<CorId, oneshot::Sender<DMsg>>>>);
+type AuthTokenGetter =
+    Arc<dyn Fn() -> Pin<Box<dyn Future<Output = Option<AuthToken>>>> + Send + Sync>;
 
-impl<DMsg> DMsgSenders<DMsg> {
+// ------ CorIdSenders ------
+
+/// Senders of `DownMsg`s or `UpMsg` results waiting for the message with the given `CorId`.
+struct CorIdSenders<T>(Arc<Mutex<BTreeMap<CorId, oneshot::Sender<T>>>>);
+
+impl<T> CorIdSenders<T> {
     fn new() -> Self {
         Self(Arc::new(Mutex::new(BTreeMap::new())))
     }
 
-    fn remove(&self, cor_id: &CorId) -> Option<oneshot::Sender<DMsg>> {
+    fn remove(&self, cor_id: &CorId) -> Option<oneshot::Sender<T>> {
         self.0.lock().unwrap_throw().remove(cor_id)
     }
 
-    fn insert(&self, cor_id: CorId, sender: oneshot::Sender<DMsg>) {
+    #[must_use]
+    fn insert(&self, cor_id: CorId, sender: oneshot::Sender<T>) -> CorIdSenderGuard<T> {
         self.0.lock().unwrap_throw().insert(cor_id, sender);
+        CorIdSenderGuard {
+            senders: self,
+            cor_id,
+        }
     }
 }
 
-impl<DMsg> Clone for DMsgSenders<DMsg> {
+impl<T> Clone for CorIdSenders<T> {
     fn clone(&self) -> Self {
-        DMsgSenders(Arc::clone(&self.0))
+        CorIdSenders(Arc::clone(&self.0))
+    }
+}
+
+// ------ CorIdSenderGuard ------
+
+/// Removes the sender on drop so senders of timed out or cancelled exchanges don't leak.
+struct CorIdSenderGuard<'a, T> {
+    senders: &'a CorIdSenders<T>,
+    cor_id: CorId,
+}
+
+impl<T> Drop for CorIdSenderGuard<'_, T> {
+    fn drop(&mut self) {
+        self.senders.remove(&self.cor_id);
+    }
+}
+
+// ------ OnlineListener ------
+
+struct OnlineListener {
+    event: &'static str,
+    closure: SendWrapper<Closure<dyn Fn()>>,
+}
+
+impl OnlineListener {
+    fn new(event: &'static str, online: Mutable<bool>) -> Self {
+        let closure = Closure::new(move || online.set_neq(window().navigator().on_line()));
+        window()
+            .add_event_listener_with_callback(event, closure.as_ref().unchecked_ref())
+            .unwrap_throw();
+        Self {
+            event,
+            closure: SendWrapper::new(closure),
+        }
+    }
+}
+
+impl Drop for OnlineListener {
+    fn drop(&mut self) {
+        let _ = window()
+            .remove_event_listener_with_callback(self.event, self.closure.as_ref().unchecked_ref());
+    }
+}
+
+// ------ Transport ------
+
+/// How `UpMsg`s and `DownMsg`s are transported between Zoon and Moon.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Transport {
+    /// `UpMsg`s are sent in `fetch` POST requests, `DownMsg`s are received through Server-Sent Events.
+    #[default]
+    SSE,
+    /// Both `UpMsg`s and `DownMsg`s are transported through one WebSocket.
+    WebSocket,
+}
+
+enum TransportConnection {
+    SSE(SSE),
+    WebSocket(WebSocket),
+}
+
+/// The getter is read when the future is polled, i.e. in a task started by the transport,
+/// so the getter set by `Connection::auth_token_getter` is used for the first handshake, too.
+async fn handshake_auth_token(
+    auth_token_getter: &RefCell<Option<AuthTokenGetter>>,
+) -> Option<AuthToken> {
+    let auth_token_getter = auth_token_getter.borrow().clone()?;
+    auth_token_getter().await
+}
+
+/// `EventSource` and `WebSocket` can't send custom headers,
+/// so the auth token is passed to Moon in the handshake url query.
+fn handshake_url(url: &str, auth_token: Option<AuthToken>) -> String {
+    match auth_token {
+        Some(auth_token) => format!(
+            "{}&{}={}",
+            url,
+            AuthToken::QUERY_PARAM,
+            String::from(js_sys::encode_uri_component(auth_token.as_str()))
+        ),
+        None => url.to_owned(),
     }
 }
 
 // ------ Connection ------
 
-pub struct Connection<UMsg, DMsg> {
+/// `UMsgError` is the error type returned from the Moon's `up_msg_handler`.
+pub struct Connection<UMsg, DMsg, UMsgError = ()> {
     session_id: SessionId,
-    _sse: SSE,
-    auth_token_getter:
-        Option<Box<dyn Fn() -> Pin<Box<dyn Future<Output = Option<AuthToken>>>> + Send + Sync>>,
-    msg_types: PhantomData<(UMsg, DMsg)>,
-    d_msg_senders: DMsgSenders<DMsg>,
+    codec: Codec,
+    transport_connection: TransportConnection,
+    lifecycle: Lifecycle,
+    retry_policy: Option<RetryPolicy>,
+    outbox: Option<Outbox>,
+    online: Mutable<bool>,
+    pending_up_msgs_count: Mutable<usize>,
+    _online_listeners: [OnlineListener; 2],
+    auth_token_getter: Option<AuthTokenGetter>,
+    msg_types: PhantomData<(UMsg, DMsg, UMsgError)>,
+    d_msg_senders: CorIdSenders<DMsg>,
+    up_msg_result_senders: CorIdSenders<Result<(), UpMsgFailure>>,
 }
 
-impl<UMsg: Serialize, DMsg: DeserializeOwned + 'static> Connection<UMsg, DMsg> {
+impl<UMsg, DMsg, UMsgError> Connection<UMsg, DMsg, UMsgError>
+where
+    UMsg: Serialize,
+    DMsg: DeserializeOwned + 'static,
+    UMsgError: DeserializeOwned,
+{
     pub fn new(down_msg_handler: impl FnMut(DMsg, CorId) + Send + Sync + 'static) -> Self {
-        let d_msg_senders = DMsgSenders::new();
+        Self::new_with_options(ConnectionOptions::default(), down_msg_handler)
+    }
+
+    pub fn new_with_options(
+        connection_options: ConnectionOptions,
+        down_msg_handler: impl FnMut(DMsg, CorId) + Send + Sync + 'static,
+    ) -> Self {
+        let d_msg_senders = CorIdSenders::new();
+        let up_msg_result_senders = CorIdSenders::new();
 
         let down_msg_handler = {
             let d_msg_senders = d_msg_senders.clone();
@@ -70,13 +197,71 @@ impl<UMsg: Serialize, DMsg: DeserializeOwned + 'static> Connection<UMsg, DMsg> {
             }
         };
 
+        let ConnectionOptions {
+            transport,
+            codec,
+            retry_policy,
+            outbox,
+        } = connection_options;
         let session_id = SessionId::new();
+
+        let online = Mutable::new(window().navigator().on_line());
+        let online_listeners = [
+            OnlineListener::new("online", online.clone()),
+            OnlineListener::new("offline", online.clone()),
+        ];
+        let pending_up_msgs_count = Mutable::new(0);
+        // WebSocket keeps unsent `UpMsg`s in its own queue.
+        let outbox = outbox
+            .filter(|_| transport == Transport::SSE)
+            .map(|storage_key| {
+                Outbox::new(
+                    storage_key,
+                    session_id,
+                    codec,
+                    retry_policy.unwrap_or_default(),
+                    online.clone(),
+                    pending_up_msgs_count.clone(),
+                )
+            });
+
+        let lifecycle = Lifecycle::new();
+        let transport_connection = match transport {
+            Transport::SSE => TransportConnection::SSE(SSE::new(
+                session_id,
+                codec,
+                lifecycle.clone(),
+                down_msg_handler,
+            )),
+            Transport::WebSocket => TransportConnection::WebSocket(WebSocket::new(
+                session_id,
+                codec,
+                lifecycle.clone(),
+                down_msg_handler,
+                {
+                    let up_msg_result_senders = up_msg_result_senders.clone();
+                    move |UpMsgResultTransporter { cor_id, result }| {
+                        if let Some(up_msg_result_sender) = up_msg_result_senders.remove(&cor_id) {
+                            let _ = up_msg_result_sender.send(result);
+                        }
+                    }
+                },
+            )),
+        };
         Self {
             session_id,
-            _sse: SSE::new(session_id, down_msg_handler),
+            codec,
+            transport_connection,
+            lifecycle,
+            retry_policy,
+            outbox,
+            online,
+            pending_up_msgs_count,
+            _online_listeners: online_listeners,
             auth_token_getter: None,
             msg_types: PhantomData,
             d_msg_senders,
+            up_msg_result_senders,
         }
     }
 
@@ -89,14 +274,53 @@ impl<UMsg: Serialize, DMsg: DeserializeOwned + 'static> Connection<UMsg, DMsg> {
         FIAT: Future<Output = IAT>,
     {
         let getter = Arc::new(getter);
-        self.auth_token_getter = Some(Box::new(move || {
+        let auth_token_getter: AuthTokenGetter = Arc::new(move || {
             let getter = Arc::clone(&getter);
             Box::pin(async move { getter().await.into() })
-        }));
+        });
+        if let Some(outbox) = &self.outbox {
+            outbox.set_auth_token_getter(Arc::clone(&auth_token_getter));
+        }
+        match &self.transport_connection {
+            TransportConnection::SSE(sse) => {
+                sse.set_auth_token_getter(Arc::clone(&auth_token_getter))
+            }
+            TransportConnection::WebSocket(web_socket) => {
+                web_socket.set_auth_token_getter(Arc::clone(&auth_token_getter))
+            }
+        }
+        self.auth_token_getter = Some(auth_token_getter);
         self
     }
 
-    pub async fn send_up_msg(&self, up_msg: UMsg) -> Result<CorId, SendUpMsgError> {
+    /// Called each time the connection to Moon is established.
+    pub fn on_open(self, on_open: impl FnMut() + Send + Sync + 'static) -> Self {
+        self.lifecycle.set_on_open(on_open);
+        self
+    }
+
+    /// Called after `on_open` when the lost connection has been reestablished.
+    /// Useful for refetching the state that may have changed in the meantime.
+    pub fn on_reconnect(self, on_reconnect: impl FnMut() + Send + Sync + 'static) -> Self {
+        self.lifecycle.set_on_reconnect(on_reconnect);
+        self
+    }
+
+    pub fn status_signal(&self) -> impl Signal<Item = ConnectionStatus> {
+        self.lifecycle.status_signal()
+    }
+
+    /// `true` when the browser is online (see `navigator.onLine`).
+    pub fn online_signal(&self) -> impl Signal<Item = bool> {
+        self.online.signal()
+    }
+
+    /// The number of `UpMsg`s waiting in the outbox (see `ConnectionOptions::outbox`).
+    pub fn pending_up_msgs_count_signal(&self) -> impl Signal<Item = usize> {
+        self.pending_up_msgs_count.signal()
+    }
+
+    pub async fn send_up_msg(&self, up_msg: UMsg) -> Result<CorId, SendUpMsgError<UMsgError>> {
         self.send_up_msg_with_options(up_msg, MsgOptions::default())
             .await
     }
@@ -105,7 +329,7 @@ impl<UMsg: Serialize, DMsg: DeserializeOwned + 'static> Connection<UMsg, DMsg> {
         &self,
         up_msg: UMsg,
         msg_options: MsgOptions,
-    ) -> Result<CorId, SendUpMsgError> {
+    ) -> Result<CorId, SendUpMsgError<UMsgError>> {
         self.send_up_msg_with_cor_id_and_options(up_msg, CorId::new(), msg_options)
             .await
     }
@@ -115,54 +339,103 @@ impl<UMsg: Serialize, DMsg: DeserializeOwned + 'static> Connection<UMsg, DMsg> {
         up_msg: UMsg,
         cor_id: CorId,
         msg_options: MsgOptions,
-    ) -> Result<CorId, SendUpMsgError> {
-        // ---- RequestInit ----
-        #[cfg(feature = "serde")]
-        let body = serde_json::to_string(&up_msg).unwrap_throw();
-
-        let request_init = RequestInit::new();
-        request_init.set_method("POST");
-        request_init.set_body(&JsValue::from(body));
-
-        // ---- Request ----
-        let request =
-            Request::new_with_str_and_init("/_api/up_msg_handler", &request_init).unwrap_throw();
-
-        // ---- Headers ----
-        let headers = request.headers();
-        headers
-            .set("X-Correlation-ID", &cor_id.to_string())
-            .unwrap_throw();
-        headers
-            .set("X-Session-ID", &self.session_id.to_string())
-            .unwrap_throw();
+    ) -> Result<CorId, SendUpMsgError<UMsgError>> {
+        let auth_token = match &self.auth_token_getter {
+            Some(auth_token_getter) if msg_options.auth_token => auth_token_getter().await,
+            _ => None,
+        };
 
-        if msg_options.auth_token {
-            let auth_token = if let Some(auth_token_getter) = &self.auth_token_getter {
-                auth_token_getter().await
-            } else {
-                None
+        if let TransportConnection::WebSocket(web_socket) = &self.transport_connection {
+            let up_msg_transporter = UpMsgTransporterForSer {
+                up_msg: &up_msg,
+                cor_id,
+                auth_token,
+            };
+            #[cfg(feature = "serde")]
+            let up_msg_transporter = self.codec.encode(&up_msg_transporter).unwrap_throw();
+
+            let (up_msg_result_sender, up_msg_result_receiver) = oneshot::channel();
+            let _up_msg_result_sender_guard = self
+                .up_msg_result_senders
+                .insert(cor_id, up_msg_result_sender);
+            web_socket
+                .send(up_msg_transporter)
+                .map_err(SendUpMsgError::RequestFailed)?;
+
+            let up_msg_result = future::select(
+                up_msg_result_receiver,
+                pin!(self.lifecycle.wait_for_connection_loss()),
+            )
+            .await;
+            return match up_msg_result {
+                future::Either::Left((Ok(Ok(())), _)) => Ok(cor_id),
+                future::Either::Left((Ok(Err(UpMsgFailure::HandlerError(error))), _)) => {
+                    match self.codec.decode(&error) {
+                        Ok(error) => Err(SendUpMsgError::UpMsgHandlerError(error)),
+                        Err(_) => Err(SendUpMsgError::ResponseIsNot2xx),
+                    }
+                }
+                future::Either::Left((Ok(Err(UpMsgFailure::Rejected(reason))), _)) => {
+                    Err(SendUpMsgError::Rejected(reason))
+                }
+                future::Either::Left((Err(_), _)) | future::Either::Right(_) => Err(
+                    SendUpMsgError::RequestFailed(JsValue::from("WebSocket connection lost")),
+                ),
             };
-            if let Some(auth_token) = auth_token {
-                headers
-                    .set("X-Auth-Token", auth_token.as_str())
-                    .unwrap_throw();
-            }
         }
 
-        // ---- Response ----
-        let response = JsFuture::from(window().fetch_with_request(&request))
-            .await
-            .map_err(|error| SendUpMsgError::RequestFailed(error))?
-            .unchecked_into::<Response>();
+        #[cfg(feature = "serde")]
+        let body = self.codec.encode(&up_msg).unwrap_throw();
+
+        if let Some(outbox) = self
+            .outbox
+            .as_ref()
+            .filter(|outbox| outbox.should_enqueue())
+        {
+            outbox.enqueue(cor_id, body, msg_options.auth_token);
+            return Ok(cor_id);
+        }
+
+        let response = fetch_up_msg_with_retries(
+            self.session_id,
+            self.codec,
+            cor_id,
+            &body,
+            auth_token.as_ref(),
+            self.retry_policy,
+        )
+        .await;
+        let response = match (response, &self.outbox) {
+            (Ok(response), _) => response,
+            (Err(_), Some(outbox)) => {
+                outbox.enqueue(cor_id, body, msg_options.auth_token);
+                return Ok(cor_id);
+            }
+            (Err(error), None) => Err(SendUpMsgError::RequestFailed(error))?,
+        };
 
         if response.ok() {
             return Ok(cor_id);
         }
+        if response.status() == UP_MSG_HANDLER_ERROR_STATUS {
+            if let Some(error) = self.decode_up_msg_handler_error(&response).await {
+                return Err(SendUpMsgError::UpMsgHandlerError(error));
+            }
+        }
         Err(SendUpMsgError::ResponseIsNot2xx)
     }
 
-    pub async fn exchange_msgs(&self, up_msg: UMsg) -> Result<(DMsg, CorId), ExchangeMsgsError> {
+    #[cfg(feature = "serde")]
+    async fn decode_up_msg_handler_error(&self, response: &Response) -> Option<UMsgError> {
+        let body = JsFuture::from(response.array_buffer().ok()?).await.ok()?;
+        let body = js_sys::Uint8Array::new(&body).to_vec();
+        self.codec.decode(&body).ok()
+    }
+
+    pub async fn exchange_msgs(
+        &self,
+        up_msg: UMsg,
+    ) -> Result<(DMsg, CorId), ExchangeMsgsError<UMsgError>> {
         self.exchange_msgs_with_options(up_msg, MsgOptions::default())
             .await
     }
@@ -171,32 +444,236 @@ impl<UMsg: Serialize, DMsg: DeserializeOwned + 'static> Connection<UMsg, DMsg> {
         &self,
         up_msg: UMsg,
         msg_options: MsgOptions,
-    ) -> Result<(DMsg, CorId), ExchangeMsgsError> {
+    ) -> Result<(DMsg, CorId), ExchangeMsgsError<UMsgError>> {
         let cor_id = CorId::new();
         let (d_msg_sender, d_msg_receiver) = oneshot::channel();
 
-        self.d_msg_senders.insert(cor_id, d_msg_sender);
+        let _d_msg_sender_guard = self.d_msg_senders.insert(cor_id, d_msg_sender);
 
-        self.send_up_msg_with_cor_id_and_options(up_msg, cor_id, msg_options)
-            .await
-            .map_err(ExchangeMsgsError::SendError)?;
-        let d_msg = d_msg_receiver
-            .await
-            .map_err(|_| ExchangeMsgsError::ReceiveError(ReceiveDownMsgError::ConnectionClosed))?;
+        let exchange = async {
+            self.send_up_msg_with_cor_id_and_options(up_msg, cor_id, msg_options)
+                .await
+                .map_err(ExchangeMsgsError::SendError)?;
+            d_msg_receiver
+                .await
+                .map_err(|_| ExchangeMsgsError::ReceiveError(ReceiveDownMsgError::ConnectionClosed))
+        };
+        let d_msg = match msg_options.timeout {
+            None => exchange.await?,
+            Some(timeout) => {
+                match future::select(pin!(exchange), pin!(Timer::sleep(timeout))).await {
+                    future::Either::Left((result, _)) => result?,
+                    future::Either::Right(_) => Err(ExchangeMsgsError::Timeout)?,
+                }
+            }
+        };
         Ok((d_msg, cor_id))
     }
 }
 
+// ------ fetch_up_msg ------
+
+async fn fetch_up_msg_with_retries(
+    session_id: SessionId,
+    codec: Codec,
+    cor_id: CorId,
+    body: &[u8],
+    auth_token: Option<&AuthToken>,
+    retry_policy: Option<RetryPolicy>,
+) -> Result<Response, JsValue> {
+    let mut attempt = 0;
+    loop {
+        let result = fetch_up_msg(session_id, codec, cor_id, body, auth_token).await;
+        attempt += 1;
+        let delay = match &result {
+            Ok(response) if RETRY_LATER_STATUSES.contains(&response.status()) => {
+                retry_after(response)
+            }
+            Ok(_) => return result,
+            Err(_) => None,
+        };
+        match retry_policy {
+            Some(retry_policy) if attempt < retry_policy.max_attempts => {
+                Timer::sleep(delay.unwrap_or_else(|| retry_policy.delay(attempt))).await
+            }
+            _ => return result,
+        }
+    }
+}
+
+/// The `Retry-After` header value in milliseconds.
+fn retry_after(response: &Response) -> Option<u32> {
+    let seconds = response.headers().get("Retry-After").ok()??;
+    Some(seconds.parse::<u32>().ok()?.saturating_mul(1000))
+}
+
+async fn fetch_up_msg(
+    session_id: SessionId,
+    codec: Codec,
+    cor_id: CorId,
+    body: &[u8],
+    auth_token: Option<&AuthToken>,
+) -> Result<Response, JsValue> {
+    // ---- RequestInit ----
+    let request_init = RequestInit::new();
+    request_init.set_method("POST");
+    request_init.set_body(&js_sys::Uint8Array::from(body));
+
+    // ---- Request ----
+    let request =
+        Request::new_with_str_and_init("/_api/up_msg_handler", &request_init).unwrap_throw();
+
+    // ---- Headers ----
+    let headers = request.headers();
+    headers
+        .set("X-Correlation-ID", &cor_id.to_string())
+        .unwrap_throw();
+    headers
+        .set("X-Session-ID", &session_id.to_string())
+        .unwrap_throw();
+    headers
+        .set("Content-Type", codec.content_type())
+        .unwrap_throw();
+
+    if let Some(auth_token) = auth_token {
+        headers
+            .set("X-Auth-Token", auth_token.as_str())
+            .unwrap_throw();
+    }
+
+    // ---- Response ----
+    let response = JsFuture::from(window().fetch_with_request(&request))
+        .await?
+        .unchecked_into::<Response>();
+    Ok(response)
+}
+
+// ------ ConnectionOptions ------
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConnectionOptions {
+    transport: Transport,
+    codec: Codec,
+    retry_policy: Option<RetryPolicy>,
+    outbox: Option<&'static str>,
+}
+
+impl ConnectionOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Binary codecs (e.g. `Codec::MessagePack` with the feature `msgpack`)
+    /// make messages smaller, JSON is easier to debug.
+    pub fn codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Failed `UpMsg` requests are resent according to the policy.
+    /// They aren't resent by default.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// `UpMsg`s that couldn't be sent are persisted in the local storage under the `storage_key`
+    /// and sent in order when the browser is online again.
+    /// `send_up_msg` returns `Ok` for such messages.
+    ///
+    /// _Note:_ Ignored for `Transport::WebSocket`, it queues unsent messages in memory.
+    pub fn outbox(mut self, storage_key: &'static str) -> Self {
+        self.outbox = Some(storage_key);
+        self
+    }
+}
+
+// ------ RetryPolicy ------
+
+/// Exponential backoff with optional jitter.
+///
+/// `429 Too Many Requests` and `503 Service Unavailable` responses
+/// are retried after the delay in their `Retry-After` header.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    min_delay_ms: u32,
+    max_delay_ms: u32,
+    jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            min_delay_ms: 250,
+            max_delay_ms: 10_000,
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Includes the first attempt.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// The delay before the first retry, doubled before each next one.
+    pub fn min_delay(mut self, ms: u32) -> Self {
+        self.min_delay_ms = ms;
+        self
+    }
+
+    pub fn max_delay(mut self, ms: u32) -> Self {
+        self.max_delay_ms = ms;
+        self
+    }
+
+    /// Randomizes delays between their halves and full values
+    /// so clients don't retry at the same moment.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    fn delay(&self, attempt: u32) -> u32 {
+        let delay = self
+            .min_delay_ms
+            .saturating_mul(2_u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay_ms);
+        if not(self.jitter) {
+            return delay;
+        }
+        let half_delay = delay / 2;
+        half_delay + (js_sys::Math::random() * f64::from(delay - half_delay)) as u32
+    }
+}
+
 // ------ MsgOptions ------
 
 #[derive(Debug, Clone, Copy)]
 pub struct MsgOptions {
     auth_token: bool,
+    timeout: Option<u32>,
 }
 
 impl Default for MsgOptions {
     fn default() -> Self {
-        Self { auth_token: true }
+        Self {
+            auth_token: true,
+            timeout: None,
+        }
     }
 }
 
@@ -209,17 +686,29 @@ impl MsgOptions {
         self.auth_token = include;
         self
     }
+
+    /// How long `exchange_msgs_with_options` waits for the `DownMsg` (in milliseconds).
+    /// It waits forever by default.
+    pub fn timeout(mut self, ms: impl Into<Option<u32>>) -> Self {
+        self.timeout = ms.into();
+        self
+    }
 }
 
 // ------ SendUpMsgError ------
 
 #[derive(Debug)]
-pub enum SendUpMsgError {
+pub enum SendUpMsgError<UMsgError = ()> {
     RequestFailed(JsValue),
     ResponseIsNot2xx,
+    /// The error returned from the Moon's `up_msg_handler`.
+    UpMsgHandlerError(UMsgError),
+    /// Moon hasn't passed the `UpMsg` sent through WebSocket to the `up_msg_handler`,
+    /// e.g. because of an invalid auth token.
+    Rejected(String),
 }
 
-impl fmt::Display for SendUpMsgError {
+impl<UMsgError: fmt::Debug> fmt::Display for SendUpMsgError<UMsgError> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::RequestFailed(error) => {
@@ -228,11 +717,17 @@ impl fmt::Display for SendUpMsgError {
             Self::ResponseIsNot2xx => {
                 write!(f, "response status is not 2xx")
             }
+            Self::UpMsgHandlerError(error) => {
+                write!(f, "UpMsg handler failed: {:?}", error)
+            }
+            Self::Rejected(reason) => {
+                write!(f, "UpMsg rejected: {}", reason)
+            }
         }
     }
 }
 
-impl Error for SendUpMsgError {}
+impl<UMsgError: fmt::Debug> Error for SendUpMsgError<UMsgError> {}
 
 // ------ ReceiveDownMsgError ------
 
@@ -256,12 +751,13 @@ impl Error for ReceiveDownMsgError {}
 // ------ ExchangeMsgsError ------
 
 #[derive(Debug)]
-pub enum ExchangeMsgsError {
-    SendError(SendUpMsgError),
+pub enum ExchangeMsgsError<UMsgError = ()> {
+    SendError(SendUpMsgError<UMsgError>),
     ReceiveError(ReceiveDownMsgError),
+    Timeout,
 }
 
-impl fmt::Display for ExchangeMsgsError {
+impl<UMsgError: fmt::Debug> fmt::Display for ExchangeMsgsError<UMsgError> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::SendError(error) => {
@@ -270,8 +766,35 @@ impl fmt::Display for ExchangeMsgsError {
             Self::ReceiveError(error) => {
                 write!(f, "{error}")
             }
+            Self::Timeout => {
+                write!(f, "DownMsg has not been received in time")
+            }
         }
     }
 }
 
-impl Error for ExchangeMsgsError {}
+impl<UMsgError: fmt::Debug> Error for ExchangeMsgsError<UMsgError> {}
+
+// ====== ====== TESTS ====== ======
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handshake_auth_token_uses_getter_set_after_connecting() {
+        // ------ ARRANGE ------
+        let auth_token_getter = RefCell::new(None);
+        // the transport starts connecting before `Connection::auth_token_getter` is called
+        let auth_token = handshake_auth_token(&auth_token_getter);
+
+        // ------ ACT ------
+        let getter: AuthTokenGetter =
+            Arc::new(|| Box::pin(async { Some(AuthToken::new("token")) }));
+        *auth_token_getter.borrow_mut() = Some(getter);
+        let auth_token = auth_token.now_or_never().flatten();
+
+        // ------ ASSERT ------
+        assert_eq!(auth_token.as_ref().map(AuthToken::as_str), Some("token"));
+    }
+}
@@ -0,0 +1,90 @@
+use crate::*;
+use std::sync::{Arc, Mutex};
+
+type Hook = Arc<Mutex<Option<Box<dyn FnMut() + Send + Sync>>>>;
+
+// ------ ConnectionStatus ------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    /// The first connection attempt hasn't succeeded yet.
+    Connecting,
+    Open,
+    /// The connection has been lost and it's being reestablished.
+    Reconnecting,
+    /// The `Connection` has been dropped.
+    Closed,
+}
+
+// ------ Lifecycle ------
+
+/// Shared by the `Connection` and its transport to track the `ConnectionStatus` and call hooks.
+#[derive(Clone)]
+pub struct Lifecycle {
+    status: Mutable<ConnectionStatus>,
+    on_open: Hook,
+    on_reconnect: Hook,
+}
+
+impl Lifecycle {
+    pub fn new() -> Self {
+        Self {
+            status: Mutable::new(ConnectionStatus::Connecting),
+            on_open: Hook::default(),
+            on_reconnect: Hook::default(),
+        }
+    }
+
+    pub fn status_signal(&self) -> impl Signal<Item = ConnectionStatus> {
+        self.status.signal()
+    }
+
+    pub fn set_on_open(&self, hook: impl FnMut() + Send + Sync + 'static) {
+        *self.on_open.lock().unwrap_throw() = Some(Box::new(hook));
+    }
+
+    pub fn set_on_reconnect(&self, hook: impl FnMut() + Send + Sync + 'static) {
+        *self.on_reconnect.lock().unwrap_throw() = Some(Box::new(hook));
+    }
+
+    pub fn opened(&self) {
+        let previous_status = self.status.replace(ConnectionStatus::Open);
+        if previous_status == ConnectionStatus::Open {
+            return;
+        }
+        call_hook(&self.on_open);
+        if previous_status == ConnectionStatus::Reconnecting {
+            call_hook(&self.on_reconnect);
+        }
+    }
+
+    /// Resolves when the connection that is open now or the next opened one has been lost.
+    pub async fn wait_for_connection_loss(&self) {
+        let mut statuses = self.status.signal().to_stream();
+        let mut was_open = false;
+        while let Some(status) = statuses.next().await {
+            if status == ConnectionStatus::Open {
+                was_open = true;
+            } else if was_open || status == ConnectionStatus::Closed {
+                return;
+            }
+        }
+    }
+
+    pub fn connection_lost(&self) {
+        let mut status = self.status.lock_mut();
+        if *status == ConnectionStatus::Open {
+            *status = ConnectionStatus::Reconnecting;
+        }
+    }
+
+    pub fn closed(&self) {
+        self.status.set_neq(ConnectionStatus::Closed);
+    }
+}
+
+fn call_hook(hook: &Hook) {
+    if let Some(hook) = hook.lock().unwrap_throw().as_mut() {
+        hook();
+    }
+}
@@ -0,0 +1,215 @@
+use super::{fetch_up_msg_with_retries, AuthTokenGetter, RetryPolicy, RETRY_LATER_STATUSES};
+use crate::moonlight::{Codec, SessionId};
+use crate::web_storage::{local_storage, WebStorage};
+use crate::*;
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    rc::Rc,
+};
+
+// ------ Outbox ------
+
+/// Persists `UpMsg`s that couldn't be sent and sends them in order once the browser is online again.
+pub struct Outbox {
+    state: SendWrapper<Rc<State>>,
+    _online_watcher: TaskHandle,
+}
+
+impl Outbox {
+    pub fn new(
+        storage_key: &'static str,
+        session_id: SessionId,
+        codec: Codec,
+        retry_policy: RetryPolicy,
+        online: Mutable<bool>,
+        pending_count: Mutable<usize>,
+    ) -> Self {
+        let entries = match local_storage().get::<VecDeque<OutboxEntry>>(storage_key) {
+            Some(Ok(entries)) => entries,
+            Some(Err(error)) => {
+                crate::eprintln!("failed to load the outbox '{}': {:?}", storage_key, error);
+                VecDeque::new()
+            }
+            None => VecDeque::new(),
+        };
+        pending_count.set_neq(entries.len());
+
+        let state = Rc::new(State {
+            storage_key,
+            session_id,
+            codec,
+            retry_policy,
+            auth_token_getter: RefCell::new(None),
+            entries: RefCell::new(entries),
+            online: online.clone(),
+            pending_count,
+            flushing: Cell::new(false),
+            flush_timer: RefCell::new(None),
+        });
+
+        // Flushes `UpMsg`s persisted by the previous app instance, too.
+        let online_watcher = Task::start_droppable({
+            let state = Rc::downgrade(&state);
+            online.signal().for_each_sync(move |online| {
+                if let Some(state) = state.upgrade().filter(|_| online) {
+                    Task::start(state.flush());
+                }
+            })
+        });
+
+        Self {
+            state: SendWrapper::new(state),
+            _online_watcher: online_watcher,
+        }
+    }
+
+    pub fn set_auth_token_getter(&self, auth_token_getter: AuthTokenGetter) {
+        *self.state.auth_token_getter.borrow_mut() = Some(auth_token_getter);
+    }
+
+    /// New `UpMsg`s have to wait in the outbox while it isn't empty to preserve their order.
+    pub fn should_enqueue(&self) -> bool {
+        !self.state.online.get() || !self.state.entries.borrow().is_empty()
+    }
+
+    pub fn enqueue(&self, cor_id: CorId, body: Vec<u8>, auth_token: bool) {
+        self.state.entries.borrow_mut().push_back(OutboxEntry {
+            cor_id,
+            body,
+            auth_token,
+        });
+        self.state.persist();
+        if self.state.online.get() {
+            Task::start(Rc::clone(&self.state).flush());
+        }
+    }
+}
+
+// ------ State ------
+
+struct State {
+    storage_key: &'static str,
+    session_id: SessionId,
+    codec: Codec,
+    retry_policy: RetryPolicy,
+    auth_token_getter: RefCell<Option<AuthTokenGetter>>,
+    entries: RefCell<VecDeque<OutboxEntry>>,
+    online: Mutable<bool>,
+    pending_count: Mutable<usize>,
+    flushing: Cell<bool>,
+    flush_timer: RefCell<Option<Timer>>,
+}
+
+impl State {
+    async fn flush(self: Rc<Self>) {
+        if self.flushing.replace(true) {
+            return;
+        }
+        self.flush_timer.take();
+
+        while self.online.get() {
+            let Some(entry) = self.entries.borrow().front().cloned() else {
+                break;
+            };
+            let auth_token_getter = self.auth_token_getter.borrow().clone();
+            let auth_token = match auth_token_getter {
+                Some(auth_token_getter) if entry.auth_token => auth_token_getter().await,
+                _ => None,
+            };
+            let response = fetch_up_msg_with_retries(
+                self.session_id,
+                self.codec,
+                entry.cor_id,
+                &entry.body,
+                auth_token.as_ref(),
+                Some(self.retry_policy),
+            )
+            .await;
+
+            match response {
+                Ok(response) if should_resend(response.status()) => {
+                    self.schedule_flush();
+                    break;
+                }
+                Ok(response) => {
+                    // Moon has processed the `UpMsg`, resending wouldn't change the response status.
+                    if !response.ok() {
+                        crate::eprintln!(
+                            "UpMsg from the outbox has been rejected with the status {}",
+                            response.status()
+                        );
+                    }
+                    self.entries.borrow_mut().pop_front();
+                    self.persist();
+                }
+                Err(_) => {
+                    self.schedule_flush();
+                    break;
+                }
+            }
+        }
+        self.flushing.set(false);
+    }
+
+    fn schedule_flush(self: &Rc<Self>) {
+        let this = Rc::downgrade(self);
+        let timer = Timer::once(self.retry_policy.max_delay_ms, move || {
+            if let Some(this) = this.upgrade() {
+                Task::start(this.flush());
+            }
+        });
+        *self.flush_timer.borrow_mut() = Some(timer);
+    }
+
+    fn persist(&self) {
+        let entries = self.entries.borrow();
+        self.pending_count.set_neq(entries.len());
+        if entries.is_empty() {
+            return local_storage().remove(self.storage_key);
+        }
+        if let Err(error) = local_storage().insert(self.storage_key, &*entries) {
+            crate::eprintln!(
+                "failed to persist the outbox '{}': {:?}",
+                self.storage_key,
+                error
+            );
+        }
+    }
+}
+
+/// Moon hasn't processed the `UpMsg` when it's overloaded, shutting down or failing.
+fn should_resend(status: u16) -> bool {
+    RETRY_LATER_STATUSES.contains(&status) || (500..600).contains(&status)
+}
+
+// ------ OutboxEntry ------
+
+#[derive(Clone, Serialize, Deserialize)]
+struct OutboxEntry {
+    cor_id: CorId,
+    body: Vec<u8>,
+    auth_token: bool,
+}
+
+// ====== ====== TESTS ====== ======
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_resend() {
+        // ------ ARRANGE ------
+        let kept_statuses = [429, 500, 502, 503];
+        let popped_statuses = [200, 204, 400, 401, 422];
+
+        // ------ ACT ------
+        let kept = kept_statuses.map(should_resend);
+        let popped = popped_statuses.map(should_resend);
+
+        // ------ ASSERT ------
+        assert_eq!(kept, [true; 4]);
+        assert_eq!(popped, [false; 5]);
+    }
+}
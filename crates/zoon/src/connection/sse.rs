@@ -1,20 +1,25 @@
 // @TODO remove / fix?
 #![allow(unexpected_cfgs)]
 
-use crate::moonlight::{DownMsgTransporterForDe, SessionId};
+use super::{handshake_auth_token, handshake_url, lifecycle::Lifecycle, AuthTokenGetter};
+use crate::moonlight::{Codec, CodecError, DownMsgTransporterForDe, SessionId};
 use crate::{format, *};
-use std::{error::Error, fmt};
+use std::{
+    cell::{Cell, RefCell},
+    error::Error,
+    fmt,
+    rc::{Rc, Weak},
+};
 
 // ------ SSE ------
 
 pub struct SSE {
-    reconnecting_event_source: SendWrapper<ReconnectingEventSource>,
-    _down_msg_handler: SendWrapper<Closure<dyn FnMut(JsValue)>>,
+    state: SendWrapper<Rc<State>>,
 }
 
 impl Drop for SSE {
     fn drop(&mut self) {
-        self.reconnecting_event_source.close();
+        self.state.close();
     }
 }
 
@@ -22,62 +27,177 @@ impl SSE {
     #[cfg(feature = "serde")]
     pub fn new<DMsg: DeserializeOwned>(
         session_id: SessionId,
+        codec: Codec,
+        lifecycle: Lifecycle,
         down_msg_handler: impl FnMut(DMsg, CorId) + 'static,
     ) -> Self {
-        let down_msg_handler = down_msg_handler_closure(down_msg_handler);
+        let state = Rc::new_cyclic(|this: &Weak<State>| State {
+            url: url(session_id, codec),
+            lifecycle,
+            auth_token_getter: RefCell::new(None),
+            reconnecting_event_source: RefCell::new(None),
+            closed: Cell::new(false),
+            down_msg_handler: down_msg_handler_closure(codec, down_msg_handler),
+            // `ReconnectingEventSource` calls `onopen` on each (re)connection
+            // and `onerror` only when an open connection has been lost.
+            on_open: {
+                let this = this.clone();
+                Closure::new(move || {
+                    if let Some(this) = this.upgrade() {
+                        this.lifecycle.opened();
+                    }
+                })
+            },
+            on_error: {
+                let this = this.clone();
+                Closure::new(move || {
+                    if let Some(this) = this.upgrade() {
+                        this.connection_lost();
+                    }
+                })
+            },
+            // Moon closes the connection once it has finished running `UpMsg` handlers,
+            // `ReconnectingEventSource` then reconnects to the restarted Moon with a backoff.
+            on_server_shutdown: {
+                let this = this.clone();
+                Closure::new(move || {
+                    if let Some(this) = this.upgrade() {
+                        this.connection_lost();
+                    }
+                })
+            },
+        });
+        // The connection is opened in a task so `Connection::auth_token_getter`
+        // is set before the first handshake.
+        Task::start(Rc::clone(&state).connect());
+        Self {
+            state: SendWrapper::new(state),
+        }
+    }
+
+    pub fn set_auth_token_getter(&self, auth_token_getter: AuthTokenGetter) {
+        *self.state.auth_token_getter.borrow_mut() = Some(auth_token_getter);
+    }
+}
+
+// ------ State ------
+
+struct State {
+    url: String,
+    lifecycle: Lifecycle,
+    auth_token_getter: RefCell<Option<AuthTokenGetter>>,
+    reconnecting_event_source: RefCell<Option<ReconnectingEventSource>>,
+    closed: Cell<bool>,
+    down_msg_handler: Closure<dyn FnMut(JsValue)>,
+    on_open: Closure<dyn Fn()>,
+    on_error: Closure<dyn Fn()>,
+    on_server_shutdown: Closure<dyn Fn()>,
+}
 
-        let reconnecting_event_source = connect(session_id);
+impl State {
+    async fn connect(self: Rc<Self>) {
+        let auth_token = handshake_auth_token(&self.auth_token_getter).await;
+        let url = handshake_url(&self.url, auth_token);
+        if self.closed.get() {
+            return;
+        }
+        let reconnecting_event_source = ReconnectingEventSource::new(
+            &url,
+            Some(ReconnectingEventSourceOptions {
+                withCredentials: false,
+                max_retry_time: 5000,
+            }),
+        );
         reconnecting_event_source
-            .add_event_listener("down_msg", down_msg_handler.as_ref().unchecked_ref());
+            .add_event_listener("down_msg", self.down_msg_handler.as_ref().unchecked_ref());
+        reconnecting_event_source.add_event_listener(
+            "server_shutdown",
+            self.on_server_shutdown.as_ref().unchecked_ref(),
+        );
+        reconnecting_event_source.set_onopen(self.on_open.as_ref().unchecked_ref());
+        reconnecting_event_source.set_onerror(self.on_error.as_ref().unchecked_ref());
+        self.reconnecting_event_source
+            .replace(Some(reconnecting_event_source));
+    }
 
-        Self {
-            reconnecting_event_source: SendWrapper::new(reconnecting_event_source),
-            _down_msg_handler: SendWrapper::new(down_msg_handler),
+    /// `ReconnectingEventSource` reconnects to its `url`,
+    /// so the url is updated with a fresh auth token for the next attempts.
+    fn connection_lost(self: Rc<Self>) {
+        self.lifecycle.connection_lost();
+        if self.auth_token_getter.borrow().is_none() {
+            return;
+        }
+        Task::start(async move {
+            let auth_token = handshake_auth_token(&self.auth_token_getter).await;
+            let url = handshake_url(&self.url, auth_token);
+            if let Some(reconnecting_event_source) =
+                self.reconnecting_event_source.borrow().as_ref()
+            {
+                reconnecting_event_source.set_url(&url);
+            }
+        });
+    }
+
+    fn close(&self) {
+        self.closed.set(true);
+        if let Some(reconnecting_event_source) = self.reconnecting_event_source.take() {
+            reconnecting_event_source.close();
         }
+        self.lifecycle.closed();
     }
 }
 
 #[cfg(feature = "serde")]
 fn down_msg_handler_closure<DMsg: DeserializeOwned>(
+    codec: Codec,
     mut down_msg_handler: impl FnMut(DMsg, CorId) + 'static,
 ) -> Closure<dyn FnMut(JsValue)> {
     Closure::new(
-        move |event: JsValue| match down_msg_transporter_from_event(event) {
+        move |event: JsValue| match decode_event_data(codec, event) {
             Ok(DownMsgTransporterForDe { down_msg, cor_id }) => down_msg_handler(down_msg, cor_id),
             Err(error) => crate::eprintln!("{:?}", error),
         },
     )
 }
 
+/// Decodes `DownMsgTransporter`s sent through SSE and frames sent through WebSocket.
 #[cfg(feature = "serde")]
-fn down_msg_transporter_from_event<DMsg: DeserializeOwned>(
+pub(super) fn decode_event_data<T: DeserializeOwned>(
+    codec: Codec,
     event: JsValue,
-) -> Result<DownMsgTransporterForDe<DMsg>, DownMsgError> {
-    let down_msg_transporter = Reflect::get(&event, &JsValue::from("data"))
-        .unwrap()
-        .as_string()
-        .ok_or(DownMsgError::InvalidDataValue)?;
+) -> Result<T, DownMsgError> {
+    let data = Reflect::get(&event, &JsValue::from("data")).unwrap();
+
+    // binary WebSocket messages
+    if let Some(array_buffer) = data.dyn_ref::<js_sys::ArrayBuffer>() {
+        let bytes = js_sys::Uint8Array::new(array_buffer).to_vec();
+        return codec
+            .decode(&bytes)
+            .map_err(DownMsgError::DeserializationFailed);
+    }
 
-    serde_json::from_str(&down_msg_transporter).map_err(DownMsgError::JsonDeserializationFailed)
+    let data = data.as_string().ok_or(DownMsgError::InvalidDataValue)?;
+    codec
+        .decode_from_str(&data)
+        .map_err(DownMsgError::DeserializationFailed)
 }
 
-fn connect(session_id: SessionId) -> ReconnectingEventSource {
-    ReconnectingEventSource::new(
-        &format!("/_api/message_sse/{}", session_id),
-        Some(ReconnectingEventSourceOptions {
-            withCredentials: false,
-            max_retry_time: 5000,
-        }),
+fn url(session_id: SessionId, codec: Codec) -> String {
+    format!(
+        "/_api/message_sse/{}?{}={}",
+        session_id,
+        Codec::QUERY_PARAM,
+        codec.name()
     )
 }
 
 // ------ DownMsgError ------
 
 #[derive(Debug)]
-enum DownMsgError {
+pub(super) enum DownMsgError {
     InvalidDataValue,
     #[cfg(feature = "serde")]
-    JsonDeserializationFailed(serde_json::Error),
+    DeserializationFailed(CodecError),
 }
 
 impl fmt::Display for DownMsgError {
@@ -87,12 +207,8 @@ impl fmt::Display for DownMsgError {
                 write!(f, "invalid DownMsg data value")
             }
             #[cfg(feature = "serde")]
-            DownMsgError::JsonDeserializationFailed(error) => {
-                write!(
-                    f,
-                    "failed to JSON deserialize DownMsgTransporter: {:?}",
-                    error
-                )
+            DownMsgError::DeserializationFailed(error) => {
+                write!(f, "failed to deserialize DownMsg data: {:?}", error)
             }
         }
     }
@@ -112,6 +228,15 @@ extern "C" {
     #[wasm_bindgen(method, js_name = addEventListener)]
     fn add_event_listener(this: &ReconnectingEventSource, type_: &str, listener: &js_sys::Function);
 
+    #[wasm_bindgen(method, setter)]
+    fn set_onopen(this: &ReconnectingEventSource, handler: &js_sys::Function);
+
+    #[wasm_bindgen(method, setter)]
+    fn set_onerror(this: &ReconnectingEventSource, handler: &js_sys::Function);
+
+    #[wasm_bindgen(method, setter)]
+    fn set_url(this: &ReconnectingEventSource, url: &str);
+
     #[wasm_bindgen(method)]
     fn close(this: &ReconnectingEventSource);
 }
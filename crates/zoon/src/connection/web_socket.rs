@@ -0,0 +1,265 @@
+use super::sse::decode_event_data;
+use super::{handshake_auth_token, handshake_url, lifecycle::Lifecycle, AuthTokenGetter};
+use crate::moonlight::{
+    Codec, DownMsgTransporterForDe, SessionId, UpMsgResultTransporter, WebSocketFrameForDe,
+};
+use crate::{format, *};
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    rc::{Rc, Weak},
+};
+
+const MIN_RECONNECT_DELAY_MS: u32 = 250;
+const MAX_RECONNECT_DELAY_MS: u32 = 5000;
+/// Moon closes sockets with the code "Service Restart" on shutdown.
+const SERVICE_RESTART_CLOSE_CODE: u16 = 1012;
+/// Clients reconnect to the restarted Moon at random moments in this window
+/// so they don't overload it.
+const SERVICE_RESTART_RECONNECT_WINDOW_MS: u32 = 5000;
+
+// ------ WebSocket ------
+
+pub struct WebSocket {
+    state: SendWrapper<Rc<State>>,
+}
+
+impl Drop for WebSocket {
+    fn drop(&mut self) {
+        self.state.close();
+    }
+}
+
+impl WebSocket {
+    #[cfg(feature = "serde")]
+    pub fn new<DMsg: DeserializeOwned>(
+        session_id: SessionId,
+        codec: Codec,
+        lifecycle: Lifecycle,
+        down_msg_handler: impl FnMut(DMsg, CorId) + 'static,
+        up_msg_result_handler: impl FnMut(UpMsgResultTransporter) + 'static,
+    ) -> Self {
+        let state = Rc::new_cyclic(|this: &Weak<State>| State {
+            url: url(session_id, codec),
+            codec,
+            lifecycle,
+            auth_token_getter: RefCell::new(None),
+            socket: RefCell::new(None),
+            pending_messages: RefCell::new(VecDeque::new()),
+            closed: Cell::new(false),
+            reconnect_attempts: Cell::new(0),
+            reconnect_timer: RefCell::new(None),
+            on_message: on_message_closure(codec, down_msg_handler, up_msg_result_handler),
+            on_open: {
+                let this = this.clone();
+                Closure::new(move || {
+                    if let Some(this) = this.upgrade() {
+                        this.on_open();
+                    }
+                })
+            },
+            on_close: {
+                let this = this.clone();
+                Closure::new(move |event: JsValue| {
+                    let code = Reflect::get(&event, &JsValue::from("code"))
+                        .ok()
+                        .and_then(|code| code.as_f64());
+                    if let Some(this) = this.upgrade() {
+                        this.schedule_reconnect(
+                            code == Some(f64::from(SERVICE_RESTART_CLOSE_CODE)),
+                        );
+                    }
+                })
+            },
+            this: this.clone(),
+        });
+        // The auth token getter is read in a task so the one set
+        // by `Connection::auth_token_getter` is used for the first handshake.
+        state.connect();
+        Self {
+            state: SendWrapper::new(state),
+        }
+    }
+
+    /// Sends the message immediately when the socket is open,
+    /// otherwise the message is sent once the socket is (re)connected.
+    pub fn send(&self, message: Vec<u8>) -> Result<(), JsValue> {
+        self.state.send(message)
+    }
+
+    pub fn set_auth_token_getter(&self, auth_token_getter: AuthTokenGetter) {
+        *self.state.auth_token_getter.borrow_mut() = Some(auth_token_getter);
+    }
+}
+
+// ------ State ------
+
+struct State {
+    url: String,
+    codec: Codec,
+    lifecycle: Lifecycle,
+    auth_token_getter: RefCell<Option<AuthTokenGetter>>,
+    socket: RefCell<Option<web_sys::WebSocket>>,
+    pending_messages: RefCell<VecDeque<Vec<u8>>>,
+    closed: Cell<bool>,
+    reconnect_attempts: Cell<u32>,
+    reconnect_timer: RefCell<Option<Timer>>,
+    on_message: Closure<dyn FnMut(JsValue)>,
+    on_open: Closure<dyn FnMut()>,
+    on_close: Closure<dyn FnMut(JsValue)>,
+    this: Weak<State>,
+}
+
+impl State {
+    /// Each (re)connection gets a fresh auth token.
+    fn connect(&self) {
+        let this = self.this.clone();
+        Task::start(async move {
+            let Some(this) = this.upgrade() else {
+                return;
+            };
+            let auth_token = handshake_auth_token(&this.auth_token_getter).await;
+            if !this.closed.get() {
+                this.open_socket(&handshake_url(&this.url, auth_token));
+            }
+        });
+    }
+
+    fn open_socket(&self, url: &str) {
+        let socket = match web_sys::WebSocket::new(url) {
+            Ok(socket) => socket,
+            Err(error) => {
+                crate::eprintln!("failed to create WebSocket: {:?}", error);
+                return self.schedule_reconnect(false);
+            }
+        };
+        socket.set_binary_type(web_sys::BinaryType::Arraybuffer);
+        socket.set_onmessage(Some(self.on_message.as_ref().unchecked_ref()));
+        socket.set_onopen(Some(self.on_open.as_ref().unchecked_ref()));
+        socket.set_onclose(Some(self.on_close.as_ref().unchecked_ref()));
+        self.socket.replace(Some(socket));
+    }
+
+    fn on_open(&self) {
+        self.reconnect_attempts.set(0);
+        if let Some(socket) = self.socket.borrow().as_ref() {
+            self.send_pending_messages(socket);
+        }
+        self.lifecycle.opened();
+    }
+
+    fn send_pending_messages(&self, socket: &web_sys::WebSocket) {
+        loop {
+            let message = self.pending_messages.borrow_mut().pop_front();
+            let Some(message) = message else {
+                break;
+            };
+            if let Err(error) = self.send_to_socket(socket, &message) {
+                crate::eprintln!("failed to send a pending WebSocket message: {:?}", error);
+                self.pending_messages.borrow_mut().push_front(message);
+                break;
+            }
+        }
+    }
+
+    fn send(&self, message: Vec<u8>) -> Result<(), JsValue> {
+        if let Some(socket) = self.socket.borrow().as_ref() {
+            if socket.ready_state() == web_sys::WebSocket::OPEN {
+                return self.send_to_socket(socket, &message);
+            }
+        }
+        self.pending_messages.borrow_mut().push_back(message);
+        Ok(())
+    }
+
+    fn send_to_socket(&self, socket: &web_sys::WebSocket, message: &[u8]) -> Result<(), JsValue> {
+        if self.codec.is_binary() {
+            socket.send_with_u8_array(message)
+        } else {
+            socket.send_with_str(&String::from_utf8_lossy(message))
+        }
+    }
+
+    fn schedule_reconnect(&self, service_restart: bool) {
+        if self.closed.get() {
+            return;
+        }
+        if let Some(socket) = self.socket.take() {
+            remove_handlers(&socket);
+        }
+        self.lifecycle.connection_lost();
+        let attempts = self.reconnect_attempts.get();
+        self.reconnect_attempts.set(attempts.saturating_add(1));
+
+        let delay = if service_restart {
+            MIN_RECONNECT_DELAY_MS
+                + (js_sys::Math::random() * f64::from(SERVICE_RESTART_RECONNECT_WINDOW_MS)) as u32
+        } else {
+            MIN_RECONNECT_DELAY_MS
+                .saturating_mul(2_u32.saturating_pow(attempts))
+                .min(MAX_RECONNECT_DELAY_MS)
+        };
+
+        let this = self.this.clone();
+        let timer = Timer::once(delay, move || {
+            if let Some(this) = this.upgrade() {
+                this.reconnect_timer.take();
+                this.connect();
+            }
+        });
+        self.reconnect_timer.replace(Some(timer));
+    }
+
+    fn close(&self) {
+        self.closed.set(true);
+        self.reconnect_timer.take();
+        if let Some(socket) = self.socket.take() {
+            remove_handlers(&socket);
+            let _ = socket.close();
+        }
+        self.lifecycle.closed();
+    }
+}
+
+#[cfg(feature = "serde")]
+fn on_message_closure<DMsg: DeserializeOwned>(
+    codec: Codec,
+    mut down_msg_handler: impl FnMut(DMsg, CorId) + 'static,
+    mut up_msg_result_handler: impl FnMut(UpMsgResultTransporter) + 'static,
+) -> Closure<dyn FnMut(JsValue)> {
+    Closure::new(
+        move |event: JsValue| match decode_event_data(codec, event) {
+            Ok(WebSocketFrameForDe::DownMsg(DownMsgTransporterForDe { down_msg, cor_id })) => {
+                down_msg_handler(down_msg, cor_id)
+            }
+            Ok(WebSocketFrameForDe::UpMsgResult(up_msg_result)) => {
+                up_msg_result_handler(up_msg_result)
+            }
+            Err(error) => crate::eprintln!("{:?}", error),
+        },
+    )
+}
+
+fn remove_handlers(socket: &web_sys::WebSocket) {
+    socket.set_onmessage(None);
+    socket.set_onopen(None);
+    socket.set_onclose(None);
+}
+
+fn url(session_id: SessionId, codec: Codec) -> String {
+    let location = window().location();
+    let protocol = if location.protocol().unwrap_throw() == "https:" {
+        "wss"
+    } else {
+        "ws"
+    };
+    let host = location.host().unwrap_throw();
+    format!(
+        "{}://{}/_api/ws/{}?{}={}",
+        protocol,
+        host,
+        session_id,
+        Codec::QUERY_PARAM,
+        codec.name()
+    )
+}
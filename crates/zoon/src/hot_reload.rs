@@ -0,0 +1,106 @@
+use crate::*;
+use std::{cell::RefCell, sync::OnceLock};
+
+// NOTE: Sync the keys and the event name with Moon's `sse_auto_reload.js`.
+const HOT_RELOAD_KEY: &str = "moon_hot_reload";
+const SCROLL_POSITION_KEY: &str = "moon_hot_reload_scroll";
+const HOT_RELOAD_EVENT: &str = "moon_hot_reload";
+const STATE_KEY_PREFIX: &str = "moon_hot_reload_state:";
+
+thread_local! {
+    static SNAPSHOTS: RefCell<Vec<Box<dyn Fn()>>> = RefCell::new(Vec::new());
+}
+
+/// Returns `true` when the app has been reloaded by `mzoon start` after a frontend rebuild
+/// and not opened or refreshed by the user.
+pub fn is_hot_reload() -> bool {
+    static IS_HOT_RELOAD: OnceLock<bool> = OnceLock::new();
+    *IS_HOT_RELOAD.get_or_init(|| {
+        let Ok(storage) = SessionStorage::try_new() else {
+            return false;
+        };
+        let is_hot_reload = matches!(storage.get::<bool>(HOT_RELOAD_KEY), Some(Ok(true)));
+        storage.remove(HOT_RELOAD_KEY);
+        is_hot_reload
+    })
+}
+
+/// Keeps the `mutable`'s value during hot reloads.
+///
+/// The value is saved to `session_storage` before the reload and restored when this function
+/// is called again after the reload, e.g. in a `static_ref` function or a `Lazy` initializer.
+/// `key` has to be unique in the app.
+///
+/// ```ignore
+/// #[static_ref]
+/// fn counter() -> &'static Mutable<i32> {
+///     let counter = Mutable::new(0);
+///     hot_reload::preserve_state("counter", &counter);
+///     counter
+/// }
+/// ```
+pub fn preserve_state<T>(key: &'static str, mutable: &Mutable<T>)
+where
+    T: Serialize + DeserializeOwned + 'static,
+{
+    let storage_key = [STATE_KEY_PREFIX, key].concat();
+    if is_hot_reload() {
+        match session_storage().get(&storage_key) {
+            Some(Ok(value)) => mutable.set(value),
+            Some(Err(error)) => {
+                crate::eprintln!(
+                    "Failed to restore the state '{}': {}",
+                    key,
+                    error.to_string()
+                )
+            }
+            None => (),
+        }
+    }
+
+    let mutable = mutable.clone();
+    let snapshot = move || {
+        if let Err(error) = session_storage().insert(&storage_key, &*mutable.lock_ref()) {
+            crate::eprintln!(
+                "Failed to preserve the state '{}': {}",
+                key,
+                error.to_string()
+            )
+        }
+    };
+    SNAPSHOTS.with(|snapshots| snapshots.borrow_mut().push(Box::new(snapshot)));
+    listen_for_hot_reload();
+}
+
+/// Scrolls to the position saved before the hot reload once the app has been rendered.
+pub(crate) fn restore_scroll_position() {
+    if !is_hot_reload() {
+        return;
+    }
+    let Some(Ok((x, y))) = session_storage().get::<(f64, f64)>(SCROLL_POSITION_KEY) else {
+        return;
+    };
+    session_storage().remove(SCROLL_POSITION_KEY);
+    Task::start(async move {
+        // signals render their elements after `start_app` returns
+        Task::next_macro_tick().await;
+        window().scroll_to_with_x_and_y(x, y);
+    });
+}
+
+fn listen_for_hot_reload() {
+    static LISTENER: OnceLock<SendWrapper<Closure<dyn Fn()>>> = OnceLock::new();
+    LISTENER.get_or_init(|| {
+        let closure = Closure::new(|| {
+            SNAPSHOTS.with(|snapshots| {
+                for snapshot in snapshots.borrow().iter() {
+                    snapshot()
+                }
+            })
+        });
+        window()
+            .add_event_listener_with_callback(HOT_RELOAD_EVENT, closure.as_ref().unchecked_ref())
+            .unwrap_throw();
+        SendWrapper::new(closure)
+    });
+}
@@ -7,6 +7,9 @@ pub mod routing;
 #[cfg(feature = "web_storage")]
 pub mod web_storage;
 
+#[cfg(feature = "web_storage")]
+pub mod hot_reload;
+
 mod animation;
 mod app_event;
 mod class_id;
@@ -26,9 +29,11 @@ mod index_generator;
 pub mod lazy;
 mod monotonic_ids;
 mod not;
+mod public_url;
 mod resize_observer;
 mod style;
 mod task;
+pub mod test_harness;
 mod timer;
 mod unify;
 mod viewport;
@@ -70,6 +75,7 @@ pub use num_traits;
 pub use once_cell;
 pub use paste::paste;
 pub use pin_project::pin_project;
+pub use public_url::public_url;
 pub use resize_observer::ResizeObserver;
 pub use send_wrapper::SendWrapper;
 pub use std::future::Future;
@@ -86,7 +92,8 @@ pub use web_worker_loader::WebWorkerLoader;
 
 #[cfg(feature = "connection")]
 pub use connection::{
-    Connection, ExchangeMsgsError, MsgOptions, ReceiveDownMsgError, SendUpMsgError,
+    Connection, ConnectionOptions, ConnectionStatus, ExchangeMsgsError, MsgOptions,
+    ReceiveDownMsgError, RetryPolicy, SendUpMsgError, Transport,
 };
 
 #[cfg(feature = "routing")]
@@ -99,7 +106,7 @@ pub use routing::{
 };
 
 #[cfg(feature = "moonlight")]
-pub use moonlight::{self, AuthToken, CorId, EntityId};
+pub use moonlight::{self, AuthToken, Codec, CorId, EntityId};
 
 #[cfg(feature = "panic_hook")]
 pub use console_error_panic_hook;
@@ -257,7 +264,16 @@ pub fn start_app<'a, I: IntoElementIterator>(
         .map(dominator::get_id)
         .unwrap_or_else(|| dominator::body().unchecked_into());
 
+    // Moon's `Frontend::pre_rendered_body` markup is displayed only until the app starts
+    if parent.has_attribute("data-pre-rendered") {
+        parent.set_inner_html("");
+        let _ = parent.remove_attribute("data-pre-rendered");
+    }
+
     for element in view_root().into_element_iter() {
         dominator::append_dom(&parent, element.into_raw().into_dom());
     }
+
+    #[cfg(feature = "web_storage")]
+    hot_reload::restore_scroll_position();
 }
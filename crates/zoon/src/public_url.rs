@@ -0,0 +1,38 @@
+use crate::*;
+use std::{collections::BTreeMap, sync::OnceLock};
+
+/// Returns the URL of a file in the `public` folder, e.g. `public_url("images/logo.png")`.
+///
+/// The URL points to the fingerprinted and precompressed copy of the file
+/// when the frontend has been built with `public_assets.fingerprint` enabled in `MoonZoon.toml`.
+pub fn public_url(path: &str) -> String {
+    let path = path.trim_start_matches('/');
+    match manifest().get(path) {
+        Some(fingerprinted_path) => format!("/_api/pkg/public/{fingerprinted_path}"),
+        None => format!("/_api/public/{path}"),
+    }
+}
+
+/// The manifest rendered by Moon's `Frontend`.
+fn manifest() -> &'static BTreeMap<String, String> {
+    static MANIFEST: OnceLock<BTreeMap<String, String>> = OnceLock::new();
+    MANIFEST.get_or_init(|| {
+        let Some(manifest) = document()
+            .get_element_by_id("moon_public_assets")
+            .and_then(|element| element.text_content())
+        else {
+            return BTreeMap::new();
+        };
+        let Ok(manifest) = js_sys::JSON::parse(&manifest) else {
+            crate::eprintln!("Failed to parse the public assets manifest");
+            return BTreeMap::new();
+        };
+        js_sys::Object::entries(manifest.unchecked_ref())
+            .iter()
+            .filter_map(|entry| {
+                let entry = entry.unchecked_into::<js_sys::Array>();
+                Some((entry.get(0).as_string()?, entry.get(1).as_string()?))
+            })
+            .collect()
+    })
+}
@@ -488,6 +488,15 @@ pub fn global_styles() -> &'static GlobalStyles {
     GLOBAL_STYLES.get_or_init(GlobalStyles::new)
 }
 
+/// The nonce of scripts rendered by Moon's `Frontend`.
+/// Browsers hide the `nonce` attribute value so it has to be read from the property.
+fn csp_nonce() -> Option<JsValue> {
+    let script = document().query_selector("script[nonce]").ok()??;
+    Reflect::get(&script, &JsValue::from("nonce"))
+        .ok()
+        .filter(|nonce| nonce.as_string().is_some_and(|nonce| !nonce.is_empty()))
+}
+
 pub struct GlobalStyles {
     sheet: SendWrapper<CssStyleSheet>,
     rule_ids: MonotonicIds,
@@ -499,6 +508,10 @@ impl GlobalStyles {
             .create_element("style")
             .expect_throw("style: create_element failed")
             .unchecked_into();
+        // the element would be blocked by Moon's Content-Security-Policy without the nonce
+        if let Some(nonce) = csp_nonce() {
+            let _ = Reflect::set(&style_element, &JsValue::from("nonce"), &nonce);
+        }
         document()
             .head()
             .expect_throw("style: head failed")
@@ -0,0 +1,92 @@
+use crate::*;
+use dominator::DomHandle;
+
+/// Renders elements into a detached `div` - it isn't a part of `document`
+/// so tests don't interfere with each other or with the app.
+///
+/// ```ignore
+/// use wasm_bindgen_test::*;
+/// use zoon::{test_harness, *};
+///
+/// wasm_bindgen_test_configure!(run_in_browser);
+///
+/// #[wasm_bindgen_test]
+/// async fn counter_increments() {
+///     let root = test_harness::mount(root);
+///     root.click("button:last-child");
+///     root.settle().await;
+///     assert!(root.text().contains('1'));
+/// }
+/// ```
+pub fn mount<I: IntoElementIterator>(view: impl FnOnce() -> I) -> TestRoot {
+    let root = document()
+        .create_element("div")
+        .unwrap_throw()
+        .unchecked_into::<web_sys::HtmlElement>();
+    let dom_handles = view()
+        .into_element_iter()
+        .map(|element| dominator::append_dom(&root, element.into_raw().into_dom()))
+        .collect();
+    TestRoot { root, dom_handles }
+}
+
+// ------ TestRoot ------
+
+/// Mounted elements are removed and their signals and tasks are stopped on drop.
+pub struct TestRoot {
+    root: web_sys::HtmlElement,
+    dom_handles: Vec<DomHandle>,
+}
+
+impl TestRoot {
+    pub fn root(&self) -> &web_sys::HtmlElement {
+        &self.root
+    }
+
+    pub fn html(&self) -> String {
+        self.root.inner_html()
+    }
+
+    pub fn text(&self) -> String {
+        self.root.text_content().unwrap_or_default()
+    }
+
+    pub fn query(&self, selector: &str) -> Option<web_sys::HtmlElement> {
+        self.root
+            .query_selector(selector)
+            .unwrap_throw()
+            .map(JsCast::unchecked_into)
+    }
+
+    pub fn query_all(&self, selector: &str) -> Vec<web_sys::HtmlElement> {
+        let nodes = self.root.query_selector_all(selector).unwrap_throw();
+        (0..nodes.length())
+            .filter_map(|index| nodes.get(index))
+            .map(JsCast::unchecked_into)
+            .collect()
+    }
+
+    /// Dispatches `click` on the first element matching `selector`.
+    ///
+    /// # Panics
+    ///
+    /// Panics when no element matches `selector`.
+    pub fn click(&self, selector: &str) {
+        self.query(selector)
+            .unwrap_or_else(|| panic!("no element matches the selector '{selector}'"))
+            .click()
+    }
+
+    /// Waits until signals have updated the DOM after a state change.
+    pub async fn settle(&self) {
+        Task::next_macro_tick().await
+    }
+}
+
+impl Drop for TestRoot {
+    fn drop(&mut self) {
+        for dom_handle in self.dom_handles.drain(..) {
+            dom_handle.discard();
+        }
+    }
+}
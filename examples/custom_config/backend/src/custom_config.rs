@@ -1,7 +1,6 @@
 use moon::*;
 
 // @TODO improve API & refactor the code below and:
-// @TODO return `Result` from `from_env_vars`? +(rename?)
 // @TODO write a derive macro `FromEnvVars`?
 
 pub static CUSTOM_CONFIG: Lazy<CustomConfig> = Lazy::new(CustomConfig::from_env_vars);
@@ -1,5 +1,6 @@
 use moon::*;
 use shared::{DownMsg, UpMsg};
+use std::time::Duration;
 
 mod custom_config;
 use custom_config::CUSTOM_CONFIG;
@@ -24,12 +25,20 @@ async fn up_msg_handler(req: UpMsgRequest<UpMsg>) {
 
     let favorite_languages = CUSTOM_CONFIG.favorite_languages.join(",");
 
-    if let Some(session) = sessions::by_session_id().wait_for(session_id).await {
-        return session
-            .send_down_msg(&DownMsg::FavoriteLanguages(favorite_languages), cor_id)
-            .await;
+    match sessions::by_session_id()
+        .wait_for(session_id, Duration::from_secs(10))
+        .await
+    {
+        Ok(session) => {
+            session
+                .send_down_msg(&DownMsg::FavoriteLanguages(favorite_languages), cor_id)
+                .await
+        }
+        Err(error) => eprintln!(
+            "cannot find the session with id `{}`: {}",
+            session_id, error
+        ),
     }
-    eprintln!("cannot find the session with id `{}`", session_id);
 }
 
 #[moon::main]